@@ -0,0 +1,37 @@
+use assert_fs::{prelude::*, TempDir};
+use criterion::{criterion_group, criterion_main, Criterion};
+use projectable::filelisting::{FileListing, Walker};
+
+/// Generate a tree with 100 directories, each with 10 subdirectories of 100 files
+fn generate_tree() -> TempDir {
+    let temp = TempDir::new().unwrap();
+    for dir in 0..100 {
+        for sub in 0..10 {
+            let sub = temp.child(format!("dir{dir}/sub{sub}"));
+            sub.create_dir_all().unwrap();
+            for file in 0..100 {
+                sub.child(format!("file{file}.txt")).touch().unwrap();
+            }
+        }
+    }
+    temp
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let temp = generate_tree();
+    let walker = Walker::new(temp.path(), &[]);
+
+    let mut group = c.benchmark_group("walk 100k files");
+    group.sample_size(10);
+    group.bench_function("serial", |b| {
+        b.iter(|| walker.walk_serial(temp.path()).unwrap())
+    });
+    group.bench_function("parallel", |b| b.iter(|| walker.walk(temp.path()).unwrap()));
+    group.bench_function("parallel into listing", |b| {
+        b.iter(|| FileListing::new(&walker.walk(temp.path()).unwrap().paths, false))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
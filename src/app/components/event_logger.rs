@@ -6,7 +6,7 @@ use crate::{
     ui::{ParagraphState, ScrollParagraph},
 };
 use anyhow::Result;
-use crossterm::event::{Event, MouseEventKind};
+use crossterm::event::{Event, KeyModifiers, MouseEventKind};
 use log::Level;
 use std::{
     cell::Cell,
@@ -26,6 +26,8 @@ use tui::{
 enum ScrollDirection {
     Down,
     Up,
+    Left,
+    Right,
 }
 
 #[derive(Debug)]
@@ -81,6 +83,10 @@ impl EventLogger {
             config,
         }
     }
+
+    pub fn set_config(&mut self, config: Rc<Config>) {
+        self.config = config;
+    }
 }
 
 impl Component for EventLogger {
@@ -103,6 +109,19 @@ impl Component for EventLogger {
         if let ExternalEvent::Crossterm(event) = ev {
             match event {
                 Event::Mouse(mouse) => match mouse.kind {
+                    // Shift turns the scroll wheel horizontal
+                    MouseEventKind::ScrollDown | MouseEventKind::ScrollUp
+                        if mouse.modifiers.contains(KeyModifiers::SHIFT) =>
+                    {
+                        self.scrolls.get_mut().push_back(ScrollEvent {
+                            direction: if mouse.kind == MouseEventKind::ScrollDown {
+                                ScrollDirection::Right
+                            } else {
+                                ScrollDirection::Left
+                            },
+                            point: (mouse.column, mouse.row),
+                        });
+                    }
                     MouseEventKind::ScrollDown => {
                         self.scrolls.get_mut().push_front(ScrollEvent {
                             direction: ScrollDirection::Down,
@@ -143,14 +162,16 @@ impl Drawable for EventLogger {
         for t in texts {
             all_text.extend(t);
         }
-        let lines = all_text.lines.len();
-        // let all = self.receiver.messages().iter().join("\n");
-        let para = ScrollParagraph::new(all_text).block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("Log")
-                .border_style(self.config.log.border_color.into()),
-        );
+        let para = ScrollParagraph::new(all_text)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Log")
+                    .border_style(self.config.log.border_color.into()),
+            )
+            .line_numbers(self.config.log.line_numbers)
+            .line_number_style(self.config.log.line_number_style.into())
+            .wrap(self.config.log.wrap);
 
         let mut state = self.state.take();
         if self.locked.get() {
@@ -167,14 +188,13 @@ impl Drawable for EventLogger {
                 match direction {
                     ScrollDirection::Up => state.up(),
                     ScrollDirection::Down => state.down(),
+                    ScrollDirection::Left => state.left(),
+                    ScrollDirection::Right => state.right(),
                 }
             }
         }
         f.render_stateful_widget(para, area, &mut state);
-        // HACK: Check if scrolled to the bottom by re-calculating the expected offset of
-        // ParagraphState.
-        let len = (lines as u16).saturating_sub(area.height - 1);
-        if len.saturating_sub(1) == state.offset_top {
+        if state.at_bottom() {
             self.locked.set(true);
         }
         self.state.set(state);
@@ -2,6 +2,7 @@ use crate::{
     app::component::{Component, Drawable},
     config::{Config, Key},
     external_event::ExternalEvent,
+    interpolate,
     queue::{AppEvent, Queue},
     ui,
 };
@@ -55,20 +56,7 @@ impl Default for FileCmdPopup {
 
 impl FileCmdPopup {
     pub fn new(queue: Queue, config: Rc<Config>) -> Self {
-        let registry = config
-            .special_commands
-            .iter()
-            .map(|(pattern, commands)| {
-                // Prefixed with ** to work with absolute paths
-                let pat = Glob::new(&format!("**/{pattern}"))
-                    .unwrap()
-                    .compile_matcher();
-                FileCommand {
-                    pattern: pat,
-                    commands: commands.clone(),
-                }
-            })
-            .collect_vec();
+        let registry = registry(&config);
         let mut state = ListState::default();
         state.select(Some(0));
         Self {
@@ -80,6 +68,11 @@ impl FileCmdPopup {
         }
     }
 
+    pub fn set_config(&mut self, config: Rc<Config>) {
+        self.registry = registry(&config);
+        self.config = config;
+    }
+
     pub fn open_for(&mut self, path: PathBuf) -> MatchState {
         self.state.get_mut().select(Some(0));
         let position = self
@@ -104,7 +97,7 @@ impl FileCmdPopup {
         if let Some(commands) = commands {
             self.queue.add(AppEvent::OpenFuzzy(
                 commands.clone(),
-                FuzzyOperation::RunCommandOnFile(path),
+                FuzzyOperation::RunCommandOnFile,
             ));
             MatchState::Matched
         } else {
@@ -159,6 +152,24 @@ impl FileCmdPopup {
     }
 }
 
+/// The special commands of `config`
+fn registry(config: &Config) -> Vec<FileCommand> {
+    config
+        .special_commands
+        .iter()
+        .map(|(pattern, commands)| {
+            // Prefixed with ** to work with absolute paths
+            let pat = Glob::new(&format!("**/{pattern}"))
+                .unwrap()
+                .compile_matcher();
+            FileCommand {
+                pattern: pat,
+                commands: commands.clone(),
+            }
+        })
+        .collect_vec()
+}
+
 impl Component for FileCmdPopup {
     fn visible(&self) -> bool {
         self.opened.is_some()
@@ -184,15 +195,14 @@ impl Component for FileCmdPopup {
                     let Some(opened) = self.opened.take() else {
                         unreachable!("checked at top of method");
                     };
-                    let option = &opened.0.commands[self.selected()];
-                    let replaced = option.replace("{}", &opened.1.display().to_string());
-                    if replaced.contains("{...}") {
+                    let option = opened.0.commands[self.selected()].clone();
+                    if interpolate::needs_input(&option) {
                         self.queue
                             .add(AppEvent::OpenInput(InputOperation::SpecialCommand(
-                                replaced,
+                                option,
                             )));
                     } else {
-                        self.queue.add(AppEvent::RunCommand(replaced));
+                        self.queue.add(AppEvent::RunCommand(option));
                     }
                     self.registry.push(opened.0);
                 }
@@ -339,13 +349,13 @@ mod tests {
     }
 
     #[test]
-    fn confirming_sends_run_command_event_with_interpolated_path() {
+    fn confirming_sends_run_command_event() {
         let mut popup = test_popup();
         let event = input_event!(KeyCode::Enter);
         popup.handle_event(&event).unwrap();
         assert!(popup
             .queue
-            .contains(&AppEvent::RunCommand("command test.txt".to_owned())));
+            .contains(&AppEvent::RunCommand("command {}".to_owned())));
     }
 
     #[test]
@@ -357,7 +367,7 @@ mod tests {
         assert!(popup
             .queue
             .contains(&AppEvent::OpenInput(InputOperation::SpecialCommand(
-                "command2 test.txt {...}".to_owned()
+                "command2 {} {...}".to_owned()
             ))));
     }
 }
@@ -1,25 +1,25 @@
 use crate::{
     app::{component::*, FuzzyOperation, InputOperation, PendingOperation},
+    archive::ArchiveKind,
     config::Config,
-    external_event::{ExternalEvent, RefreshData},
-    filelisting::{FileListing, Item},
+    external_event::{
+        get_statuses, ExternalEvent, GitStatusWorker, RefreshData, StatusRequest, StatusUpdate,
+    },
+    filelisting::{FileListing, Item, Walked, Walker},
+    interpolate,
     marks::Marks,
     queue::{AppEvent, Queue},
 };
 use anyhow::{bail, Context, Result};
+use crossbeam_channel::Sender;
 use crossterm::event::Event;
 use easy_switch::switch;
 use git2::{Repository, Status};
-use ignore::{
-    overrides::{Override, OverrideBuilder},
-    Walk, WalkBuilder,
-};
 use itertools::Itertools;
 use log::{debug, info, warn};
 use std::{
     cell::{Cell, RefCell},
-    collections::HashMap,
-    iter,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
     rc::Rc,
 };
@@ -44,6 +44,11 @@ pub struct Filetree {
     queue: Queue,
     repo: Option<Repository>,
     status_cache: Option<HashMap<PathBuf, Status>>,
+    status_worker: Option<GitStatusWorker>,
+    /// Where the status worker sends its updates, kept to start it again when `use_git` is set
+    status_sender: Option<Sender<ExternalEvent>>,
+    /// Directories in the listing whose children have not been read yet
+    unloaded: HashSet<PathBuf>,
     config: Rc<Config>,
     state: Cell<ListState>,
     marks: Rc<RefCell<Marks>>,
@@ -51,24 +56,43 @@ pub struct Filetree {
 }
 
 impl Filetree {
+    #[cfg(test)]
     fn from_dir(path: impl AsRef<Path>, queue: Queue) -> Result<Self> {
+        Self::from_dir_with_config(path, queue, Rc::new(Config::default()), Default::default())
+    }
+
+    pub fn from_dir_with_config(
+        path: impl AsRef<Path>,
+        queue: Queue,
+        config: Rc<Config>,
+        marks: Rc<RefCell<Marks>>,
+    ) -> Result<Self> {
+        let walked = Walker::new(path.as_ref(), &config.filetree.ignore)
+            .show_hidden(config.filetree.show_hidden_by_default)
+            .max_depth(eager_depth(&config))
+            .walk(path.as_ref())?;
         let mut tree = Filetree {
             root_path: path.as_ref().to_path_buf(),
             is_focused: true,
             queue: queue.clone(),
-            repo: Repository::open(path.as_ref().join(".git")).ok(),
+            repo: if config.filetree.use_git {
+                Repository::open(path.as_ref().join(".git")).ok()
+            } else {
+                None
+            },
             status_cache: None,
-            config: Rc::new(Config::default()),
-            marks: Default::default(),
-            listing: FileListing::new(
-                &Walk::new(path.as_ref())
-                    .filter_map(|entry| entry.ok().map(|entry| entry.into_path()))
-                    .filter(|entry_path| entry_path != path.as_ref())
-                    .collect_vec(),
-                false,
+            status_worker: None,
+            status_sender: None,
+            listing: FileListing::with_empty_dirs(
+                &walked.paths,
+                &walked.unloaded,
+                config.filetree.dirs_first,
             ),
+            unloaded: walked.unloaded.into_iter().collect(),
+            is_showing_hidden: config.filetree.show_hidden_by_default,
+            config,
+            marks,
             state: ListState::default().into(),
-            is_showing_hidden: false,
         };
         tree.populate_status_cache();
         if let Some(item) = tree.get_selected() {
@@ -79,47 +103,96 @@ impl Filetree {
         Ok(tree)
     }
 
-    pub fn from_dir_with_config(
-        path: impl AsRef<Path>,
-        queue: Queue,
-        config: Rc<Config>,
-        marks: Rc<RefCell<Marks>>,
-    ) -> Result<Self> {
-        let overrides = build_override_ignorer(&path, &config.filetree.ignore)?;
-        let mut listing = FileListing::new(
-            &WalkBuilder::new(path.as_ref())
-                .overrides(overrides)
-                .hidden(!config.filetree.show_hidden_by_default)
-                .build()
-                .filter_map(|entry| entry.ok().map(|entry| entry.into_path()))
-                .filter(|entry_path| entry_path != path.as_ref()) // Ignore root
-                .collect_vec(),
-            config.filetree.dirs_first,
+    /// Use `config` from now on. Changes to `use_git`, `dirs_first` and
+    /// `show_hidden_by_default` are applied right away, keeping folds and the selection. Other
+    /// changes, like to `filetree.ignore`, only show for directories loaded afterwards.
+    pub fn set_config(&mut self, config: Rc<Config>) {
+        let old = std::mem::replace(&mut self.config, Rc::clone(&config));
+        let (old, new) = (&old.filetree, &config.filetree);
+
+        if old.use_git != new.use_git {
+            if new.use_git {
+                self.repo = Repository::open(self.root_path.join(".git")).ok();
+                if let Some(sender) = self.status_sender.clone() {
+                    self.start_status_worker(sender);
+                }
+                self.populate_status_cache();
+            } else {
+                self.repo = None;
+                self.status_worker = None;
+                self.status_cache = None;
+            }
+        }
+
+        let mut needs_rebuild = old.dirs_first != new.dirs_first;
+        if old.show_hidden_by_default != new.show_hidden_by_default {
+            needs_rebuild |= self.is_showing_hidden != new.show_hidden_by_default;
+            self.is_showing_hidden = new.show_hidden_by_default;
+        }
+        if needs_rebuild {
+            if let Err(err) = self.rebuild() {
+                warn!(
+                    "{:#}",
+                    err.context("error applying the new filetree config")
+                );
+            }
+        }
+    }
+
+    /// Read the tree again with the current config, keeping open directories open and the
+    /// same item selected
+    fn rebuild(&mut self) -> Result<()> {
+        let open_dirs = self
+            .listing
+            .all_items()
+            .iter()
+            .filter(|item| !item.is_file())
+            .map(|item| item.path().to_path_buf())
+            .filter(|dir| self.listing.is_folded(dir.as_path()) == Some(false))
+            .collect_vec();
+        let selected = self.get_selected().map(|item| item.path().to_path_buf());
+
+        let walked = self.walk(
+            &self.root_path,
+            self.visibility(),
+            eager_depth(&self.config),
+        )?;
+        self.listing = FileListing::with_empty_dirs(
+            &walked.paths,
+            &walked.unloaded,
+            self.config.filetree.dirs_first,
         );
-        listing.fold_all();
+        self.listing.fold_all();
+        self.unloaded = walked.unloaded.into_iter().collect();
 
-        Ok(Filetree {
-            repo: if config.filetree.use_git {
-                Repository::open(path.as_ref().join(".git")).ok()
-            } else {
-                None
-            },
-            listing,
-            config: Rc::clone(&config),
-            marks,
-            ..Self::from_dir(path, queue)?
-        })
+        // Directories that are hidden now are not read and stay out of the listing
+        for dir in open_dirs {
+            self.load_path(&dir)?;
+            self.load_children(&dir)?;
+            self.listing.unfold(dir.as_path());
+        }
+        if let Some(selected) = selected.filter(|selected| self.has_item(selected)) {
+            self.listing.select(selected.as_path());
+        }
+        self.sync_selected();
+        Ok(())
     }
 
     pub fn refresh(&mut self) -> Result<()> {
-        let mut listing = FileListing::new(
-            &self
-                .build_walkbuilder(HiddenVisibility::Hidden)?
-                .collect_vec(),
+        let walked = self.walk(
+            &self.root_path,
+            HiddenVisibility::Hidden,
+            eager_depth(&self.config),
+        )?;
+        let mut listing = FileListing::with_empty_dirs(
+            &walked.paths,
+            &walked.unloaded,
             self.config.filetree.dirs_first,
         );
         listing.fold_all();
         self.listing = listing;
+        self.unloaded = walked.unloaded.into_iter().collect();
+        self.is_showing_hidden = false;
         self.populate_status_cache();
 
         Ok(())
@@ -128,6 +201,7 @@ impl Filetree {
     pub fn partial_refresh(&mut self, refresh_data: &RefreshData) -> Result<()> {
         match refresh_data {
             RefreshData::Delete(path) => {
+                self.unloaded.retain(|dir| !dir.starts_with(path));
                 self.listing.remove(path.as_path())?;
                 if self.get_selected().is_some_and(|item| item.path() == path) {
                     self.queue.add(AppEvent::PreviewFile(path.clone()));
@@ -137,33 +211,117 @@ impl Filetree {
                 if !path.exists() {
                     return Ok(());
                 }
+                // Will be read when its parent is opened
+                if path
+                    .parent()
+                    .is_some_and(|parent| self.unloaded.contains(parent))
+                {
+                    return Ok(());
+                }
                 if path.is_dir() {
+                    // Directories can be moved in with their contents
+                    if eager_depth(&self.config).is_some() {
+                        self.unloaded.insert(path.clone());
+                    }
                     self.listing.add(Item::Dir(path.clone()));
                 } else {
                     self.listing.add(Item::File(path.clone()));
                 }
-                self.populate_status_cache();
+                self.update_status(&[path]);
                 if self.get_selected().is_some_and(|item| item.path() == path) {
                     self.queue.add(AppEvent::PreviewFile(path.clone()));
                 }
             }
+            // The previewer refreshes itself on modifications, only git colors can change
+            RefreshData::Modify(path) => self.update_status(&[path]),
+            RefreshData::Rename { from, to } => {
+                self.rename_unloaded(from, to);
+                // Renames done through projectable are already applied, others can replace
+                // an item that is listed
+                if self.has_item(to) {
+                    if self.has_item(from) {
+                        self.listing.remove(from.as_path())?;
+                    }
+                    self.update_status(&[from, to]);
+                    if self.get_selected().is_some_and(|item| item.path() == to) {
+                        self.queue.add(AppEvent::PreviewFile(to.clone()));
+                    }
+                    return Ok(());
+                }
+                if !self.has_item(from) {
+                    return self.partial_refresh(&RefreshData::Add(to.clone()));
+                }
+
+                let selected = self.get_selected().map(|item| item.path().to_path_buf());
+                if let Err(err) = self.rename_in_place(from, to) {
+                    debug!("falling back to remove and add for rename: {err}");
+                    self.listing.remove(from.as_path())?;
+                    self.listing.add(if to.is_dir() {
+                        Item::Dir(to.clone())
+                    } else {
+                        Item::File(to.clone())
+                    });
+                }
+                self.update_status(&[from, to]);
+
+                // Keep the same item selected, even if it was the one renamed
+                if let Some(selected) = selected {
+                    let selected = replace_prefix(&selected, from, to);
+                    if self.has_item(&selected) {
+                        self.listing.select(selected.as_path());
+                        self.sync_selected();
+                        if selected.starts_with(to) {
+                            self.queue.add(AppEvent::PreviewFile(selected));
+                        }
+                    }
+                }
+            }
         }
 
         Ok(())
     }
 
+    fn has_item(&self, path: &Path) -> bool {
+        self.listing
+            .all_items()
+            .iter()
+            .any(|item| item.path() == path)
+    }
+
+    /// Apply a rename of `from` to `to` to the listing without losing folds. `to` can be in a
+    /// different directory than `from`.
+    fn rename_in_place(&mut self, from: &Path, to: &Path) -> Result<()> {
+        let file_name = to.file_name().context("rename target has no file name")?;
+        let renamed = from.with_file_name(file_name);
+        if renamed != from {
+            self.listing.rename(from, &renamed)?;
+        }
+        let new_parent = to.parent().context("rename target has no parent")?;
+        if renamed.parent() != Some(new_parent) {
+            self.listing.mv(renamed.as_path(), new_parent)?;
+        }
+        Ok(())
+    }
+
     pub fn move_item(&mut self, old: impl AsRef<Path>, new: impl AsRef<Path>) -> Result<()> {
         self.listing
             .mv(old.as_ref(), &new)
             .context("error moving item")?;
-        self.populate_status_cache();
+        let moved = new.as_ref().join(
+            old.as_ref()
+                .file_name()
+                .context("moved item has no file name")?,
+        );
+        self.rename_unloaded(old.as_ref(), &moved);
+        self.update_status(&[old.as_ref(), &moved]);
         self.open_path(new)?;
         Ok(())
     }
 
     pub fn rename(&mut self, old: impl AsRef<Path>, new: impl AsRef<Path>) -> Result<()> {
         self.listing.rename(old.as_ref(), new.as_ref())?;
-        self.populate_status_cache();
+        self.rename_unloaded(old.as_ref(), new.as_ref());
+        self.update_status(&[old.as_ref(), new.as_ref()]);
         Ok(())
     }
 
@@ -176,6 +334,7 @@ impl Filetree {
             return Ok(());
         }
 
+        self.load_path(path.as_ref())?;
         self.listing.select(path.as_ref());
         self.queue
             .add(AppEvent::PreviewFile(path.as_ref().to_path_buf()));
@@ -184,18 +343,94 @@ impl Filetree {
         Ok(())
     }
 
-    pub fn open_all(&mut self) {
+    pub fn open_all(&mut self) -> Result<()> {
+        self.load_all_under(None)?;
         self.listing.unfold_all();
+        self.sync_selected();
+        Ok(())
     }
 
     pub fn close_all(&mut self) {
         self.listing.fold_all();
     }
 
-    pub fn open_under(&mut self) {
+    pub fn open_under(&mut self) -> Result<()> {
+        if let Some(Item::Dir(dir)) = self.get_selected() {
+            self.load_all_under(Some(&dir.clone()))?;
+        }
         self.listing
             .selected()
             .map(|selected| self.listing.unfold_under(selected));
+        Ok(())
+    }
+
+    /// Read the children of `dir` if they have not been already
+    fn load_children(&mut self, dir: &Path) -> Result<()> {
+        if !self.unloaded.remove(dir) {
+            return Ok(());
+        }
+        self.read_children(dir)
+    }
+
+    /// Add the children of `dir`, which must have been taken out of `unloaded`
+    fn read_children(&mut self, dir: &Path) -> Result<()> {
+        let walked = self.walk(dir, self.visibility(), Some(1))?;
+        let dirs: HashSet<&PathBuf> = walked.unloaded.iter().collect();
+        let children = walked
+            .paths
+            .iter()
+            .map(|path| {
+                if dirs.contains(path) {
+                    Item::Dir(path.clone())
+                } else {
+                    Item::File(path.clone())
+                }
+            })
+            .collect();
+        self.listing
+            .add_children(dir, children, self.config.filetree.dirs_first)
+            .with_context(|| format!("failed to load \"{}\"", dir.display()))?;
+        self.unloaded.extend(walked.unloaded);
+        self.sync_selected();
+        Ok(())
+    }
+
+    /// Read every directory under `dir`, or the whole tree if `dir` is `None`
+    fn load_all_under(&mut self, dir: Option<&Path>) -> Result<()> {
+        loop {
+            // Reading a directory can find more under it, so this goes a level at a time
+            let (mut pending, rest): (Vec<PathBuf>, Vec<PathBuf>) = self
+                .unloaded
+                .drain()
+                .partition(|unloaded| dir.map_or(true, |dir| unloaded.starts_with(dir)));
+            self.unloaded = rest.into_iter().collect();
+            if pending.is_empty() {
+                return Ok(());
+            }
+            while let Some(next) = pending.pop() {
+                if let Err(err) = self.read_children(&next) {
+                    self.unloaded.insert(next);
+                    self.unloaded.extend(pending);
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Read the directories above `path`, so it can be selected
+    fn load_path(&mut self, path: &Path) -> Result<()> {
+        for dir in path.ancestors().skip(1).collect_vec().into_iter().rev() {
+            self.load_children(dir)?;
+        }
+        Ok(())
+    }
+
+    fn rename_unloaded(&mut self, from: &Path, to: &Path) {
+        self.unloaded = self
+            .unloaded
+            .drain()
+            .map(|dir| replace_prefix(&dir, from, to))
+            .collect();
     }
 
     pub fn close_under(&mut self) {
@@ -206,7 +441,9 @@ impl Filetree {
 
     pub fn filter_include(&mut self, items: &[PathBuf]) -> Result<()> {
         let items = self
-            .build_walkbuilder(HiddenVisibility::Hidden)?
+            .walk(&self.root_path, HiddenVisibility::Hidden, None)?
+            .paths
+            .into_iter()
             .filter(|entry_path| {
                 items
                     .iter()
@@ -215,54 +452,121 @@ impl Filetree {
             .collect_vec();
 
         self.listing = FileListing::new(&items, self.config.filetree.dirs_first);
+        self.unloaded.clear();
 
         Ok(())
     }
 
     pub fn toggle_dotfiles(&mut self) -> Result<()> {
-        let items = self
-            .build_walkbuilder(if self.is_showing_hidden {
+        let walked = self.walk(
+            &self.root_path,
+            if self.is_showing_hidden {
                 HiddenVisibility::Hidden
             } else {
                 HiddenVisibility::Visible
-            })?
-            .collect_vec();
+            },
+            eager_depth(&self.config),
+        )?;
         self.is_showing_hidden = !self.is_showing_hidden;
 
-        self.listing = FileListing::new(&items, self.config.filetree.dirs_first);
+        self.listing = FileListing::with_empty_dirs(
+            &walked.paths,
+            &walked.unloaded,
+            self.config.filetree.dirs_first,
+        );
         self.listing.fold_all();
+        self.unloaded = walked.unloaded.into_iter().collect();
 
         info!("toggling visibility of dotfiles");
 
         Ok(())
     }
 
+    /// Compute git statuses on a background thread from now on
+    pub fn start_status_worker(&mut self, sender: Sender<ExternalEvent>) {
+        self.status_sender = Some(sender.clone());
+        if self.repo.is_none() {
+            return;
+        }
+        match GitStatusWorker::spawn(self.root_path.clone(), sender) {
+            Ok(worker) => self.status_worker = Some(worker),
+            Err(err) => warn!("{err:#}"),
+        }
+    }
+
     fn populate_status_cache(&mut self) {
-        self.status_cache = self.repo.as_ref().and_then(|repo| {
-            repo.statuses(None).ok().map(|statuses| {
-                statuses
-                    .iter()
-                    .map(|status| (self.root_path.join(status.path().unwrap()), status.status()))
-                    .collect::<HashMap<PathBuf, Status>>()
-            })
-        });
+        self.request_status(StatusRequest::Full);
+    }
+
+    /// Update the statuses of `paths` and everything under them
+    fn update_status(&mut self, paths: &[&Path]) {
+        self.request_status(StatusRequest::Paths(
+            paths.iter().map(|path| path.to_path_buf()).collect(),
+        ));
+    }
+
+    /// Send `request` to the status worker, or compute it on this thread if the worker is not
+    /// running
+    fn request_status(&mut self, request: StatusRequest) {
+        let request = match &self.status_worker {
+            Some(worker) => match worker.request(request) {
+                Ok(()) => return,
+                Err(request) => {
+                    debug!("git status worker stopped, getting statuses on this thread");
+                    self.status_worker = None;
+                    request
+                }
+            },
+            None => request,
+        };
+        let Some(repo) = self.repo.as_ref() else {
+            return;
+        };
+        match get_statuses(repo, &self.root_path, request) {
+            Ok(update) => self.apply_status(update),
+            Err(err) => debug!("{err:#}"),
+        }
+    }
+
+    fn apply_status(&mut self, update: StatusUpdate) {
+        update.apply(self.status_cache.get_or_insert_with(HashMap::new));
     }
 
     fn sync_selected(&mut self) {
         self.state.get_mut().select(self.listing.selected());
     }
 
-    fn build_walkbuilder(
+    fn visibility(&self) -> HiddenVisibility {
+        if self.is_showing_hidden {
+            HiddenVisibility::Visible
+        } else {
+            HiddenVisibility::Hidden
+        }
+    }
+
+    fn walk(
         &self,
+        dir: &Path,
         show_dotfiles: HiddenVisibility,
-    ) -> Result<impl Iterator<Item = PathBuf> + '_> {
-        let overrides = build_override_ignorer(&self.root_path, &self.config.filetree.ignore)?;
-        Ok(WalkBuilder::new(&self.root_path)
-            .overrides(overrides)
-            .hidden(show_dotfiles != HiddenVisibility::Visible)
-            .build()
-            .filter_map(|entry| entry.ok().map(|entry| entry.into_path()))
-            .filter(|entry_path| entry_path != &self.root_path))
+        max_depth: Option<usize>,
+    ) -> Result<Walked> {
+        Walker::new(&self.root_path, &self.config.filetree.ignore)
+            .show_hidden(show_dotfiles == HiddenVisibility::Visible)
+            .max_depth(max_depth)
+            .walk(dir)
+    }
+
+    /// Every path in the tree, including ones in directories that have not been read
+    fn all_paths(&self) -> Result<Vec<PathBuf>> {
+        if self.unloaded.is_empty() {
+            return Ok(self
+                .listing
+                .all_items()
+                .iter()
+                .map(|item| item.path().to_path_buf())
+                .collect());
+        }
+        Ok(self.walk(&self.root_path, self.visibility(), None)?.paths)
     }
 }
 
@@ -371,6 +675,12 @@ impl Component for Filetree {
     }
 
     fn handle_event(&mut self, ev: &ExternalEvent) -> Result<()> {
+        // Statuses can arrive while a popup is open
+        match ev {
+            ExternalEvent::GitStatus(update) => self.apply_status(update.clone()),
+            ExternalEvent::RefreshGitStatus => self.populate_status_cache(),
+            _ => {}
+        }
         if !self.focused() {
             return Ok(());
         }
@@ -404,11 +714,14 @@ impl Component for Filetree {
                     self.config.filetree.down_three, not_empty => self.listing.select_next_n(JUMP_DOWN_AMOUNT as usize),
                     self.config.filetree.up_three, not_empty => self.listing.select_prev_n(JUMP_DOWN_AMOUNT as usize),
                     self.config.filetree.exec_cmd, not_empty => {
-                        if let Some(item) = self.get_selected() {
-                             self.queue.add(AppEvent::OpenInput(InputOperation::Command {
-                                 to: item.path().to_path_buf(),
-                             }));
-                         }
+                        if self.get_selected().is_some() {
+                            self.queue.add(AppEvent::OpenInput(InputOperation::Command));
+                        }
+                    },
+                    self.config.filetree.repeat_cmd, not_empty => {
+                        if self.get_selected().is_some() {
+                            self.queue.add(AppEvent::RepeatCommand);
+                        }
                     },
                     self.config.filetree.delete => {
                         if let Some(item) = self.listing.selected_item() {
@@ -423,6 +736,14 @@ impl Component for Filetree {
                             warn!("invalid focus target");
                         }
                     },
+                    self.config.filetree.extract => {
+                        match self.get_selected() {
+                            Some(Item::File(path)) if ArchiveKind::from_path(path).is_some() => {
+                                self.queue.add(AppEvent::ExtractArchive(path.clone()));
+                            }
+                            _ => warn!("selected file is not a supported archive"),
+                        }
+                    },
                     self.config.filetree.git_filter => {
                         if let Some(cache) = self.status_cache.as_ref() {
                             info!("filtered for modified files");
@@ -436,13 +757,17 @@ impl Component for Filetree {
                             warn!("no git status to filter for");
                         }
                     },
-                    self.config.filetree.search => self.queue.add(AppEvent::SearchFiles(self.listing.all_items().iter().map(|item| item.path().to_path_buf()).collect())),
+                    self.config.filetree.search => self.queue.add(AppEvent::SearchFiles(self.all_paths()?)),
                     self.config.filetree.clear => {
                         info!("refreshed filetree");
                         self.refresh().context("problem refreshing filetree")?;
                     },
                     self.config.open => match self.get_selected() {
-                        Some(Item::Dir(_)) => self.listing.toggle_fold(),
+                        Some(Item::Dir(dir)) => {
+                            let dir = dir.clone();
+                            self.listing.toggle_fold();
+                            self.load_children(&dir)?;
+                        }
                         Some(Item::File(file)) => self
                             .queue
                             .add(AppEvent::OpenFile(file.clone())),
@@ -471,7 +796,7 @@ impl Component for Filetree {
                         }
                     },
                     self.config.filetree.close_all => self.close_all(),
-                    self.config.filetree.open_all => self.open_all(),
+                    self.config.filetree.open_all => self.open_all()?,
                     self.config.filetree.special_command => {
                         if let Some(selected) = self.get_selected() {
                             if self.config.filetree.special_commands_fuzzy {
@@ -486,7 +811,7 @@ impl Component for Filetree {
                             self.queue.add(AppEvent::Mark(selected.path().to_path_buf()));
                         }
                     },
-                    self.config.filetree.open_under => self.open_under(),
+                    self.config.filetree.open_under => self.open_under()?,
                     self.config.filetree.close_under => self.close_under(),
                     self.config.filetree.show_dotfiles => self.toggle_dotfiles()?,
                     self.config.filetree.rename => {
@@ -496,12 +821,9 @@ impl Component for Filetree {
                     },
                     self.config.filetree.move_path => {
                         if let Some(selected) = self.get_selected() {
-                            let vis = if self.is_showing_hidden {
-                                HiddenVisibility::Visible
-                            } else {
-                                HiddenVisibility::Hidden
-                            };
-                            let items = self.build_walkbuilder(vis)?
+                            let items = self.walk(&self.root_path, self.visibility(), None)?
+                                .paths
+                                .into_iter()
                                 .filter_map(|item| item.is_dir().then_some(item))
                                 .map(|p| p.strip_prefix(&self.root_path).expect("paths should start with root").display().to_string())
                                 .collect();
@@ -511,14 +833,12 @@ impl Component for Filetree {
                     _ => {
                         let key: crate::config::Key = key.into();
                         if let Some(cmd) = self.config.commands.get(&key) {
-                            if let Some(selected) = self.get_selected() {
-                                let new_cmd =
-                                    cmd.replace("{}", &selected.path().as_os_str().to_string_lossy());
-                                if new_cmd.contains("{...}") {
+                            if self.get_selected().is_some() {
+                                if interpolate::needs_input(cmd) {
                                     self.queue
-                                        .add(AppEvent::OpenInput(InputOperation::SpecialCommand(new_cmd)));
+                                        .add(AppEvent::OpenInput(InputOperation::SpecialCommand(cmd.clone())));
                                 } else {
-                                    self.queue.add(AppEvent::RunCommand(new_cmd));
+                                    self.queue.add(AppEvent::RunCommand(cmd.clone()));
                                 }
                             }
                         };
@@ -544,20 +864,18 @@ impl Component for Filetree {
     }
 }
 
-/// Builds an `Override` that ignores certain paths
-fn build_override_ignorer(root: impl AsRef<Path>, ignore: &[String]) -> Result<Override> {
-    let mut override_builder = OverrideBuilder::new(root.as_ref());
+/// How many levels of directories to read up front, or `None` for all of them
+fn eager_depth(config: &Config) -> Option<usize> {
+    config.filetree.eager_depth.map(|depth| depth.max(1))
+}
 
-    for pat in ignore.iter().map(|x| x.as_str()).chain(iter::once("/.git")) {
-        override_builder
-            .add(&format!("!{pat}"))
-            .with_context(|| format!("failed to add glob for: \"!{pat}\""))?
-            .add(&format!("!{pat}/**"))
-            .with_context(|| format!("failed to add glob for: \"!{pat}/**\""))?;
+/// Replace the `from` prefix of `path` with `to`
+fn replace_prefix(path: &Path, from: &Path, to: &Path) -> PathBuf {
+    match path.strip_prefix(from) {
+        Ok(suffix) if suffix.as_os_str().is_empty() => to.to_path_buf(),
+        Ok(suffix) => to.join(suffix),
+        Err(_) => path.to_path_buf(),
     }
-    override_builder
-        .build()
-        .context("failed to build override ignorer")
 }
 
 #[cfg(test)]
@@ -566,6 +884,7 @@ mod tests {
     use crate::{app::components::testing::*, config::FiletreeConfig};
     use collect_all::collect;
     use smallvec::smallvec;
+    use std::{fs, iter};
     use test_log::test;
 
     /// Create temporary files and return the temp dir
@@ -755,7 +1074,6 @@ mod tests {
         let temp = temp_files!("test.txt");
         let mut filetree =
             Filetree::from_dir(temp.path(), Queue::new()).expect("should be able to make filetree");
-        let path = temp.to_path_buf();
         scopeguard::guard(temp, |temp| temp.close().unwrap());
 
         let e = input_event!(KeyCode::Char('e'));
@@ -764,9 +1082,21 @@ mod tests {
             .expect("should be able to handle event");
         assert!(filetree
             .queue
-            .contains(&AppEvent::OpenInput(InputOperation::Command {
-                to: path.join("test.txt")
-            })));
+            .contains(&AppEvent::OpenInput(InputOperation::Command)));
+    }
+
+    #[test]
+    fn can_send_repeat_cmd() {
+        let temp = temp_files!("test.txt");
+        let mut filetree =
+            Filetree::from_dir(temp.path(), Queue::new()).expect("should be able to make filetree");
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+
+        let e = input_event!(KeyCode::Char('E'); KeyModifiers::SHIFT);
+        filetree
+            .handle_event(&e)
+            .expect("should be able to handle event");
+        assert!(filetree.queue.contains(&AppEvent::RepeatCommand));
     }
 
     #[test]
@@ -815,15 +1145,122 @@ mod tests {
         assert_eq!(1, filetree.listing.selected().unwrap());
     }
 
+    #[test]
+    fn partial_refresh_rename_over_listed_file() {
+        let temp = temp_files!("test.txt", "test2.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        let path = temp.path().to_path_buf();
+        filetree.listing.select(path.join("test2.txt").as_path());
+
+        fs::rename(path.join("test.txt"), path.join("test2.txt")).unwrap();
+        filetree
+            .partial_refresh(&RefreshData::Rename {
+                from: path.join("test.txt"),
+                to: path.join("test2.txt"),
+            })
+            .unwrap();
+        assert!(!filetree.has_item(&path.join("test.txt")));
+        assert!(filetree.has_item(&path.join("test2.txt")));
+        assert_eq!(1, filetree.listing.len());
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::PreviewFile(path.join("test2.txt"))));
+        temp.close().unwrap();
+    }
+
+    #[test]
+    fn partial_refresh_rename_keeps_selection_and_folds() {
+        let temp = temp_files!("test/test.txt", "test/inner/test.txt", "other.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        let path = temp.path().to_path_buf();
+        filetree.listing.unfold_all();
+        filetree.listing.fold(path.join("test/inner").as_path());
+        filetree
+            .listing
+            .select(path.join("test/test.txt").as_path());
+
+        fs::rename(path.join("test"), path.join("renamed")).unwrap();
+        filetree
+            .partial_refresh(&RefreshData::Rename {
+                from: path.join("test"),
+                to: path.join("renamed"),
+            })
+            .unwrap();
+        assert_eq!(
+            path.join("renamed/test.txt"),
+            filetree.get_selected().unwrap().path()
+        );
+        assert_eq!(
+            Some(true),
+            filetree
+                .listing
+                .is_folded(path.join("renamed/inner").as_path())
+        );
+        assert!(!filetree.has_item(&path.join("test")));
+        assert!(filetree.has_item(&path.join("renamed/inner/test.txt")));
+    }
+
+    #[test]
+    fn partial_refresh_rename_can_move_between_dirs() {
+        let temp = temp_files!("a/test.txt", "b/other.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        let path = temp.path().to_path_buf();
+
+        fs::rename(path.join("a/test.txt"), path.join("b/test.txt")).unwrap();
+        filetree
+            .partial_refresh(&RefreshData::Rename {
+                from: path.join("a/test.txt"),
+                to: path.join("b/test.txt"),
+            })
+            .unwrap();
+        assert!(!filetree.has_item(&path.join("a/test.txt")));
+        assert!(filetree.has_item(&path.join("b/test.txt")));
+    }
+
+    #[test]
+    fn partial_refresh_rename_of_unknown_item_adds_it() {
+        let temp = temp_files!("test.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        let path = temp.path().to_path_buf();
+
+        fs::write(path.join("new.txt"), "").unwrap();
+        filetree
+            .partial_refresh(&RefreshData::Rename {
+                from: path.join("unknown.tmp"),
+                to: path.join("new.txt"),
+            })
+            .unwrap();
+        assert!(filetree.has_item(&path.join("new.txt")));
+    }
+
     #[test]
     fn can_open_all() {
         let temp = temp_files!("test.txt", "test/test2.txt", "test2/test4/test.txt");
         let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
         scopeguard::guard(temp, |temp| temp.close().unwrap());
-        filetree.open_all();
+        filetree.open_all().unwrap();
         assert_eq!(6, filetree.listing.len());
     }
 
+    #[test]
+    fn can_extract_selected_archive() {
+        let temp = temp_files!("test.zip", "test.txt");
+        let mut filetree = Filetree::from_dir(temp.path(), Queue::new()).unwrap();
+        let path = temp.path().to_path_buf();
+        scopeguard::guard(temp, |temp| temp.close().unwrap());
+        let event = input_event!(KeyCode::Char('X'); KeyModifiers::SHIFT);
+        for i in 0..filetree.listing.len() {
+            filetree.listing.select(i);
+            assert!(filetree.handle_event(&event).is_ok());
+        }
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::ExtractArchive(path.join("test.zip"))));
+        assert!(!filetree
+            .queue
+            .contains(&AppEvent::ExtractArchive(path.join("test.txt"))));
+    }
+
     #[test]
     fn can_mark_selected() {
         let temp = temp_files!("test.txt");
@@ -1009,7 +1446,7 @@ mod tests {
     }
 
     #[test]
-    fn custom_commands_are_performed_with_substitutions() {
+    fn custom_commands_are_performed() {
         let temp = temp_files!("test.txt");
         let config = Config {
             commands: collect![HashMap<_, _>:
@@ -1028,10 +1465,9 @@ mod tests {
         assert!(filetree
             .handle_event(&input_event!(KeyCode::Char('z')))
             .is_ok());
-        assert!(filetree.queue.contains(&AppEvent::RunCommand(format!(
-            "vim {}",
-            temp.join("test.txt").display()
-        ))));
+        assert!(filetree
+            .queue
+            .contains(&AppEvent::RunCommand("vim {}".to_owned())));
         assert!(filetree
             .handle_event(&input_event!(KeyCode::Char('x')))
             .is_ok());
@@ -1041,4 +1477,215 @@ mod tests {
                 "nvim {...}".to_owned()
             ))));
     }
+
+    #[test]
+    fn partial_refresh_updates_status_of_added_path() {
+        let temp = temp_files!("test.txt");
+        git2::Repository::init(temp.path()).unwrap();
+        let path = temp.path().to_owned();
+        let mut filetree =
+            Filetree::from_dir(&path, Queue::new()).expect("should be able to make filetree");
+        assert_eq!(
+            Some(&Status::WT_NEW),
+            filetree
+                .status_cache
+                .as_ref()
+                .unwrap()
+                .get(&path.join("test.txt"))
+        );
+
+        fs::write(path.join("test2.txt"), "").unwrap();
+        filetree
+            .partial_refresh(&RefreshData::Add(path.join("test2.txt")))
+            .unwrap();
+        let cache = filetree.status_cache.as_ref().unwrap();
+        assert_eq!(Some(&Status::WT_NEW), cache.get(&path.join("test2.txt")));
+        assert_eq!(Some(&Status::WT_NEW), cache.get(&path.join("test.txt")));
+    }
+
+    #[test]
+    fn status_updates_are_applied_while_unfocused() {
+        let temp = temp_files!("test.txt");
+        let path = temp.path().to_owned();
+        let mut filetree =
+            Filetree::from_dir(&path, Queue::new()).expect("should be able to make filetree");
+        filetree.focus(false);
+
+        filetree
+            .handle_event(&ExternalEvent::GitStatus(StatusUpdate::Full(
+                HashMap::from([(path.join("test.txt"), Status::WT_MODIFIED)]),
+            )))
+            .unwrap();
+        assert_eq!(
+            Some(&Status::WT_MODIFIED),
+            filetree
+                .status_cache
+                .as_ref()
+                .unwrap()
+                .get(&path.join("test.txt"))
+        );
+    }
+
+    #[test]
+    fn reloading_config_resorts_and_keeps_folds() {
+        let temp = temp_files!("a.txt", "dir/test.txt", "dir/sub/test2.txt", ".hidden");
+        let path = temp.path().to_owned();
+        let config = |dirs_first, show_hidden_by_default| {
+            Rc::new(Config {
+                filetree: FiletreeConfig {
+                    eager_depth: Some(1),
+                    dirs_first,
+                    show_hidden_by_default,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+        };
+        let mut filetree = Filetree::from_dir_with_config(
+            &path,
+            Queue::new(),
+            config(false, false),
+            Rc::new(RefCell::new(Marks::default())),
+        )
+        .unwrap();
+        filetree.open_path(path.join("dir/test.txt")).unwrap();
+        filetree.listing.unfold(path.join("dir").as_path());
+
+        filetree.set_config(config(true, true));
+        assert_eq!(
+            vec![
+                path.join("dir"),
+                path.join("dir/sub"),
+                path.join("dir/test.txt"),
+                path.join(".hidden"),
+                path.join("a.txt"),
+            ],
+            filetree
+                .listing
+                .all_items()
+                .iter()
+                .map(|item| item.path().to_path_buf())
+                .collect_vec()
+        );
+        assert_eq!(
+            Some(false),
+            filetree.listing.is_folded(path.join("dir").as_path())
+        );
+        assert_eq!(
+            Some(true),
+            filetree.listing.is_folded(path.join("dir/sub").as_path())
+        );
+        assert_eq!(
+            path.join("dir/test.txt"),
+            filetree.get_selected().unwrap().path()
+        );
+
+        filetree.set_config(config(true, false));
+        assert!(!filetree.has_item(&path.join(".hidden")));
+        temp.close().unwrap();
+    }
+
+    #[test]
+    fn reloading_config_without_git_drops_statuses() {
+        let temp = temp_files!("test.txt");
+        git2::Repository::init(temp.path()).unwrap();
+        let path = temp.path().to_owned();
+        let mut filetree =
+            Filetree::from_dir(&path, Queue::new()).expect("should be able to make filetree");
+        assert!(filetree.status_cache.is_some());
+
+        filetree.set_config(Rc::new(Config {
+            filetree: FiletreeConfig {
+                use_git: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        }));
+        assert!(filetree.repo.is_none());
+        assert!(filetree.status_cache.is_none());
+
+        filetree.set_config(Rc::new(Config::default()));
+        assert_eq!(
+            Some(&Status::WT_NEW),
+            filetree
+                .status_cache
+                .as_ref()
+                .unwrap()
+                .get(&path.join("test.txt"))
+        );
+        temp.close().unwrap();
+    }
+
+    #[test]
+    fn lazy_tree_reads_dirs_when_opened() {
+        let temp = temp_files!("test.txt", "dir/test2.txt", "dir/sub/test3.txt");
+        let path = temp.path().to_owned();
+        let config = Config {
+            filetree: FiletreeConfig {
+                eager_depth: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut filetree = Filetree::from_dir_with_config(
+            &path,
+            Queue::new(),
+            Rc::new(config),
+            Rc::new(RefCell::new(Marks::default())),
+        )
+        .unwrap();
+        assert_eq!(2, filetree.listing.all_items().len());
+        assert!(!filetree.has_item(&path.join("dir/test2.txt")));
+
+        filetree.listing.select(path.join("dir").as_path());
+        filetree
+            .handle_event(&input_event!(KeyCode::Enter))
+            .unwrap();
+        assert!(filetree.has_item(&path.join("dir/test2.txt")));
+        assert_eq!(
+            Some(&Item::Dir(path.join("dir/sub"))),
+            filetree
+                .listing
+                .all_items()
+                .iter()
+                .find(|item| item.path() == path.join("dir/sub"))
+        );
+        assert!(!filetree.has_item(&path.join("dir/sub/test3.txt")));
+
+        filetree.open_path(path.join("dir/sub/test3.txt")).unwrap();
+        assert_eq!(
+            path.join("dir/sub/test3.txt"),
+            filetree.get_selected().unwrap().path()
+        );
+    }
+
+    #[test]
+    fn lazy_tree_searches_unread_dirs() {
+        let temp = temp_files!("test.txt", "dir/test2.txt");
+        let path = temp.path().to_owned();
+        let config = Config {
+            filetree: FiletreeConfig {
+                eager_depth: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut filetree = Filetree::from_dir_with_config(
+            &path,
+            Queue::new(),
+            Rc::new(config),
+            Rc::new(RefCell::new(Marks::default())),
+        )
+        .unwrap();
+        filetree
+            .handle_event(&input_event!(KeyCode::Char('/')))
+            .unwrap();
+        let paths = iter::from_fn(|| filetree.queue.pop())
+            .find_map(|event| match event {
+                AppEvent::SearchFiles(paths) => Some(paths),
+                _ => None,
+            })
+            .expect("should have sent search event");
+        assert!(paths.contains(&path.join("dir/test2.txt")));
+    }
 }
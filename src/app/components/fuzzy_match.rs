@@ -3,6 +3,7 @@ use crate::{
     app::component::{Component, Drawable},
     config::Config,
     external_event::ExternalEvent,
+    interpolate,
     queue::{AppEvent, Queue},
     ui,
 };
@@ -24,7 +25,10 @@ use tui_textarea::{Input, Key, TextArea};
 pub enum FuzzyOperation {
     OpenFile,
     MoveFile(PathBuf),
-    RunCommandOnFile(PathBuf),
+    /// Run the chosen command on the selected item
+    RunCommandOnFile,
+    /// Names of the tasks, in the same order as the items
+    RunTask(Vec<String>),
     None,
 }
 
@@ -58,6 +62,10 @@ impl FuzzyMatcher {
         }
     }
 
+    pub fn set_config(&mut self, config: Rc<Config>) {
+        self.config = config;
+    }
+
     pub fn start(&mut self, items: Vec<String>, operation: FuzzyOperation) {
         self.operation = operation;
         self.input = items;
@@ -105,16 +113,19 @@ impl FuzzyMatcher {
                 let path = std::mem::take(path);
                 self.queue.add(AppEvent::MoveFile(path, selected.into()));
             }
-            FuzzyOperation::RunCommandOnFile(ref mut path) => {
-                let path = std::mem::take(path);
-                let replaced = selected.replace("{}", &path.display().to_string());
-                if replaced.contains("{...}") {
+            FuzzyOperation::RunCommandOnFile => {
+                if interpolate::needs_input(&selected) {
                     self.queue
                         .add(AppEvent::OpenInput(InputOperation::SpecialCommand(
-                            replaced,
+                            selected,
                         )));
                 } else {
-                    self.queue.add(AppEvent::RunCommand(replaced));
+                    self.queue.add(AppEvent::RunCommand(selected));
+                }
+            }
+            FuzzyOperation::RunTask(ref names) => {
+                if let Some(index) = self.input.iter().position(|item| *item == selected) {
+                    self.queue.add(AppEvent::RunTask(names[index].clone()));
                 }
             }
             FuzzyOperation::None => panic!("should not submit with no operation"),
@@ -342,4 +353,18 @@ mod tests {
             .queue
             .contains(&AppEvent::GotoFile("item2".to_owned().into())));
     }
+
+    #[test]
+    fn submitting_task_sends_its_name() {
+        let mut matcher = FuzzyMatcher::new(Queue::new());
+        matcher.start(
+            vec!["build: Build it".to_owned(), "test".to_owned()],
+            FuzzyOperation::RunTask(vec!["build".to_owned(), "test".to_owned()]),
+        );
+        matcher.area.insert_str("build");
+        matcher.submit();
+        assert!(matcher
+            .queue
+            .contains(&AppEvent::RunTask("build".to_owned())));
+    }
 }
@@ -1,11 +1,22 @@
 use crate::{
     app::component::{Component, Drawable},
-    external_event::ExternalEvent,
-    queue::{AppEvent, Queue, TmuxOpts},
+    config::Config,
+    external_event::{ExternalEvent, JobId},
+    history::{History, HistoryKind},
+    interpolate,
+    multiplexer::MultiplexerOpts,
+    queue::{AppEvent, Queue},
     ui,
 };
-use anyhow::Result;
-use std::path::{PathBuf, MAIN_SEPARATOR};
+use anyhow::{Context, Result};
+use crossterm::event::Event;
+use easy_switch::switch;
+use std::{
+    borrow::Cow,
+    mem,
+    path::{PathBuf, MAIN_SEPARATOR},
+    rc::Rc,
+};
 use tui::{
     backend::Backend,
     layout::{Alignment, Rect},
@@ -23,13 +34,16 @@ pub enum InputOperation {
     NewDir {
         at: PathBuf,
     },
-    Command {
-        to: PathBuf,
-    },
+    /// Run the command typed in on the selected item
+    Command,
     Rename {
         to: PathBuf,
     },
     SpecialCommand(String),
+    PreviewSearch,
+    /// Save the output of a job to the file typed in
+    SaveOutput(JobId),
+    AddWatch,
     #[default]
     NoOperations,
 }
@@ -38,21 +52,47 @@ pub enum InputOperation {
 pub struct InputBox {
     pub operation: InputOperation,
     queue: Queue,
+    config: Rc<Config>,
     text: String,
     /// Offset from back of `text`
     cursor_offset: u32,
+    history: History,
+    /// Index of the history entry being shown
+    history_pos: Option<usize>,
+    /// What was typed before going through the history
+    draft: String,
+    /// Text to search the history for, when searching
+    search: Option<String>,
 }
 
 impl InputBox {
-    pub fn new(queue: Queue) -> Self {
+    pub fn new(queue: Queue, config: Rc<Config>) -> Self {
+        Self::with_history(queue, config, History::default())
+    }
+
+    pub fn with_history(queue: Queue, config: Rc<Config>, history: History) -> Self {
         Self {
             text: String::new(),
             queue,
+            config,
             operation: Default::default(),
             cursor_offset: 0,
+            history,
+            history_pos: None,
+            draft: String::new(),
+            search: None,
         }
     }
 
+    pub fn set_config(&mut self, config: Rc<Config>) {
+        self.config = config;
+    }
+
+    /// The last command run from the command input, before `{}` was substituted
+    pub fn last_command(&self) -> Option<&str> {
+        self.history.last(HistoryKind::Command)
+    }
+
     fn has_work(&self) -> bool {
         self.operation != InputOperation::NoOperations
     }
@@ -60,6 +100,140 @@ impl InputBox {
     fn reset(&mut self) {
         self.text = String::new();
         self.operation = InputOperation::NoOperations;
+        self.history_pos = None;
+        self.draft = String::new();
+        self.search = None;
+    }
+
+    fn history_kind(&self) -> Option<HistoryKind> {
+        match self.operation {
+            InputOperation::Command => Some(HistoryKind::Command),
+            InputOperation::SpecialCommand(_) => Some(HistoryKind::Input),
+            _ => None,
+        }
+    }
+
+    fn add_to_history(&mut self) -> Result<()> {
+        let Some(kind) = self.history_kind() else {
+            return Ok(());
+        };
+        self.history.add(kind, self.text.clone());
+        self.history
+            .write()
+            .context("failed to write command history")
+    }
+
+    fn show_history_entry(&mut self, kind: HistoryKind, pos: usize) {
+        if self.history_pos.is_none() {
+            self.draft = mem::take(&mut self.text);
+        }
+        self.history_pos = Some(pos);
+        self.text = self.history.entries(kind)[pos].clone();
+        self.cursor_offset = 0;
+    }
+
+    fn recall_older(&mut self) {
+        let Some(kind) = self.history_kind() else {
+            return;
+        };
+        let pos = match self.history_pos {
+            Some(pos) => pos.checked_sub(1),
+            None => self.history.entries(kind).len().checked_sub(1),
+        };
+        if let Some(pos) = pos {
+            self.show_history_entry(kind, pos);
+        }
+    }
+
+    fn recall_newer(&mut self) {
+        let (Some(kind), Some(pos)) = (self.history_kind(), self.history_pos) else {
+            return;
+        };
+        if pos + 1 < self.history.entries(kind).len() {
+            self.show_history_entry(kind, pos + 1);
+        } else {
+            // Went past the newest entry, so go back to what was being typed
+            self.history_pos = None;
+            self.text = mem::take(&mut self.draft);
+            self.cursor_offset = 0;
+        }
+    }
+
+    /// Go to the next older entry containing the text that was typed when the search started
+    fn search_older(&mut self) {
+        let Some(kind) = self.history_kind() else {
+            return;
+        };
+        let query = self.search.get_or_insert_with(|| self.text.clone());
+        let end = self
+            .history_pos
+            .unwrap_or_else(|| self.history.entries(kind).len());
+        let found = self.history.entries(kind)[..end]
+            .iter()
+            .rposition(|entry| entry.contains(query.as_str()));
+        if let Some(pos) = found {
+            self.show_history_entry(kind, pos);
+        }
+    }
+
+    /// Act on what was typed, for the current operation
+    fn submit(&mut self) -> Result<()> {
+        match &self.operation {
+            InputOperation::NewFile { at } => {
+                self.queue
+                    .add(AppEvent::NewFile(at.join(self.text.as_str())));
+            }
+            InputOperation::NewDir { at } => self
+                .queue
+                .add(AppEvent::NewDir(at.join(self.text.as_str()))),
+            InputOperation::Command => {
+                self.queue.add(AppEvent::RunCommand(self.text.clone()));
+            }
+            InputOperation::SpecialCommand(cmd) => {
+                let full_cmd = interpolate::fill_input(cmd, &self.text);
+                self.queue.add(AppEvent::RunCommand(full_cmd));
+            }
+            InputOperation::Rename { to } => {
+                let new_path = to.parent().unwrap().join(&self.text);
+                self.queue.add(AppEvent::RenameFile(to.clone(), new_path));
+            }
+            InputOperation::PreviewSearch => {
+                self.queue.add(AppEvent::SearchPreview(self.text.clone()));
+            }
+            InputOperation::AddWatch => {
+                self.queue.add(AppEvent::AddWatch(self.text.clone()));
+            }
+            InputOperation::SaveOutput(id) => {
+                self.queue
+                    .add(AppEvent::SaveOutput(*id, self.text.as_str().into()));
+            }
+            InputOperation::NoOperations => unreachable!("should have work"),
+        };
+        // The command should still run if history can't be saved
+        let written = self.add_to_history();
+        self.reset();
+        written
+    }
+
+    /// Run the typed command in a pane of the terminal multiplexer. Only commands can be.
+    fn run_in_multiplexer(&mut self, opts: MultiplexerOpts) -> Result<()> {
+        if !matches!(self.operation, InputOperation::Command) {
+            return Ok(());
+        }
+        self.queue
+            .add(AppEvent::RunCommandInMultiplexer(self.text.clone(), opts));
+        let written = self.add_to_history();
+        self.reset();
+        written
+    }
+
+    fn delete_back(&mut self) {
+        if self.text.len() as u32 <= self.cursor_offset {
+            return;
+        }
+        self.search = None;
+        self.text
+            .remove((self.text.len() - self.cursor_offset as usize) - 1);
     }
 
     fn cursor_left(&mut self) {
@@ -120,87 +294,89 @@ impl Component for InputBox {
         if !self.visible() {
             return Ok(());
         }
-        if let ExternalEvent::Crossterm(ev) = ev {
-            let input_event: Input = ev.clone().into();
-            match input_event {
-                Input { key: Key::Esc, .. } => self.reset(),
-                Input {
-                    key: Key::Right, ..
-                } => self.cursor_right(),
-                Input { key: Key::Left, .. } => self.cursor_left(),
-                // TODO: Keybinds to customize
-                Input {
-                    key: Key::Char(key),
-                    alt: false,
-                    ctrl: true,
-                } if matches!(key, 'v' | 'x' | 'n' | 'f') => {
-                    if let InputOperation::Command { to } = &self.operation {
-                        let cmd = self.text.replace("{}", &to.display().to_string());
-                        self.queue.add(AppEvent::RunCommandWithTmux(
-                            cmd,
-                            match key {
-                                'v' => TmuxOpts::VerticalSplit,
-                                'x' => TmuxOpts::HorizontalSplit,
-                                'n' => TmuxOpts::NewWindow,
-                                'f' => TmuxOpts::FloatingWindow,
-                                _ => unreachable!("in match guard"),
-                            },
-                        ));
-                        self.reset();
-                    }
-                }
-                Input {
-                    key: Key::Enter,
-                    alt: false,
-                    ctrl: false,
-                } if self
-                    .has_valid_input()
-                    .expect("should not be called with no work") =>
-                {
-                    match &self.operation {
-                        InputOperation::NewFile { at } => {
-                            self.queue
-                                .add(AppEvent::NewFile(at.join(self.text.as_str())));
-                        }
-                        InputOperation::NewDir { at } => self
-                            .queue
-                            .add(AppEvent::NewDir(at.join(self.text.as_str()))),
-                        InputOperation::Command { to } => {
-                            // Perform string substitution for path
-                            let cmd = self.text.replace("{}", &to.display().to_string());
-                            self.queue.add(AppEvent::RunCommand(cmd));
-                        }
-                        InputOperation::SpecialCommand(cmd) => {
-                            let full_cmd = cmd.replace("{...}", self.text.as_str());
-                            self.queue.add(AppEvent::RunCommand(full_cmd));
-                        }
-                        InputOperation::Rename { to } => {
-                            let new_path = to.parent().unwrap().join(&self.text);
-                            self.queue.add(AppEvent::RenameFile(to.clone(), new_path));
-                        }
-                        InputOperation::NoOperations => unreachable!("checked in match guard"),
-                    };
+        let ExternalEvent::Crossterm(ev) = ev else {
+            return Ok(());
+        };
+        if let Event::Key(key) = ev {
+            let input = &self.config.input;
+            let valid = self
+                .has_valid_input()
+                .expect("should not be called with no work");
+            let handled = switch! { key;
+                input.cancel => {
                     self.reset();
-                }
-                Input {
-                    key: Key::Char('u'),
-                    ctrl: true,
-                    ..
-                } => drop(self.text.drain(..self.cursor_pos())),
-                Input {
-                    key: Key::Delete | Key::Backspace,
-                    ..
-                } if self.text.len() as u32 > self.cursor_offset => {
-                    self.text
-                        .remove((self.text.len() - self.cursor_offset as usize) - 1);
-                }
-                Input {
-                    key: Key::Char(k), ..
-                } => self.text.insert(self.cursor_pos(), k),
-
-                _ => {}
+                    true
+                },
+                input.submit, valid => {
+                    self.submit()?;
+                    true
+                },
+                input.cursor_left => {
+                    self.cursor_left();
+                    true
+                },
+                input.cursor_right => {
+                    self.cursor_right();
+                    true
+                },
+                input.cursor_start => {
+                    self.cursor_offset = self.text.len() as u32;
+                    true
+                },
+                input.cursor_end => {
+                    self.cursor_offset = 0;
+                    true
+                },
+                input.delete => {
+                    self.delete_back();
+                    true
+                },
+                input.delete_to_start => {
+                    self.search = None;
+                    drop(self.text.drain(..self.cursor_pos()));
+                    true
+                },
+                input.history_older => {
+                    self.recall_older();
+                    true
+                },
+                input.history_newer => {
+                    self.recall_newer();
+                    true
+                },
+                input.search_history => {
+                    self.search_older();
+                    true
+                },
+                input.vertical_split => {
+                    self.run_in_multiplexer(MultiplexerOpts::VerticalSplit)?;
+                    true
+                },
+                input.horizontal_split => {
+                    self.run_in_multiplexer(MultiplexerOpts::HorizontalSplit)?;
+                    true
+                },
+                input.new_window => {
+                    self.run_in_multiplexer(MultiplexerOpts::NewWindow)?;
+                    true
+                },
+                input.floating_window => {
+                    self.run_in_multiplexer(MultiplexerOpts::FloatingWindow)?;
+                    true
+                },
+                _ => false,
+            };
+            if handled {
+                return Ok(());
             }
         }
+        if let Input {
+            key: Key::Char(k), ..
+        } = ev.clone().into()
+        {
+            self.search = None;
+            self.text.insert(self.cursor_pos(), k);
+        }
         Ok(())
     }
 }
@@ -212,19 +388,25 @@ impl Drawable for InputBox {
         }
         let area = ui::centered_rect_absolute(50, 3, area);
         let title = match self.operation {
-            InputOperation::Command { .. } => "Run Command",
+            InputOperation::Command => "Run Command",
             InputOperation::SpecialCommand(_) => "Command Input",
             InputOperation::NewDir { .. } => "New Directory",
             InputOperation::NewFile { .. } => "New File",
             InputOperation::Rename { .. } => "Rename file",
+            InputOperation::PreviewSearch => "Search Preview",
+            InputOperation::SaveOutput(_) => "Save Output To",
+            InputOperation::AddWatch => "Add Watch (GLOB COMMAND)",
             InputOperation::NoOperations => unreachable!("checked at top of method"),
         };
+        let title = self.search.as_ref().map_or(Cow::Borrowed(title), |query| {
+            Cow::Owned(format!("{title} (search: {query})"))
+        });
         let mut textarea = TextArea::default();
         textarea.insert_str(&self.text);
         textarea.set_block(
             Block::default()
                 .borders(Borders::ALL)
-                .title(title)
+                .title(title.as_ref())
                 .title_alignment(Alignment::Center)
                 .border_style(if self.has_valid_input().expect("should have operation") {
                     Style::default().fg(Color::LightGreen)
@@ -244,6 +426,7 @@ impl Drawable for InputBox {
 #[cfg(test)]
 mod tests {
     use super::{super::testing::*, *};
+    use crate::config::{Key as KeyBindKey, KeyBind};
     use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers};
     use test_log::test;
 
@@ -277,7 +460,7 @@ mod tests {
             InputOperation::NewDir { at: "/".into() },
             InputOperation::NewFile { at: "/".into() },
         ] {
-            let mut input_box = InputBox::new(Queue::new());
+            let mut input_box = InputBox::new(Queue::new(), Rc::new(Config::default()));
             input_box.operation = operation;
             input_box.text = "should not work \\".to_owned();
             assert!(!input_box.has_valid_input().expect("should have work"));
@@ -390,6 +573,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn can_send_preview_search_event() {
+        let event = input_event!(KeyCode::Enter);
+        let mut input_box = InputBox {
+            operation: InputOperation::PreviewSearch,
+            text: "query".to_owned(),
+            ..Default::default()
+        };
+        input_box.handle_event(&event).expect("input should work");
+        assert_eq!(
+            AppEvent::SearchPreview("query".to_owned()),
+            input_box.queue.pop().expect("should have sent event")
+        );
+    }
+
     #[test]
     fn resets_after_option_entered() {
         let event = input_event!(KeyCode::Enter);
@@ -445,13 +643,13 @@ mod tests {
 
         let mut input_box = InputBox {
             text: "testing {}".to_owned(),
-            operation: InputOperation::Command { to: "/".into() },
+            operation: InputOperation::Command,
             ..Default::default()
         };
         input_box.handle_event(&enter).unwrap();
 
         assert_eq!(
-            AppEvent::RunCommand("testing /".to_owned()),
+            AppEvent::RunCommand("testing {}".to_owned()),
             input_box.queue.pop().unwrap()
         );
     }
@@ -460,7 +658,7 @@ mod tests {
     fn deletes_where_cursor_is() {
         let mut input_box = InputBox {
             text: "testing".to_owned(),
-            operation: InputOperation::Command { to: "/".into() },
+            operation: InputOperation::Command,
             ..Default::default()
         };
 
@@ -474,7 +672,7 @@ mod tests {
     fn inserts_where_cursor_is() {
         let mut input_box = InputBox {
             text: "testing".to_owned(),
-            operation: InputOperation::Command { to: "/".into() },
+            operation: InputOperation::Command,
             ..Default::default()
         };
 
@@ -488,7 +686,7 @@ mod tests {
     fn deletes_line_where_cursor_is() {
         let mut input_box = InputBox {
             text: "testing".to_owned(),
-            operation: InputOperation::Command { to: "/".into() },
+            operation: InputOperation::Command,
             ..Default::default()
         };
 
@@ -499,18 +697,130 @@ mod tests {
     }
 
     #[test]
-    fn can_send_tmux_command() {
+    fn can_send_multiplexer_command() {
         let mut input_box = InputBox {
             text: "testing".to_owned(),
-            operation: InputOperation::Command { to: "/".into() },
+            operation: InputOperation::Command,
             ..Default::default()
         };
 
         let event = input_event!(KeyCode::Char('v'); KeyModifiers::CONTROL);
         assert!(input_box.handle_event(&event).is_ok());
-        assert!(input_box.queue.contains(&AppEvent::RunCommandWithTmux(
+        assert!(input_box.queue.contains(&AppEvent::RunCommandInMultiplexer(
             "testing".to_owned(),
-            TmuxOpts::VerticalSplit
+            MultiplexerOpts::VerticalSplit
         )));
     }
+
+    #[test]
+    fn keys_can_be_customized() {
+        let mut config = Config::default();
+        config.input.submit = KeyBind::key(KeyBindKey::ctrl('s'));
+        config.input.cursor_start = KeyBind::key(KeyBindKey::ctrl('b'));
+        let mut input_box = InputBox::new(Queue::new(), Rc::new(config));
+        input_box.operation = InputOperation::Command;
+        input_box.text = "make".to_owned();
+
+        // Enter isn't bound anymore, so it does nothing
+        input_box
+            .handle_event(&input_event!(KeyCode::Enter))
+            .unwrap();
+        assert!(input_box.visible());
+        let events = input_events!(
+            KeyCode::Char('b'); KeyModifiers::CONTROL,
+            KeyCode::Char('x'),
+            KeyCode::Char('s'); KeyModifiers::CONTROL
+        );
+        for event in events {
+            input_box.handle_event(&event).unwrap();
+        }
+        assert!(input_box
+            .queue
+            .contains(&AppEvent::RunCommand("xmake".to_owned())));
+    }
+
+    #[test]
+    fn multiplexer_keys_only_run_commands() {
+        let mut input_box = InputBox {
+            text: "new.txt".to_owned(),
+            operation: InputOperation::NewFile { at: "/".into() },
+            ..Default::default()
+        };
+        let event = input_event!(KeyCode::Char('v'); KeyModifiers::CONTROL);
+        input_box.handle_event(&event).unwrap();
+        assert_eq!(None, input_box.queue.pop());
+        assert_eq!("new.txt", input_box.text);
+    }
+
+    fn input_box_with_history() -> InputBox {
+        let mut history = History::default();
+        for cmd in ["cargo build", "ls", "cargo test"] {
+            history.add(HistoryKind::Command, cmd.to_owned());
+        }
+        let mut input_box =
+            InputBox::with_history(Queue::new(), Rc::new(Config::default()), history);
+        input_box.operation = InputOperation::Command;
+        input_box
+    }
+
+    #[test]
+    fn up_and_down_go_through_history() {
+        let mut input_box = input_box_with_history();
+        input_box.text = "draft".to_owned();
+
+        let events = input_events!(KeyCode::Up, KeyCode::Up, KeyCode::Up, KeyCode::Up);
+        for event in &events {
+            input_box.handle_event(event).unwrap();
+        }
+        assert_eq!("cargo build", input_box.text);
+        input_box
+            .handle_event(&input_event!(KeyCode::Down))
+            .unwrap();
+        assert_eq!("ls", input_box.text);
+        for event in input_events!(KeyCode::Down, KeyCode::Down) {
+            input_box.handle_event(&event).unwrap();
+        }
+        assert_eq!("draft", input_box.text);
+    }
+
+    #[test]
+    fn ctrl_r_searches_history() {
+        let mut input_box = input_box_with_history();
+        input_box.text = "cargo".to_owned();
+
+        let search = input_event!(KeyCode::Char('r'); KeyModifiers::CONTROL);
+        input_box.handle_event(&search).unwrap();
+        assert_eq!("cargo test", input_box.text);
+        input_box.handle_event(&search).unwrap();
+        assert_eq!("cargo build", input_box.text);
+        // No older matches
+        input_box.handle_event(&search).unwrap();
+        assert_eq!("cargo build", input_box.text);
+    }
+
+    #[test]
+    fn executed_commands_are_added_to_history() {
+        let mut input_box = input_box_with_history();
+        input_box.text = "echo {}".to_owned();
+        input_box
+            .handle_event(&input_event!(KeyCode::Enter))
+            .unwrap();
+        assert_eq!(Some("echo {}"), input_box.last_command());
+
+        input_box.operation = InputOperation::SpecialCommand("cargo add {...}".to_owned());
+        input_box.text = "serde".to_owned();
+        input_box
+            .handle_event(&input_event!(KeyCode::Enter))
+            .unwrap();
+        assert_eq!(Some("echo {}"), input_box.last_command());
+        assert_eq!(["serde"], input_box.history.entries(HistoryKind::Input));
+    }
+
+    #[test]
+    fn history_is_not_used_for_other_operations() {
+        let mut input_box = input_box_with_history();
+        input_box.operation = InputOperation::NewFile { at: "/".into() };
+        input_box.handle_event(&input_event!(KeyCode::Up)).unwrap();
+        assert!(input_box.text.is_empty());
+    }
 }
@@ -0,0 +1,344 @@
+use crate::{
+    app::{
+        component::{Component, Drawable},
+        InputOperation,
+    },
+    config::{Config, Key},
+    external_event::{ExternalEvent, Job, JobId, JobStatus, Jobs},
+    queue::{AppEvent, Queue},
+    ui::{self, ParagraphState, ScrollParagraph},
+};
+use ansi_to_tui::IntoText;
+use anyhow::Result;
+use crossterm::event::Event;
+use easy_switch::switch;
+use itertools::Itertools;
+use std::{cell::Cell, rc::Rc};
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    widgets::{Block, Borders, Clear, List, ListItem, ListState},
+    Frame,
+};
+
+pub struct JobsPopup {
+    jobs: Jobs,
+    queue: Queue,
+    open: bool,
+    config: Rc<Config>,
+    state: Cell<ListState>,
+    /// Job whose output is being shown instead of the list
+    viewing: Option<JobId>,
+    output_state: Cell<ParagraphState>,
+}
+
+impl Default for JobsPopup {
+    fn default() -> Self {
+        Self::new(Jobs::new(1), Queue::new(), Rc::new(Config::default()))
+    }
+}
+
+impl JobsPopup {
+    pub fn new(jobs: Jobs, queue: Queue, config: Rc<Config>) -> Self {
+        let mut state = ListState::default();
+        state.select(Some(0));
+        Self {
+            jobs,
+            queue,
+            config,
+            state: state.into(),
+            open: false,
+            viewing: None,
+            output_state: ParagraphState::default().into(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: Rc<Config>) {
+        self.config = config;
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+
+    pub fn close(&mut self) {
+        self.state.get_mut().select(Some(0));
+        self.viewing = None;
+        self.open = false;
+    }
+
+    /// Show the output of the selected job
+    pub fn view_selected(&mut self) {
+        if let Some(job) = self.jobs.jobs().get(self.selected()) {
+            self.viewing = Some(job.id);
+            self.output_state.get_mut().scroll_bottom();
+        }
+    }
+
+    pub fn kill_selected(&mut self) -> Result<()> {
+        if let Some(job) = self.jobs.jobs().get(self.selected()) {
+            self.jobs.kill(job.id)?;
+        }
+        Ok(())
+    }
+
+    fn selected(&self) -> usize {
+        let state = self.state.take();
+        let selected = state.selected().expect("should have something selected");
+        self.state.set(state);
+        selected
+    }
+
+    fn select_next(&mut self) {
+        let current = self.selected();
+        let len = self.jobs.jobs().len();
+        // Finished jobs can be forgotten, so the selection might be past the end
+        if current + 1 >= len {
+            return;
+        }
+        self.state.get_mut().select(Some(current + 1));
+    }
+
+    fn select_prev(&mut self) {
+        let current = self.selected();
+        if current == 0 {
+            return;
+        }
+        self.state.get_mut().select(Some(current - 1));
+    }
+
+    fn select_first(&mut self) {
+        self.state.get_mut().select(Some(0));
+    }
+
+    fn select_last(&mut self) {
+        let len = self.jobs.jobs().len();
+        if len == 0 {
+            return;
+        }
+        self.state.get_mut().select(Some(len - 1));
+    }
+
+    fn job_item(&self, job: &Job) -> ListItem<'_> {
+        let style = match job.status {
+            JobStatus::Queued | JobStatus::Running => self.config.jobs.running_style,
+            _ if job.status.success() => self.config.jobs.finished_style,
+            _ => self.config.jobs.failed_style,
+        };
+        let pid = job.pid.map(|pid| pid.to_string()).unwrap_or_default();
+        let runtime = job
+            .runtime()
+            .map(|runtime| format!("{:.1}s", runtime.as_secs_f32()))
+            .unwrap_or_default();
+        ListItem::new(format!(
+            "{:>3} {:<9} {pid:>7} {runtime:>7}  {}",
+            job.id,
+            job.status.to_string(),
+            job.command
+        ))
+        .style(style.into())
+    }
+}
+
+impl JobsPopup {
+    fn draw_output<B: Backend>(&self, f: &mut Frame<B>, area: Rect, id: JobId) -> Result<()> {
+        let output = self.jobs.output(id);
+        let command = self
+            .jobs
+            .jobs()
+            .into_iter()
+            .find(|job| job.id == id)
+            .map(|job| job.command)
+            .unwrap_or_default();
+        let mut title = format!("Output of [{id}] {command}");
+        if output.is_truncated() {
+            title.push_str(" (truncated)");
+        }
+        let paragraph = ScrollParagraph::new(output.text().into_text()?)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(self.config.popup_border_style.into())
+                    .title(title),
+            )
+            .bar_style(self.config.preview.scroll_bar_color.into())
+            .unreached_bar_style(self.config.preview.unreached_bar_color.into());
+        let area = ui::centered_rect(80, 80, area);
+        f.render_widget(Clear, area);
+        let mut state = self.output_state.take();
+        // Keep following new output, unless scrolled up
+        if state.at_bottom() {
+            state.scroll_bottom();
+        }
+        f.render_stateful_widget(paragraph, area, &mut state);
+        self.output_state.set(state);
+
+        Ok(())
+    }
+
+    fn handle_output_key(&mut self, id: JobId, key: &crossterm::event::KeyEvent) {
+        let state = self.output_state.get_mut();
+        switch! { key;
+            self.config.quit => self.viewing = None,
+            Key::esc() => self.viewing = None,
+            self.config.down => state.down(),
+            self.config.up => state.up(),
+            self.config.preview.down_key => state.down_by(self.config.preview.scroll_amount),
+            self.config.preview.up_key => state.up_by(self.config.preview.scroll_amount),
+            self.config.all_up => state.reset(),
+            self.config.all_down => state.scroll_bottom(),
+            self.config.jobs.save => self
+                .queue
+                .add(AppEvent::OpenInput(InputOperation::SaveOutput(id))),
+        }
+    }
+}
+
+impl Drawable for JobsPopup {
+    fn draw<B: Backend>(&self, f: &mut Frame<B>, area: Rect) -> Result<()> {
+        if !self.visible() {
+            return Ok(());
+        }
+        if let Some(id) = self.viewing {
+            return self.draw_output(f, area, id);
+        }
+
+        let jobs = self.jobs.jobs();
+        let items = jobs.iter().map(|job| self.job_item(job)).collect_vec();
+        let list = List::new(items)
+            .highlight_style(self.config.selected.into())
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(self.config.popup_border_style.into())
+                    .title(format!("Jobs ({} running)", self.jobs.running())),
+            );
+        let area = ui::centered_rect_absolute(70, 15, area);
+        f.render_widget(Clear, area);
+        let mut state = self.state.take();
+        f.render_stateful_widget(list, area, &mut state);
+        self.state.set(state);
+
+        Ok(())
+    }
+}
+
+impl Component for JobsPopup {
+    fn visible(&self) -> bool {
+        self.open
+    }
+
+    fn handle_event(&mut self, ev: &ExternalEvent) -> Result<()> {
+        if !self.visible() {
+            return Ok(());
+        }
+
+        if let ExternalEvent::Crossterm(Event::Key(key)) = ev {
+            if let Some(id) = self.viewing {
+                self.handle_output_key(id, key);
+                return Ok(());
+            }
+            switch! { key;
+                self.config.open => self.view_selected(),
+                self.config.quit => self.close(),
+                Key::esc() => self.close(),
+                self.config.down => self.select_next(),
+                self.config.up => self.select_prev(),
+                self.config.all_up => self.select_first(),
+                self.config.all_down => self.select_last(),
+                self.config.jobs.kill => self.kill_selected()?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::components::testing::*;
+    use duct::cmd;
+    use test_log::test;
+
+    fn test_popup() -> JobsPopup {
+        let jobs = Jobs::new(1);
+        jobs.spawn("sleep", cmd!("sleep", "5")).unwrap();
+        jobs.spawn("sleep", cmd!("sleep", "5")).unwrap();
+        let mut popup = JobsPopup::new(jobs, Queue::new(), Rc::new(Config::default()));
+        popup.open();
+        popup
+    }
+
+    #[test]
+    fn kills_selected_job() {
+        let mut popup = test_popup();
+        popup.select_next();
+        popup
+            .handle_event(&input_event!(KeyCode::Char('d')))
+            .unwrap();
+        let jobs = popup.jobs.jobs();
+        assert_eq!(JobStatus::Running, jobs[0].status);
+        assert_eq!(JobStatus::Killed, jobs[1].status);
+        popup.jobs.kill_all().unwrap();
+    }
+
+    #[test]
+    fn does_not_panic_with_zero_jobs() {
+        let mut popup = JobsPopup::default();
+        popup.open();
+        let events = input_events!(
+            KeyCode::Char('j'),
+            KeyCode::Char('k'),
+            KeyCode::Char('g'),
+            KeyCode::Char('G'); KeyModifiers::SHIFT,
+            KeyCode::Char('d')
+        );
+        for event in events {
+            assert!(popup.handle_event(&event).is_ok());
+        }
+    }
+
+    #[test]
+    fn can_select_last() {
+        let mut popup = test_popup();
+        popup.select_last();
+        assert_eq!(1, popup.selected());
+        popup.jobs.kill_all().unwrap();
+    }
+
+    #[test]
+    fn shows_output_of_selected_job_and_can_save_it() {
+        let mut popup = test_popup();
+        popup.select_next();
+        popup.handle_event(&input_event!(KeyCode::Enter)).unwrap();
+        assert_eq!(Some(1), popup.viewing);
+
+        popup
+            .handle_event(&input_event!(KeyCode::Char('s')))
+            .unwrap();
+        assert!(popup
+            .queue
+            .contains(&AppEvent::OpenInput(InputOperation::SaveOutput(1))));
+        // Keys for the list don't apply while viewing output
+        popup
+            .handle_event(&input_event!(KeyCode::Char('d')))
+            .unwrap();
+        assert_ne!(JobStatus::Killed, popup.jobs.jobs()[1].status);
+
+        popup.handle_event(&input_event!(KeyCode::Esc)).unwrap();
+        assert_eq!(None, popup.viewing);
+        assert!(popup.visible());
+        popup.jobs.kill_all().unwrap();
+    }
+
+    #[test]
+    fn resets_selected_when_closed() {
+        let mut popup = test_popup();
+        popup.select_next();
+        popup.close();
+        assert_eq!(0, popup.selected());
+        assert!(!popup.visible());
+        popup.jobs.kill_all().unwrap();
+    }
+}
@@ -57,6 +57,10 @@ impl MarksPopup {
         }
     }
 
+    pub fn set_config(&mut self, config: Rc<Config>) {
+        self.config = config;
+    }
+
     pub fn open(&mut self) {
         self.open = true;
     }
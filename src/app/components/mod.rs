@@ -3,18 +3,22 @@ mod file_cmd_popup;
 mod filetree;
 mod fuzzy_match;
 mod input_box;
+mod jobs_popup;
 mod marks_popup;
 mod pending_popup;
 mod popup;
 mod preview_file;
 mod testing;
+mod watches_popup;
 
 pub use event_logger::*;
 pub use file_cmd_popup::*;
 pub use filetree::*;
 pub use fuzzy_match::*;
 pub use input_box::*;
+pub use jobs_popup::*;
 pub use marks_popup::*;
 pub use pending_popup::*;
 pub use popup::*;
 pub use preview_file::*;
+pub use watches_popup::*;
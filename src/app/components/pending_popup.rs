@@ -56,6 +56,10 @@ impl PendingPopup {
         }
     }
 
+    pub fn set_config(&mut self, config: Rc<Config>) {
+        self.config = config;
+    }
+
     fn has_work(&self) -> bool {
         self.operation != PendingOperation::NoPending
     }
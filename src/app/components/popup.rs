@@ -20,7 +20,6 @@ use tui::{
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
 pub enum Preset {
     Help,
-    RunningCommand,
     #[default]
     Nothing,
 }
@@ -40,6 +39,10 @@ impl Popup {
             config,
         }
     }
+
+    pub fn set_config(&mut self, config: Rc<Config>) {
+        self.config = config;
+    }
 }
 
 impl Component for Popup {
@@ -100,6 +103,15 @@ impl Drawable for Popup {
                     (self.config.filetree.clear.to_string(), "Clear search"),
                     (self.config.preview.up_key.to_string(), "Preview down"),
                     (self.config.preview.down_key.to_string(), "Preview up"),
+                    (self.config.preview.search.to_string(), "Search preview"),
+                    (
+                        self.config.preview.next_match.to_string(),
+                        "Next preview match",
+                    ),
+                    (
+                        self.config.preview.prev_match.to_string(),
+                        "Previous preview match",
+                    ),
                     (
                         self.config.filetree.diff_mode.to_string(),
                         "Toggle diff view",
@@ -113,6 +125,10 @@ impl Drawable for Popup {
                         "Show dotfiles",
                     ),
                     (self.config.filetree.exec_cmd.to_string(), "Execute command"),
+                    (
+                        self.config.filetree.repeat_cmd.to_string(),
+                        "Repeat last command",
+                    ),
                     (
                         self.config.filetree.special_command.to_string(),
                         "Execute special command",
@@ -138,7 +154,11 @@ impl Drawable for Popup {
                         "Mark selected file",
                     ),
                     (self.config.filetree.rename.to_string(), "Rename/move file"),
+                    (self.config.filetree.extract.to_string(), "Extract archive"),
                     (self.config.marks.open.to_string(), "Open marks window"),
+                    (self.config.jobs.open.to_string(), "Open jobs window"),
+                    (self.config.watch.open.to_string(), "Open watch window"),
+                    (self.config.open_tasks.to_string(), "Run task"),
                     (self.config.quit.to_string(), "Quit"),
                     (self.config.help.to_string(), "Open help window"),
                 ];
@@ -171,13 +191,6 @@ impl Drawable for Popup {
                     35,
                 )
             }
-            Preset::RunningCommand => {
-                let message = format!(
-                    "Command in-progress. Press {} to quit",
-                    self.config.kill_processes
-                );
-                (vec![Spans::from(vec![Span::raw(message)])], "Command", 3)
-            }
             Preset::Nothing => unreachable!("checked at top of method"),
         };
 
@@ -1,26 +1,44 @@
 use crate::{
     app::component::{Component, Drawable},
+    archive::{self, ArchiveKind},
+    command_env::CommandEnv,
     config::Config,
-    external_event::ExternalEvent,
+    external_event::{ExternalEvent, RefreshData},
+    interpolate,
     ui::{ParagraphState, ScrollParagraph},
 };
 use ansi_to_tui::IntoText;
 use anyhow::{bail, Context, Result};
-use crossterm::event::{Event, MouseEventKind};
+use crossterm::event::{Event, KeyModifiers, MouseEventKind};
 #[cfg(not(target_os = "windows"))]
 use duct::cmd;
 use easy_switch::switch;
+use itertools::Itertools;
 use log::trace;
 #[cfg(not(target_os = "windows"))]
 use std::env;
 #[cfg(target_os = "windows")]
+use std::ffi::OsString;
+#[cfg(target_os = "windows")]
 use std::os::windows::process::CommandExt;
 #[cfg(target_os = "windows")]
 use std::process::Command;
-use std::{cell::Cell, collections::VecDeque, fs, path::Path, rc::Rc};
+use std::{
+    borrow::Cow,
+    cell::Cell,
+    collections::VecDeque,
+    fmt::Write,
+    fs::{self, File},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 use tui::{
     backend::Backend,
     layout::Rect,
+    style::Style,
+    text::{Span, Spans, Text},
     widgets::{Block, Borders},
     Frame,
 };
@@ -29,6 +47,8 @@ use tui::{
 enum ScrollDirection {
     Down,
     Up,
+    Left,
+    Right,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
@@ -45,26 +65,362 @@ enum Mode {
     Diff,
 }
 
+/// Longest line shown in a truncated preview. Longer lines are cut, so a huge file with few
+/// newlines, like minified JSON, is never read into memory whole.
+const MAX_LINE_LEN: usize = 1024;
+
+/// Partial contents of a file that is too large to preview all at once. Lines are streamed from
+/// the top of the file in chunks, optionally along with a fixed amount of lines from the bottom.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Truncated {
+    path: PathBuf,
+    size: u64,
+    /// Most bytes read at once, for the tail and for each chunk
+    max_bytes: u64,
+    /// Byte offset of the first byte that has not been loaded yet
+    offset: u64,
+    /// Byte offset where `tail` starts
+    tail_start: u64,
+    head: String,
+    head_lines: usize,
+    /// Start of a line that did not end in the last chunk
+    partial_line: Vec<u8>,
+    /// Whether the last line was cut, so the rest of it is skipped
+    is_cut: bool,
+    tail: String,
+    tail_lines: usize,
+}
+
+impl Truncated {
+    fn open(
+        path: impl AsRef<Path>,
+        size: u64,
+        max_bytes: u64,
+        lines: usize,
+        tail_lines: usize,
+    ) -> Result<Self> {
+        let max_bytes = max_bytes.max(1);
+        let (tail_start, tail) = read_tail(path.as_ref(), size, max_bytes, tail_lines)?;
+        let mut truncated = Self {
+            path: path.as_ref().to_path_buf(),
+            size,
+            max_bytes,
+            tail_start,
+            tail_lines: tail.lines().count(),
+            tail,
+            ..Default::default()
+        };
+        truncated.load_more(lines)?;
+        Ok(truncated)
+    }
+
+    /// Whether every line between the top of the file and the tail has been loaded
+    fn is_complete(&self) -> bool {
+        self.offset >= self.tail_start
+    }
+
+    /// Load the next `lines` lines from the top of the file, stopping at the tail or after
+    /// `max_bytes` bytes
+    fn load_more(&mut self, lines: usize) -> Result<()> {
+        if self.is_complete() {
+            return Ok(());
+        }
+
+        let mut file = File::open(&self.path)
+            .with_context(|| format!("problem opening \"{}\"", self.path.display()))?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let budget = (self.tail_start - self.offset).min(self.max_bytes);
+        let mut reader = BufReader::new(file).take(budget);
+        let mut loaded = 0;
+        while loaded < lines {
+            let available = reader.fill_buf()?;
+            if available.is_empty() {
+                if reader.limit() != 0 {
+                    // The file shrank since it was opened
+                    self.tail_start = self.offset;
+                }
+                break;
+            }
+            let newline = available.iter().position(|byte| *byte == b'\n');
+            let len = newline.map_or(available.len(), |pos| pos + 1);
+            if self.is_cut {
+                // Skip the rest of a line that was already shown cut
+                self.is_cut = newline.is_none();
+            } else {
+                let room = MAX_LINE_LEN + 1 - self.partial_line.len();
+                self.partial_line
+                    .extend_from_slice(&available[..len.min(room)]);
+                if len > room {
+                    self.is_cut = true;
+                    self.finish_line();
+                    loaded += 1;
+                } else if newline.is_some() {
+                    self.finish_line();
+                    loaded += 1;
+                }
+            }
+            reader.consume(len);
+            self.offset += len as u64;
+        }
+        // The last line before the tail might not end in a newline
+        if self.is_complete() && !self.partial_line.is_empty() {
+            self.finish_line();
+        }
+        trace!(
+            "loaded {} lines of \"{}\" for preview",
+            self.head_lines,
+            self.path.display()
+        );
+
+        Ok(())
+    }
+
+    /// Add the line in `partial_line` to the head, cut if it is too long
+    fn finish_line(&mut self) {
+        let line = String::from_utf8_lossy(&self.partial_line);
+        if self.partial_line.len() > MAX_LINE_LEN {
+            self.head.push_str(&cut_line(&line));
+            self.head.push('\n');
+        } else {
+            self.head.push_str(&line);
+        }
+        self.head_lines += 1;
+        self.partial_line.clear();
+    }
+
+    fn banner(&self) -> String {
+        if self.is_complete() {
+            return format!(
+                "large file ({} bytes), showing all {} lines",
+                self.size,
+                self.head_lines + self.tail_lines
+            );
+        }
+        let mut banner = format!("preview truncated, showing first {} lines", self.head_lines);
+        if self.tail_lines != 0 {
+            write!(banner, " and last {} lines", self.tail_lines)
+                .expect("writing to a string should not fail");
+        }
+        banner
+    }
+
+    fn to_text(&self, banner_style: Style) -> Result<Text<'static>> {
+        let mut text = Text::styled(self.banner(), banner_style);
+        text.extend(self.head.into_text()?);
+        if !self.is_complete() && !self.tail.is_empty() {
+            text.extend(Text::styled("...", banner_style));
+        }
+        text.extend(self.tail.into_text()?);
+        Ok(text)
+    }
+}
+
+/// Read the last `lines` lines of a file with a length of `size`, looking at no more than the
+/// last `max_bytes` bytes. Returns the lines and the byte offset they start at.
+fn read_tail(path: &Path, size: u64, max_bytes: u64, lines: usize) -> Result<(u64, String)> {
+    const BLOCK_SIZE: u64 = 8192;
+
+    if lines == 0 {
+        return Ok((size, String::new()));
+    }
+    let mut file =
+        File::open(path).with_context(|| format!("problem opening \"{}\"", path.display()))?;
+    let limit = size.saturating_sub(max_bytes);
+    // Read backwards, so the blocks are in reverse
+    let mut blocks = Vec::new();
+    let mut start = size;
+    let mut newlines = 0;
+    // Start of the first whole line found so far
+    let mut first_line = None;
+    'blocks: while start > limit {
+        let block_start = start.saturating_sub(BLOCK_SIZE).max(limit);
+        let mut block = vec![0; (start - block_start) as usize];
+        file.seek(SeekFrom::Start(block_start))?;
+        file.read_exact(&mut block)?;
+        start = block_start;
+        for (i, byte) in block.iter().enumerate().rev() {
+            let pos = block_start + i as u64;
+            // A trailing newline does not begin another line
+            if *byte == b'\n' && pos + 1 < size {
+                newlines += 1;
+                first_line = Some(pos + 1);
+                if newlines == lines {
+                    blocks.push(block);
+                    break 'blocks;
+                }
+            }
+        }
+        blocks.push(block);
+    }
+
+    let tail_start = if newlines == lines || start == 0 {
+        first_line.filter(|_| newlines == lines).unwrap_or(0)
+    } else {
+        // Out of bytes to look at, so only show the whole lines that were found
+        first_line.unwrap_or(size)
+    };
+    let buf = blocks.into_iter().rev().flatten().collect_vec();
+    let tail = String::from_utf8_lossy(&buf[(tail_start - start) as usize..]);
+    let tail = tail
+        .split_inclusive('\n')
+        .map(|line| {
+            if line.trim_end_matches('\n').len() <= MAX_LINE_LEN {
+                return Cow::Borrowed(line);
+            }
+            let mut cut = cut_line(line);
+            if line.ends_with('\n') {
+                cut.push('\n');
+            }
+            Cow::Owned(cut)
+        })
+        .collect();
+    Ok((tail_start, tail))
+}
+
+/// The first `MAX_LINE_LEN` bytes of `line`, marked as cut
+fn cut_line(line: &str) -> String {
+    let mut end = MAX_LINE_LEN.min(line.len());
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}…", line[..end].trim_end_matches('\n'))
+}
+
+/// A match of a preview search, as a byte range into the text of a line with ANSI escapes removed
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Match {
+    line: usize,
+    range: Range<usize>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Search {
+    query: String,
+    matches: Vec<Match>,
+    /// Index into `matches`
+    current: usize,
+}
+
+/// Find all non-overlapping occurrences of `query` in `line`. The search is case-insensitive
+/// unless `query` contains an uppercase character.
+fn find_matches(line: &str, query: &str) -> Vec<Range<usize>> {
+    let case_sensitive = query.chars().any(char::is_uppercase);
+    let chars_eq = |a: char, b: char| {
+        if case_sensitive {
+            a == b
+        } else {
+            a.to_lowercase().eq(b.to_lowercase())
+        }
+    };
+
+    let mut matches = Vec::new();
+    let mut search_from = 0;
+    for (start, _) in line.char_indices() {
+        if start < search_from {
+            continue;
+        }
+        let mut end = start;
+        let mut query_chars = query.chars();
+        let mut line_chars = line[start..].char_indices();
+        let found = loop {
+            let Some(query_char) = query_chars.next() else {
+                break true;
+            };
+            match line_chars.next() {
+                Some((offset, c)) if chars_eq(c, query_char) => end = start + offset + c.len_utf8(),
+                _ => break false,
+            }
+        };
+        if found {
+            matches.push(start..end);
+            search_from = end;
+        }
+    }
+    matches
+}
+
+/// Patch `style` onto the parts of `spans` that fall within each range. The ranges must be sorted
+/// and must not overlap.
+fn highlight(spans: Spans<'static>, ranges: &[(Range<usize>, Style)]) -> Spans<'static> {
+    let mut highlighted = Vec::with_capacity(spans.0.len() + ranges.len() * 2);
+    let mut offset = 0;
+    for span in spans.0 {
+        let span_range = offset..offset + span.content.len();
+        offset = span_range.end;
+        let mut pos = span_range.start;
+        for (range, style) in ranges {
+            if range.end <= pos || range.start >= span_range.end {
+                continue;
+            }
+            let start = range.start.max(pos);
+            let end = range.end.min(span_range.end);
+            if start > pos {
+                highlighted.push(Span::styled(
+                    span.content[pos - span_range.start..start - span_range.start].to_owned(),
+                    span.style,
+                ));
+            }
+            highlighted.push(Span::styled(
+                span.content[start - span_range.start..end - span_range.start].to_owned(),
+                span.style.patch(*style),
+            ));
+            pos = end;
+        }
+        if pos < span_range.end {
+            highlighted.push(Span::styled(
+                span.content[pos - span_range.start..].to_owned(),
+                span.style,
+            ));
+        }
+    }
+    Spans(highlighted)
+}
+
 pub struct PreviewFile {
     git_cmd: String,
     mode: Mode,
     contents: String,
+    truncated: Option<Truncated>,
+    path: Option<PathBuf>,
+    search: Option<Search>,
     focused: bool,
     config: Rc<Config>,
     state: Cell<ParagraphState>,
     scrolls: Cell<VecDeque<Scroll>>,
+    /// Height of the preview area when it was last drawn
+    height: Cell<u16>,
+    wrap: bool,
+    /// Environment of the preview command
+    env: Option<CommandEnv>,
+}
+
+/// Command to show the diff of a file with, through the pager of `config` if it has one
+fn git_cmd(config: &Config) -> String {
+    config
+        .preview
+        .git_pager
+        .as_ref()
+        .map_or("git diff {}".to_owned(), |cmd| {
+            format!("git diff {{}} | {}", cmd)
+        })
 }
 
 impl Default for PreviewFile {
     fn default() -> Self {
         Self {
             contents: String::new(),
+            truncated: None,
+            path: None,
+            search: None,
             focused: true,
             mode: Mode::default(),
             config: Rc::new(Config::default()),
             git_cmd: "git diff {}".to_owned(),
             state: ParagraphState::default().into(),
             scrolls: VecDeque::new().into(),
+            height: Cell::default(),
+            wrap: false,
+            env: None,
         }
     }
 }
@@ -73,61 +429,116 @@ impl PreviewFile {
     pub fn new() -> Self {
         Self {
             contents: String::new(),
+            truncated: None,
+            path: None,
+            search: None,
             focused: true,
             mode: Mode::default(),
             config: Rc::new(Config::default()),
             git_cmd: "git diff {}".to_owned(),
             state: ParagraphState::default().into(),
             scrolls: VecDeque::new().into(),
+            height: Cell::default(),
+            wrap: false,
+            env: None,
         }
     }
 
     pub fn with_config(config: Rc<Config>) -> Self {
         Self {
-            config: Rc::clone(&config),
-            git_cmd: config
-                .preview
-                .git_pager
-                .as_ref()
-                .map_or("git diff {}".to_owned(), |cmd| {
-                    format!("git diff {{}} | {}", cmd)
-                }),
+            wrap: config.preview.wrap,
+            git_cmd: git_cmd(&config),
+            config,
             ..Self::new()
         }
     }
 
+    /// Use `config` from now on. Wrapping is only reset if the config changed it, so toggling it
+    /// sticks otherwise.
+    pub fn set_config(&mut self, config: Rc<Config>) {
+        if config.preview.wrap != self.config.preview.wrap {
+            self.wrap = config.preview.wrap;
+        }
+        self.git_cmd = git_cmd(&config);
+        if let Some(env) = &mut self.env {
+            env.set_config(Rc::clone(&config));
+        }
+        self.config = config;
+    }
+
+    /// Give the preview command the variables of `env`
+    #[must_use]
+    pub fn command_env(mut self, env: CommandEnv) -> Self {
+        self.env = Some(env);
+        self
+    }
+
     pub fn preview_file(&mut self, file: impl AsRef<Path>) -> Result<()> {
+        self.load_preview(file, self.config.preview.tail_lines)
+    }
+
+    /// Preview `file`, showing its last `tail_lines` lines too if it is truncated
+    fn load_preview(&mut self, file: impl AsRef<Path>, tail_lines: usize) -> Result<()> {
+        self.state.get_mut().reset();
+        self.truncated = None;
+        if self.path.as_deref() != Some(file.as_ref()) {
+            self.search = None;
+            self.path = Some(file.as_ref().to_path_buf());
+        }
+        if self.mode == Mode::Preview && ArchiveKind::from_path(&file).is_some() {
+            self.contents = archive::preview(&file)?;
+            return self.update_matches();
+        }
         if let Ok(meta) = fs::metadata(&file) {
-            const MB: u64 = 1_048_576;
-            if meta.len() > MB {
-                self.contents = "file is too large to preview".to_owned();
-                return Ok(());
+            if self.mode == Mode::Preview && meta.len() > self.config.preview.max_size {
+                self.contents.clear();
+                self.truncated = Some(Truncated::open(
+                    file,
+                    meta.len(),
+                    self.config.preview.max_size,
+                    self.config.preview.truncated_lines,
+                    tail_lines,
+                )?);
+                return self.update_matches();
             }
         }
         if self.config.preview.preview_cmd.is_empty() || self.git_cmd.is_empty() {
             bail!("should have command");
         }
-        self.state.get_mut().reset();
-        let replaced = {
-            #[cfg(target_os = "windows")]
-            let replacement = format!("\"{}\"", file.as_ref().display());
-            #[cfg(not(target_os = "windows"))]
-            let replacement = format!("'{}'", file.as_ref().display());
-
-            if self.mode == Mode::Preview {
-                self.config.preview.preview_cmd.replace("{}", &replacement)
-            } else {
-                self.git_cmd.replace("{}", &replacement)
-            }
+        let template = if self.mode == Mode::Preview {
+            &self.config.preview.preview_cmd
+        } else {
+            &self.git_cmd
         };
+        let replaced = self.env.as_ref().map_or_else(
+            || {
+                let context = interpolate::Context {
+                    selected: Some(file.as_ref()),
+                    root: Path::new(""),
+                    marks: &[],
+                };
+                interpolate::interpolate(template, &context)
+                    .with_context(|| format!("failed to fill in \"{template}\""))
+            },
+            |env| env.interpolate(template, Some(file.as_ref())),
+        )?;
 
         #[cfg(target_os = "windows")]
         let out = {
-            let out = Command::new("cmd.exe")
-                // See https://github.com/rust-lang/rust/issues/92939
-                .raw_arg(&format!("/C {replaced}"))
-                .output()
-                .with_context(|| format!("problem running preview command with {replaced}"))?;
+            let mut command = Command::new("cmd.exe");
+            // See https://github.com/rust-lang/rust/issues/92939
+            let mut arg = OsString::from("/C ");
+            arg.push(&replaced);
+            command.raw_arg(arg);
+            if let Some(env) = &self.env {
+                command.envs(env.vars(Some(file.as_ref())));
+            }
+            let out = command.output().with_context(|| {
+                format!(
+                    "problem running preview command with {}",
+                    replaced.to_string_lossy()
+                )
+            })?;
             String::from_utf8_lossy(&out.stdout).to_string()
         };
         #[cfg(not(target_os = "windows"))]
@@ -135,14 +546,151 @@ impl PreviewFile {
             env::var("SHELL").unwrap_or("sh".to_owned()),
             "-c",
             &replaced
-        )
+        );
+        #[cfg(not(target_os = "windows"))]
+        let out = match &self.env {
+            Some(env) => env.apply(out, Some(file.as_ref())),
+            None => out,
+        }
         .unchecked()
         .stderr_to_stdout()
         .read()
-        .with_context(|| format!("problem running preview command with {replaced}"))?;
+        .with_context(|| {
+            format!(
+                "problem running preview command with {}",
+                replaced.to_string_lossy()
+            )
+        })?;
 
-        trace!("ran preview command: \"{replaced}\"");
+        trace!("ran preview command: {replaced:?}");
         self.contents = out;
+        self.update_matches()
+    }
+
+    /// Re-run the preview of the current file, keeping the scroll position. If the preview was
+    /// scrolled to the bottom, it stays there to follow the end of the file.
+    pub fn refresh(&mut self) -> Result<()> {
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+        let old_state = self.state.get_mut().clone();
+        let tail_lines = if old_state.at_bottom() {
+            // A truncated preview starts over from the top of the file, so load its end instead
+            self.config
+                .preview
+                .tail_lines
+                .max(self.config.preview.truncated_lines)
+        } else {
+            self.config.preview.tail_lines
+        };
+        self.load_preview(path, tail_lines)?;
+        let state = self.state.get_mut();
+        if old_state.at_bottom() {
+            state.scroll_bottom();
+        } else {
+            state.offset_top = old_state.offset_top;
+            state.offset_left = old_state.offset_left;
+        }
+        trace!("refreshed preview");
+        Ok(())
+    }
+
+    /// Search for `query` in the preview, jumping to the first match at or below the current
+    /// scroll position
+    pub fn search(&mut self, query: String) -> Result<()> {
+        self.search = Some(Search {
+            query,
+            ..Default::default()
+        });
+        self.update_matches()?;
+        let offset = usize::from(self.state.get_mut().offset_top);
+        if let Some(search) = self.search.as_mut() {
+            search.current = search
+                .matches
+                .iter()
+                .position(|m| m.line >= offset)
+                .unwrap_or(0);
+        }
+        self.scroll_to_match();
+        Ok(())
+    }
+
+    pub fn next_match(&mut self) {
+        if let Some(search) = self.search.as_mut() {
+            if search.matches.is_empty() {
+                return;
+            }
+            search.current = (search.current + 1) % search.matches.len();
+        }
+        self.scroll_to_match();
+    }
+
+    pub fn prev_match(&mut self) {
+        if let Some(search) = self.search.as_mut() {
+            if search.matches.is_empty() {
+                return;
+            }
+            search.current = search
+                .current
+                .checked_sub(1)
+                .unwrap_or(search.matches.len() - 1);
+        }
+        self.scroll_to_match();
+    }
+
+    fn scroll_to_match(&mut self) {
+        if let Some(m) = self
+            .search
+            .as_ref()
+            .and_then(|search| search.matches.get(search.current))
+        {
+            self.state.get_mut().scroll_to_line(m.line);
+        }
+    }
+
+    /// Recompute the matches of the current search, if there is one
+    fn update_matches(&mut self) -> Result<()> {
+        if self.search.is_none() {
+            return Ok(());
+        }
+        let text = self.text()?;
+        let search = self.search.as_mut().expect("checked at top of method");
+        let query = &search.query;
+        let matches = text
+            .lines
+            .iter()
+            .enumerate()
+            .flat_map(|(line, spans)| {
+                let content: String = spans.0.iter().map(|span| span.content.as_ref()).collect();
+                find_matches(&content, query)
+                    .into_iter()
+                    .map(move |range| Match { line, range })
+            })
+            .collect::<Vec<_>>();
+        search.matches = matches;
+        search.current = search.current.min(search.matches.len().saturating_sub(1));
+        Ok(())
+    }
+
+    fn text(&self) -> Result<Text<'static>> {
+        Ok(match &self.truncated {
+            Some(truncated) => truncated.to_text(self.config.preview.truncated_style.into())?,
+            None => self.contents.into_text()?,
+        })
+    }
+
+    /// Load more of a truncated preview if the user has scrolled close to the end of what has
+    /// been loaded so far
+    fn load_if_near_end(&mut self) -> Result<()> {
+        let Some(truncated) = self.truncated.as_mut() else {
+            return Ok(());
+        };
+        let offset = usize::from(self.state.get_mut().offset_top);
+        let height = usize::from(self.height.get());
+        if offset + height * 2 >= truncated.head_lines && !truncated.is_complete() {
+            truncated.load_more(self.config.preview.truncated_lines)?;
+            self.update_matches()?;
+        }
         Ok(())
     }
 
@@ -168,6 +716,18 @@ impl Component for PreviewFile {
     }
 
     fn handle_event(&mut self, ev: &ExternalEvent) -> Result<()> {
+        if let ExternalEvent::PartialRefresh(data) = ev {
+            // Editors often save by renaming a temporary file over the original
+            let previewed_modified = data.iter().any(|data| match data {
+                RefreshData::Modify(path) | RefreshData::Rename { to: path, .. } => {
+                    self.path.as_ref() == Some(path)
+                }
+                _ => false,
+            });
+            if previewed_modified {
+                self.refresh()?;
+            }
+        }
         if !self.focused {
             return Ok(());
         }
@@ -178,9 +738,28 @@ impl Component for PreviewFile {
                     switch! { key;
                         self.config.preview.down_key => self.state.get_mut().down_by(self.config.preview.scroll_amount),
                         self.config.preview.up_key => self.state.get_mut().up_by(self.config.preview.scroll_amount),
+                        self.config.preview.left_key => self.state.get_mut().left_by(self.config.preview.scroll_amount),
+                        self.config.preview.right_key => self.state.get_mut().right_by(self.config.preview.scroll_amount),
+                        self.config.preview.toggle_wrap => self.wrap = !self.wrap,
+                        self.config.preview.next_match => self.next_match(),
+                        self.config.preview.prev_match => self.prev_match(),
                     }
                 }
                 Event::Mouse(mouse) => match mouse.kind {
+                    // Shift turns the scroll wheel horizontal
+                    MouseEventKind::ScrollDown | MouseEventKind::ScrollUp
+                        if mouse.modifiers.contains(KeyModifiers::SHIFT) =>
+                    {
+                        self.scrolls.get_mut().push_back(Scroll {
+                            direction: if mouse.kind == MouseEventKind::ScrollDown {
+                                ScrollDirection::Right
+                            } else {
+                                ScrollDirection::Left
+                            },
+                            x: mouse.column,
+                            y: mouse.row,
+                        });
+                    }
                     MouseEventKind::ScrollDown => {
                         self.scrolls.get_mut().push_front(Scroll {
                             direction: ScrollDirection::Down,
@@ -200,6 +779,7 @@ impl Component for PreviewFile {
                 _ => {}
             }
         }
+        self.load_if_near_end()?;
 
         Ok(())
     }
@@ -207,16 +787,48 @@ impl Component for PreviewFile {
 
 impl Drawable for PreviewFile {
     fn draw<B: Backend>(&self, f: &mut Frame<B>, area: Rect) -> Result<()> {
-        let text = self.contents.into_text()?;
+        let mut text = self.text()?;
+        let title = match &self.search {
+            Some(search) if search.matches.is_empty() => {
+                format!("Preview (no matches for \"{}\")", search.query)
+            }
+            Some(search) => format!(
+                "Preview (match {} of {})",
+                search.current + 1,
+                search.matches.len()
+            ),
+            None => "Preview".to_owned(),
+        };
+        if let Some(search) = &self.search {
+            let groups = search.matches.iter().enumerate().group_by(|(_, m)| m.line);
+            for (line, matches) in &groups {
+                let ranges = matches
+                    .map(|(i, m)| {
+                        let style = if i == search.current {
+                            self.config.preview.current_match_style
+                        } else {
+                            self.config.preview.match_style
+                        };
+                        (m.range.clone(), style.into())
+                    })
+                    .collect::<Vec<_>>();
+                let spans = std::mem::take(&mut text.lines[line]);
+                text.lines[line] = highlight(spans, &ranges);
+            }
+        }
+        self.height.set(area.height);
         let paragraph = ScrollParagraph::new(text)
             .block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .title("Preview")
+                    .title(title)
                     .border_style(self.config.preview.border_color.into()),
             )
             .bar_style(self.config.preview.scroll_bar_color.into())
-            .unreached_bar_style(self.config.preview.unreached_bar_color.into());
+            .unreached_bar_style(self.config.preview.unreached_bar_color.into())
+            .line_numbers(self.config.preview.line_numbers)
+            .line_number_style(self.config.preview.line_number_style.into())
+            .wrap(self.wrap);
         let mut state = self.state.take();
         let mut scrolls = self.scrolls.take();
         while let Some(Scroll { direction, x, y }) = scrolls.pop_front() {
@@ -225,6 +837,8 @@ impl Drawable for PreviewFile {
                 match direction {
                     ScrollDirection::Up => state.up(),
                     ScrollDirection::Down => state.down(),
+                    ScrollDirection::Left => state.left(),
+                    ScrollDirection::Right => state.right(),
                 }
             }
         }
@@ -237,11 +851,13 @@ impl Drawable for PreviewFile {
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use super::{super::testing::*, *};
     use assert_fs::{prelude::*, TempDir};
     use collect_all::collect;
-    use crossterm::event::{KeyModifiers, MouseEvent};
+    use crossterm::event::MouseEvent;
+    use smallvec::smallvec;
     use test_log::test;
+    use tui::{backend::TestBackend, Terminal};
 
     #[test]
     fn can_get_file_contents() {
@@ -304,6 +920,22 @@ mod tests {
         assert_eq!("should be previewed", previewer.contents);
     }
 
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn preview_command_fills_in_placeholders() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        let child = temp_dir.child("it's {name}.txt");
+        child.touch().unwrap();
+
+        let mut config = Config::default();
+        config.preview.preview_cmd = "echo {name} {ext}".to_owned();
+        let mut previewer = PreviewFile::with_config(Rc::new(config));
+        previewer
+            .preview_file(child.path())
+            .expect("preview should work");
+        assert_eq!("it's {name}.txt txt", previewer.contents);
+    }
+
     #[test]
     fn mouse_inputs_are_stored_in_queue() {
         let mut previewer = PreviewFile::default();
@@ -340,4 +972,394 @@ mod tests {
             previewer.scrolls.take()
         );
     }
+
+    fn large_file_config() -> Config {
+        let mut config = Config::default();
+        config.preview.max_size = 10;
+        config.preview.truncated_lines = 3;
+        config
+    }
+
+    #[test]
+    fn large_files_are_truncated() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        let child = temp_dir.child("large.txt");
+        child.write_str("1\n2\n3\n4\n5\n6\n7\n8\n").unwrap();
+
+        let mut previewer = PreviewFile::with_config(Rc::new(large_file_config()));
+        previewer
+            .preview_file(child.path())
+            .expect("preview should work");
+        let truncated = previewer.truncated.as_ref().expect("should be truncated");
+        assert_eq!("1\n2\n3\n", truncated.head);
+        assert_eq!(3, truncated.head_lines);
+        assert!(!truncated.is_complete());
+        assert_eq!(
+            "preview truncated, showing first 3 lines",
+            truncated.banner()
+        );
+    }
+
+    #[test]
+    fn truncated_preview_loads_more_when_scrolled() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        let child = temp_dir.child("large.txt");
+        child.write_str("1\n2\n3\n4\n5\n6\n7\n8\n").unwrap();
+
+        let mut previewer = PreviewFile::with_config(Rc::new(large_file_config()));
+        previewer
+            .preview_file(child.path())
+            .expect("preview should work");
+        previewer.height.set(1);
+        previewer.state.get_mut().down_by(2);
+        previewer
+            .handle_event(&ExternalEvent::RefreshFiletree)
+            .expect("should be able to load more");
+        let truncated = previewer.truncated.as_ref().expect("should be truncated");
+        assert_eq!("1\n2\n3\n4\n5\n6\n", truncated.head);
+
+        previewer.state.get_mut().down_by(3);
+        previewer
+            .handle_event(&ExternalEvent::RefreshFiletree)
+            .expect("should be able to load more");
+        let truncated = previewer.truncated.as_ref().expect("should be truncated");
+        assert_eq!("1\n2\n3\n4\n5\n6\n7\n8\n", truncated.head);
+        assert!(truncated.is_complete());
+    }
+
+    #[test]
+    fn truncated_preview_can_show_tail() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        let child = temp_dir.child("large.txt");
+        child.write_str("1\n2\n3\n4\n5\n6\n7\n8").unwrap();
+
+        let mut config = large_file_config();
+        config.preview.tail_lines = 2;
+        let mut previewer = PreviewFile::with_config(Rc::new(config));
+        previewer
+            .preview_file(child.path())
+            .expect("preview should work");
+        let truncated = previewer.truncated.as_mut().expect("should be truncated");
+        assert_eq!("7\n8", truncated.tail);
+        assert_eq!(
+            "preview truncated, showing first 3 lines and last 2 lines",
+            truncated.banner()
+        );
+
+        // Loading should stop where the tail starts
+        truncated
+            .load_more(10)
+            .expect("should be able to load more");
+        assert_eq!("1\n2\n3\n4\n5\n6\n", truncated.head);
+        assert!(truncated.is_complete());
+    }
+
+    #[test]
+    fn truncated_preview_cuts_long_lines() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        let child = temp_dir.child("large.txt");
+        let long = "a".repeat(5 * MAX_LINE_LEN);
+        child.write_str(&format!("{long}\nb\n{long}")).unwrap();
+
+        let mut config = large_file_config();
+        config.preview.max_size = 2 * MAX_LINE_LEN as u64;
+        config.preview.tail_lines = 1;
+        let mut previewer = PreviewFile::with_config(Rc::new(config));
+        previewer
+            .preview_file(child.path())
+            .expect("preview should work");
+        let truncated = previewer.truncated.as_mut().expect("should be truncated");
+        let cut = format!("{}…\n", &long[..MAX_LINE_LEN]);
+        assert_eq!(cut, truncated.head);
+        assert_eq!(2 * MAX_LINE_LEN as u64, truncated.offset);
+        // The last line does not fit in the bytes to look at, so there is no tail
+        assert_eq!("", truncated.tail);
+
+        // Skipping the rest of the cut line takes more than one read
+        truncated.load_more(1).expect("should be able to load more");
+        assert_eq!(cut, truncated.head);
+        truncated.load_more(1).expect("should be able to load more");
+        assert_eq!(format!("{cut}b\n"), truncated.head);
+        truncated.load_more(1).expect("should be able to load more");
+        assert_eq!(format!("{cut}b\n{cut}"), truncated.head);
+    }
+
+    #[test]
+    fn tail_only_reads_up_to_max_bytes() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        let child = temp_dir.child("large.txt");
+        child.write_str("1\n2\n3\n4\n5\n6\n7\n8\n").unwrap();
+
+        assert_eq!(
+            (10, "6\n7\n8\n".to_owned()),
+            read_tail(child.path(), 16, 7, 5).unwrap()
+        );
+        assert_eq!(
+            (0, "1\n2\n3\n4\n5\n6\n7\n8\n".to_owned()),
+            read_tail(child.path(), 16, 100, 10).unwrap()
+        );
+        assert_eq!(
+            (12, "7\n8\n".to_owned()),
+            read_tail(child.path(), 16, 100, 2).unwrap()
+        );
+    }
+
+    #[test]
+    fn diff_mode_is_not_truncated() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        let child = temp_dir.child("large.txt");
+        child.write_str("1\n2\n3\n4\n5\n6\n7\n8\n").unwrap();
+
+        let mut previewer = PreviewFile::with_config(Rc::new(large_file_config()));
+        previewer.toggle_mode();
+        previewer
+            .preview_file(child.path())
+            .expect("preview should work");
+        assert!(previewer.truncated.is_none());
+    }
+
+    #[test]
+    fn finds_matches_with_smart_case() {
+        assert_eq!(vec![0..3, 8..11], find_matches("foo bar FOO", "foo"));
+        assert_eq!(vec![8..11], find_matches("foo bar FOO", "FOO"));
+        assert_eq!(vec![0..2, 2..4], find_matches("aaaaa", "aa"));
+        assert!(find_matches("foo", "foobar").is_empty());
+    }
+
+    #[test]
+    fn highlights_across_spans() {
+        let spans = Spans(vec![
+            Span::styled("hello ", Style::default()),
+            Span::styled("world", Style::default()),
+        ]);
+        let style = Style::default().bg(tui::style::Color::Yellow);
+        let highlighted = highlight(spans, &[(4..8, style)]);
+        assert_eq!(
+            Spans(vec![
+                Span::styled("hell", Style::default()),
+                Span::styled("o ", style),
+                Span::styled("wo", style),
+                Span::styled("rld", Style::default()),
+            ]),
+            highlighted
+        );
+    }
+
+    #[test]
+    fn can_search_and_cycle_through_matches() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        let child = temp_dir.child("test.txt");
+        child
+            .write_str("match\nnothing\n\u{1b}[31mmatch\u{1b}[0m and match\n")
+            .unwrap();
+
+        let mut previewer = PreviewFile::default();
+        previewer
+            .preview_file(child.path())
+            .expect("preview should work");
+        previewer
+            .search("match".to_owned())
+            .expect("search should work");
+        let search = previewer.search.as_ref().expect("should be searching");
+        assert_eq!(
+            vec![
+                Match {
+                    line: 0,
+                    range: 0..5
+                },
+                Match {
+                    line: 2,
+                    range: 0..5
+                },
+                Match {
+                    line: 2,
+                    range: 10..15
+                },
+            ],
+            search.matches
+        );
+
+        previewer.next_match();
+        assert_eq!(2, previewer.state.get_mut().offset_top);
+        previewer.next_match();
+        previewer.next_match();
+        assert_eq!(0, previewer.search.as_ref().unwrap().current);
+        previewer.prev_match();
+        assert_eq!(2, previewer.search.as_ref().unwrap().current);
+    }
+
+    #[test]
+    fn search_is_cleared_on_new_file() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        temp_dir.child("one.txt").write_str("test").unwrap();
+        temp_dir.child("two.txt").write_str("test").unwrap();
+
+        let mut previewer = PreviewFile::default();
+        previewer
+            .preview_file(temp_dir.join("one.txt"))
+            .expect("preview should work");
+        previewer
+            .search("test".to_owned())
+            .expect("search should work");
+        previewer
+            .preview_file(temp_dir.join("one.txt"))
+            .expect("preview should work");
+        assert!(previewer.search.is_some());
+        previewer
+            .preview_file(temp_dir.join("two.txt"))
+            .expect("preview should work");
+        assert!(previewer.search.is_none());
+    }
+
+    #[test]
+    fn shift_scrolling_is_horizontal() {
+        let mut previewer = PreviewFile::default();
+        let events = [
+            ExternalEvent::Crossterm(Event::Mouse(MouseEvent {
+                kind: MouseEventKind::ScrollDown,
+                column: 1,
+                row: 1,
+                modifiers: KeyModifiers::SHIFT,
+            })),
+            ExternalEvent::Crossterm(Event::Mouse(MouseEvent {
+                kind: MouseEventKind::ScrollUp,
+                column: 1,
+                row: 1,
+                modifiers: KeyModifiers::SHIFT,
+            })),
+        ];
+        for event in events {
+            assert!(previewer.handle_event(&event).is_ok());
+        }
+        assert_eq!(
+            vec![ScrollDirection::Right, ScrollDirection::Left],
+            previewer
+                .scrolls
+                .take()
+                .into_iter()
+                .map(|scroll| scroll.direction)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn can_toggle_wrap() {
+        let mut previewer = PreviewFile::default();
+        previewer
+            .handle_event(&input_event!(KeyCode::Char('w')))
+            .expect("should handle event");
+        assert!(previewer.wrap);
+        previewer
+            .handle_event(&input_event!(KeyCode::Char('w')))
+            .expect("should handle event");
+        assert!(!previewer.wrap);
+    }
+
+    #[test]
+    fn archives_are_listed() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        let child = temp_dir.child("test.tar");
+        let mut builder = tar::Builder::new(File::create(child.path()).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mtime(0);
+        builder
+            .append_data(&mut header, "hello.txt", &b"hello"[..])
+            .unwrap();
+        builder.finish().unwrap();
+
+        let mut previewer = PreviewFile::default();
+        previewer
+            .preview_file(child.path())
+            .expect("preview should work");
+        assert_eq!(
+            "1 entries, 5 bytes\n         5  1970-01-01 00:00  hello.txt\n",
+            previewer.contents
+        );
+    }
+
+    fn draw_previewer(previewer: &PreviewFile) {
+        let mut terminal = Terminal::new(TestBackend::new(20, 5)).unwrap();
+        terminal
+            .draw(|f| previewer.draw(f, f.size()).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn refreshes_when_previewed_file_is_modified() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        let child = temp_dir.child("test.txt");
+        child.write_str("1\n2\n3\n4\n5\n6\n").unwrap();
+
+        let mut previewer = PreviewFile::default();
+        previewer
+            .preview_file(child.path())
+            .expect("preview should work");
+        draw_previewer(&previewer);
+        previewer.state.get_mut().down();
+        draw_previewer(&previewer);
+
+        child.write_str("1\n2\n3\n4\n5\n6\n7\n").unwrap();
+        previewer
+            .handle_event(&ExternalEvent::PartialRefresh(smallvec![
+                RefreshData::Modify(temp_dir.join("other.txt"))
+            ]))
+            .expect("should handle refresh");
+        assert_eq!("1\n2\n3\n4\n5\n6", previewer.contents);
+        previewer
+            .handle_event(&ExternalEvent::PartialRefresh(smallvec![
+                RefreshData::Modify(child.to_path_buf())
+            ]))
+            .expect("should handle refresh");
+        assert_eq!("1\n2\n3\n4\n5\n6\n7", previewer.contents);
+        assert_eq!(1, previewer.state.get_mut().offset_top);
+    }
+
+    #[test]
+    fn refresh_follows_end_of_truncated_file() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        let child = temp_dir.child("large.txt");
+        child.write_str("1\n2\n3\n4\n5\n6\n7\n8\n").unwrap();
+
+        let mut previewer = PreviewFile::with_config(Rc::new(large_file_config()));
+        previewer
+            .preview_file(child.path())
+            .expect("preview should work");
+        assert_eq!("", previewer.truncated.as_ref().unwrap().tail);
+        previewer.state.get_mut().scroll_bottom();
+        draw_previewer(&previewer);
+
+        child.write_str("1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n").unwrap();
+        previewer.refresh().expect("should refresh");
+        let truncated = previewer.truncated.as_ref().expect("should be truncated");
+        assert_eq!("1\n2\n3\n", truncated.head);
+        assert_eq!("8\n9\n10\n", truncated.tail);
+        assert_eq!(u16::MAX, previewer.state.get_mut().offset_top);
+
+        // Away from the bottom, only the first lines are loaded again
+        previewer.state.get_mut().reset();
+        draw_previewer(&previewer);
+        previewer.refresh().expect("should refresh");
+        assert_eq!("", previewer.truncated.as_ref().unwrap().tail);
+    }
+
+    #[test]
+    fn refresh_follows_tail_when_at_bottom() {
+        let temp_dir = TempDir::new().expect("should be able to make temp dir");
+        let child = temp_dir.child("test.txt");
+        child.write_str("1\n2\n3\n4\n5\n6\n").unwrap();
+
+        let mut previewer = PreviewFile::default();
+        previewer
+            .preview_file(child.path())
+            .expect("preview should work");
+        previewer.state.get_mut().scroll_bottom();
+        draw_previewer(&previewer);
+        let bottom = previewer.state.get_mut().offset_top;
+
+        child.write_str("1\n2\n3\n4\n5\n6\n7\n8\n").unwrap();
+        previewer.refresh().expect("should refresh");
+        draw_previewer(&previewer);
+        assert_eq!(bottom + 2, previewer.state.get_mut().offset_top);
+    }
 }
@@ -0,0 +1,260 @@
+use crate::{
+    app::{
+        component::{Component, Drawable},
+        InputOperation,
+    },
+    config::Config,
+    external_event::ExternalEvent,
+    queue::{AppEvent, Queue},
+    ui,
+    watch::Watches,
+};
+use anyhow::Result;
+use crossterm::event::Event;
+use easy_switch::switch;
+use itertools::Itertools;
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+use tui::{
+    backend::Backend,
+    layout::Rect,
+    widgets::{Block, Borders, Clear, List, ListItem, ListState},
+    Frame,
+};
+
+pub struct WatchesPopup {
+    // Shared with the app, which runs the watches when files change
+    watches: Rc<RefCell<Watches>>,
+    queue: Queue,
+    open: bool,
+    config: Rc<Config>,
+    state: Cell<ListState>,
+}
+
+impl Default for WatchesPopup {
+    fn default() -> Self {
+        Self::new(Default::default(), Queue::new(), Rc::new(Config::default()))
+    }
+}
+
+impl WatchesPopup {
+    pub fn new(watches: Rc<RefCell<Watches>>, queue: Queue, config: Rc<Config>) -> Self {
+        let mut state = ListState::default();
+        state.select(Some(0));
+        Self {
+            watches,
+            queue,
+            config,
+            state: state.into(),
+            open: false,
+        }
+    }
+
+    /// Use `config`, keeping the selection in range of the watches it might have replaced
+    pub fn set_config(&mut self, config: Rc<Config>) {
+        self.config = config;
+        if self.selected() >= self.watches.borrow().len() {
+            self.select_last();
+        }
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+
+    pub fn close(&mut self) {
+        self.state.get_mut().select(Some(0));
+        self.open = false;
+    }
+
+    pub fn toggle_selected(&mut self) {
+        if self.selected() < self.watches.borrow().len() {
+            self.watches.borrow_mut().toggle(self.selected());
+        }
+    }
+
+    pub fn delete_selected(&mut self) {
+        let selected = self.selected();
+        if selected >= self.watches.borrow().len() {
+            return;
+        }
+        self.watches.borrow_mut().remove(selected);
+        if selected >= self.watches.borrow().len() {
+            self.select_last();
+        }
+    }
+
+    fn selected(&self) -> usize {
+        let state = self.state.take();
+        let selected = state.selected().expect("should have something selected");
+        self.state.set(state);
+        selected
+    }
+
+    fn select_next(&mut self) {
+        let current = self.selected();
+        if current + 1 >= self.watches.borrow().len() {
+            return;
+        }
+        self.state.get_mut().select(Some(current + 1));
+    }
+
+    fn select_prev(&mut self) {
+        let current = self.selected();
+        if current == 0 {
+            return;
+        }
+        self.state.get_mut().select(Some(current - 1));
+    }
+
+    fn select_first(&mut self) {
+        self.state.get_mut().select(Some(0));
+    }
+
+    fn select_last(&mut self) {
+        let len = self.watches.borrow().len();
+        self.state.get_mut().select(Some(len.saturating_sub(1)));
+    }
+}
+
+impl Drawable for WatchesPopup {
+    fn draw<B: Backend>(&self, f: &mut Frame<B>, area: Rect) -> Result<()> {
+        if !self.visible() {
+            return Ok(());
+        }
+
+        let watches = self.watches.borrow();
+        let items = watches
+            .watches()
+            .iter()
+            .map(|watch| {
+                let (state, style) = if watch.enabled {
+                    ("on ", self.config.watch.enabled_style)
+                } else {
+                    ("off", self.config.watch.disabled_style)
+                };
+                ListItem::new(format!("[{state}] {}: {}", watch.glob, watch.command))
+                    .style(style.into())
+            })
+            .collect_vec();
+        let list = List::new(items)
+            .highlight_style(self.config.selected.into())
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(self.config.popup_border_style.into())
+                    .title("Watches"),
+            );
+        let area = ui::centered_rect_absolute(60, 15, area);
+        f.render_widget(Clear, area);
+        let mut state = self.state.take();
+        f.render_stateful_widget(list, area, &mut state);
+        self.state.set(state);
+
+        Ok(())
+    }
+}
+
+impl Component for WatchesPopup {
+    fn visible(&self) -> bool {
+        self.open
+    }
+
+    fn handle_event(&mut self, ev: &ExternalEvent) -> Result<()> {
+        if !self.visible() {
+            return Ok(());
+        }
+
+        if let ExternalEvent::Crossterm(Event::Key(key)) = ev {
+            switch! { key;
+                self.config.quit => self.close(),
+                self.config.down => self.select_next(),
+                self.config.up => self.select_prev(),
+                self.config.all_up => self.select_first(),
+                self.config.all_down => self.select_last(),
+                self.config.watch.toggle => self.toggle_selected(),
+                self.config.watch.delete => self.delete_selected(),
+                self.config.watch.add => {
+                    self.queue.add(AppEvent::OpenInput(InputOperation::AddWatch));
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app::components::testing::*, watch::Watch};
+    use test_log::test;
+
+    fn test_popup() -> WatchesPopup {
+        let mut watches = Watches::default();
+        watches.add(Watch::new("**/*.rs".to_owned(), "cargo check".to_owned()).unwrap());
+        watches.add(Watch::new("*.md".to_owned(), "mdbook build".to_owned()).unwrap());
+        let mut popup = WatchesPopup::new(
+            Rc::new(RefCell::new(watches)),
+            Queue::new(),
+            Rc::new(Config::default()),
+        );
+        popup.open();
+        popup
+    }
+
+    #[test]
+    fn can_toggle_watches() {
+        let mut popup = test_popup();
+        popup
+            .handle_event(&input_event!(KeyCode::Char('t')))
+            .unwrap();
+        assert!(!popup.watches.borrow().watches()[0].enabled);
+        popup
+            .handle_event(&input_event!(KeyCode::Char('t')))
+            .unwrap();
+        assert!(popup.watches.borrow().watches()[0].enabled);
+    }
+
+    #[test]
+    fn deleting_last_watch_selects_new_last() {
+        let mut popup = test_popup();
+        popup.select_last();
+        popup
+            .handle_event(&input_event!(KeyCode::Char('d')))
+            .unwrap();
+        assert_eq!(1, popup.watches.borrow().len());
+        assert_eq!(0, popup.selected());
+    }
+
+    #[test]
+    fn add_key_asks_for_watch() {
+        let mut popup = test_popup();
+        popup
+            .handle_event(&input_event!(KeyCode::Char('a')))
+            .unwrap();
+        assert!(popup
+            .queue
+            .contains(&AppEvent::OpenInput(InputOperation::AddWatch)));
+    }
+
+    #[test]
+    fn does_not_panic_with_zero_watches() {
+        let mut popup = test_popup();
+        popup.watches = Default::default();
+        let events = input_events!(
+            KeyCode::Char('j'),
+            KeyCode::Char('k'),
+            KeyCode::Char('g'),
+            KeyCode::Char('G'); KeyModifiers::SHIFT,
+            KeyCode::Char('t'),
+            KeyCode::Char('d')
+        );
+        for event in events {
+            assert!(popup.handle_event(&event).is_ok());
+        }
+        assert_eq!(0, popup.selected());
+    }
+}
@@ -4,15 +4,24 @@ mod components;
 use self::component::{Component, Drawable};
 pub use self::components::*;
 use crate::{
-    config::{Config, Key},
-    external_event::{ExternalEvent, RefreshData},
+    archive,
+    command_env::{self, CommandEnv},
+    config::{Config, Key, Task},
+    external_event::{ExternalEvent, JobId, JobStatus, Jobs, RefreshData},
+    history::History,
     marks::Marks,
-    queue::{AppEvent, Queue, TmuxOpts},
+    multiplexer,
+    queue::{AppEvent, Queue},
+    tasks::{self, TaskChain, TaskRun},
+    watch::{Watch, Watches},
 };
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use crossbeam_channel::Sender;
 use crossterm::event::Event;
 use duct::{cmd, Expression};
 use easy_switch::switch;
+use either::Either;
+use itertools::Itertools;
 use log::{error, info, warn};
 #[cfg(target_os = "windows")]
 use std::os::windows::process::CommandExt;
@@ -20,12 +29,17 @@ use std::os::windows::process::CommandExt;
 use std::process::Command;
 use std::{
     cell::RefCell,
+    collections::HashMap,
     fs::{self, File},
     iter,
     path::{Path, PathBuf},
     rc::Rc,
+    time::Duration,
+};
+use std::{
+    env,
+    ffi::{OsStr, OsString},
 };
-use std::{env, ffi::OsStr};
 use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
@@ -36,9 +50,7 @@ use tui::{
 #[derive(Debug)]
 pub enum TerminalEvent {
     OpenFile(PathBuf),
-    RunCommandThreaded(Expression),
     RunCommand(Expression),
-    StopAllCommands,
 }
 
 pub struct App {
@@ -52,6 +64,17 @@ pub struct App {
     text_popup: Popup,
     file_cmd_popup: FileCmdPopup,
     marks_popup: MarksPopup,
+    jobs: Jobs,
+    jobs_popup: JobsPopup,
+    watches: Rc<RefCell<Watches>>,
+    watches_popup: WatchesPopup,
+    /// Tasks from the task picker that still have to run
+    tasks: Option<TaskChain>,
+    /// Tasks found in the build files of the project, like Makefile targets
+    discovered_tasks: Vec<(String, Task)>,
+    /// Command being run in the foreground by main.rs
+    foreground_command: Option<String>,
+    command_env: CommandEnv,
     fuzzy_matcher: FuzzyMatcher,
     config: Rc<Config>,
     logger: EventLogger,
@@ -72,16 +95,41 @@ impl App {
             Rc::clone(&marks),
         )?;
         tree.open_path(cwd)?;
+        let jobs = Jobs::new(config.jobs.max_concurrent)
+            .max_output(config.jobs.max_output)
+            .max_history(config.jobs.max_history);
+        let discovered_tasks = tasks::discover(&path);
+        let watches = Rc::new(RefCell::new(
+            Watches::from_config(&config.watch.commands).context("error setting up watches")?,
+        ));
+        let command_env = CommandEnv::new(path.clone(), Rc::clone(&marks), Rc::clone(&config));
         Ok(App {
             path: path.clone(),
             tree,
             should_quit: false,
             pending: PendingPopup::new(queue.clone(), Rc::clone(&config)),
-            input_box: InputBox::new(queue.clone()),
-            previewer: PreviewFile::with_config(Rc::clone(&config)),
+            input_box: InputBox::with_history(
+                queue.clone(),
+                Rc::clone(&config),
+                History::from_history_file(&path).context("error getting command history")?,
+            ),
+            previewer: PreviewFile::with_config(Rc::clone(&config))
+                .command_env(command_env.clone()),
             text_popup: Popup::new(Rc::clone(&config)),
             config: Rc::clone(&config),
             marks_popup: MarksPopup::new(marks, queue.clone(), Rc::clone(&config), path),
+            tasks: None,
+            discovered_tasks,
+            foreground_command: None,
+            command_env,
+            jobs_popup: JobsPopup::new(jobs.clone(), queue.clone(), Rc::clone(&config)),
+            watches_popup: WatchesPopup::new(
+                Rc::clone(&watches),
+                queue.clone(),
+                Rc::clone(&config),
+            ),
+            watches,
+            jobs,
             file_cmd_popup: FileCmdPopup::new(queue.clone(), Rc::clone(&config)),
             fuzzy_matcher: FuzzyMatcher::new_with_config(queue.clone(), Rc::clone(&config)),
             logger: EventLogger::new(Rc::clone(&config)),
@@ -121,6 +169,13 @@ impl App {
                     info!("created file \"{}\"", path.display());
                     self.tree.partial_refresh(&RefreshData::Add(path))?;
                 }
+                AppEvent::ExtractArchive(path) => {
+                    let created = archive::extract(&path)?;
+                    info!("extracted archive \"{}\"", path.display());
+                    for path in created {
+                        self.tree.partial_refresh(&RefreshData::Add(path))?;
+                    }
+                }
                 AppEvent::NewDir(path) => {
                     fs::create_dir(&path)
                         .context("failed to create dir while resolving event queue")?;
@@ -150,72 +205,51 @@ impl App {
                     .preview_file(path)
                     .context("failed to preview while resolving event queue")?,
                 AppEvent::TogglePreviewMode => self.previewer.toggle_mode(),
+                AppEvent::SearchPreview(query) => self.previewer.search(query)?,
                 AppEvent::RunCommand(cmd) => {
-                    // Strip !!, and if it exists, run in foreground, not background
-                    let (threaded, cmd) = cmd
-                        .strip_prefix("!!")
-                        .map_or((true, cmd.as_str()), |s| (false, s));
-
-                    let cmd = if let Some(cmd_start) = &self.config.exec_cmd {
-                        duct::cmd(
-                            cmd_start.get(0).context(
-                                "problem executing user shell command: no first argument",
-                            )?,
-                            cmd_start
-                                .iter()
-                                .skip(1)
-                                .map(|s| s.as_os_str())
-                                .chain(iter::once(OsStr::new(cmd))),
-                        )
-                    } else {
-                        #[cfg(not(target_os = "windows"))]
-                        let cmd = cmd!(
-                            env::var("SHELL").unwrap_or_else(|_| "sh".to_owned()),
-                            "-c",
-                            cmd
-                        );
-                        #[cfg(target_os = "windows")]
-                        let cmd = cmd!("cmd.exe", "/C", cmd);
-                        cmd
-                    };
-
-                    if threaded {
-                        self.text_popup.preset = Preset::RunningCommand;
-                        return Ok(Some(TerminalEvent::RunCommandThreaded(
-                            cmd.stdout_capture()
-                                .stderr_capture()
-                                .stderr_to_stdout()
-                                .stdin_null()
-                                .unchecked(),
-                        )));
-                    } else {
-                        return Ok(Some(TerminalEvent::RunCommand(cmd.unchecked())));
-                    };
+                    if let Either::Right(expr) = self.run_command(&cmd, None)? {
+                        return Ok(Some(TerminalEvent::RunCommand(expr)));
+                    }
                 }
-                AppEvent::RunCommandWithTmux(cmd, opts) => {
-                    if env::var("TMUX").is_err() {
-                        error!("not in tmux session");
+                AppEvent::RunTask(name) => {
+                    if self.tasks.is_some() {
+                        warn!("tasks are already running");
                         continue;
                     }
-
-                    let cmd_expr = match opts {
-                        TmuxOpts::NewWindow => cmd!("command", "tmux", "new-window", cmd),
-                        TmuxOpts::VerticalSplit => {
-                            cmd!("command", "tmux", "split-window", "-h", cmd)
-                        }
-                        TmuxOpts::HorizontalSplit => {
-                            cmd!("command", "tmux", "split-window", "-v", cmd)
-                        }
-                        TmuxOpts::FloatingWindow => {
-                            cmd!("command", "tmux", "display-popup", "-E", cmd)
-                        }
-                    };
-                    let out = cmd_expr.stderr_to_stdout().unchecked().read()?;
+                    let order = tasks::run_order(&self.all_tasks(), &name)?;
+                    self.tasks = Some(TaskChain::new(order));
+                    self.queue.add(AppEvent::NextTask);
+                }
+                AppEvent::NextTask => {
+                    if let Some(expr) = self.run_next_task()? {
+                        return Ok(Some(TerminalEvent::RunCommand(expr)));
+                    }
+                }
+                AppEvent::RepeatCommand => {
+                    if let Some(cmd) = self.input_box.last_command() {
+                        self.queue.add(AppEvent::RunCommand(cmd.to_owned()));
+                    } else {
+                        warn!("no command to repeat");
+                    }
+                }
+                AppEvent::RunCommandInMultiplexer(cmd, opts) => {
+                    let template = multiplexer::template(&self.config.multiplexer, opts)?;
+                    let cmd = self.interpolate(&cmd)?;
+                    let selected = self.tree.get_selected().map(|item| item.path().to_owned());
+                    let vars = self.command_env.vars(selected.as_deref());
+                    let cmd = multiplexer::with_env(&cmd, &vars);
+                    let cmd_expr = self.shell(&multiplexer::fill(&template, &cmd))?;
+                    let out = self
+                        .command_env
+                        .apply(cmd_expr, selected.as_deref())
+                        .stderr_to_stdout()
+                        .unchecked()
+                        .read()?;
                     if out.is_empty() {
                         continue;
                     }
 
-                    info!("opening tmux window");
+                    info!("opening {opts}");
                     warn!("{out}");
                 }
                 AppEvent::SearchFiles(files) => {
@@ -247,9 +281,27 @@ impl App {
                 }
                 AppEvent::OpenFuzzy(items, operation) => self.fuzzy_matcher.start(items, operation),
                 AppEvent::FilterFor(items) => self.tree.filter_include(&items)?,
+                AppEvent::SaveOutput(id, path) => {
+                    let path = if path.is_relative() {
+                        self.path().join(path)
+                    } else {
+                        path
+                    };
+                    fs::write(&path, self.jobs.output(id).text())
+                        .context("failed to save job output")?;
+                    info!("saved output of job {id} to \"{}\"", path.display());
+                }
+                AppEvent::AddWatch(text) => {
+                    let Some((glob, command)) = text.trim().split_once(char::is_whitespace) else {
+                        bail!("watch should be a glob followed by a command");
+                    };
+                    let watch = Watch::new(glob.to_owned(), command.trim().to_owned())?;
+                    info!("watching \"{glob}\"");
+                    self.watches.borrow_mut().add(watch);
+                }
                 AppEvent::StopAllCommands => {
-                    self.text_popup.preset = Preset::Nothing;
-                    return Ok(Some(TerminalEvent::StopAllCommands));
+                    self.tasks = None;
+                    self.jobs.kill_all()?;
                 }
             }
         }
@@ -263,6 +315,8 @@ impl App {
             || self.text_popup.visible()
             || self.file_cmd_popup.visible()
             || self.marks_popup.visible()
+            || self.jobs_popup.visible()
+            || self.watches_popup.visible()
             || self.fuzzy_matcher.visible();
         // Do not give the Filetree or previewer focus if there are any popups open
         self.tree.focus(!popup_open);
@@ -278,6 +332,8 @@ impl App {
         self.text_popup.handle_event(ev)?;
         self.file_cmd_popup.handle_event(ev)?;
         self.marks_popup.handle_event(ev)?;
+        self.jobs_popup.handle_event(ev)?;
+        self.watches_popup.handle_event(ev)?;
 
         match ev {
             ExternalEvent::Crossterm(Event::Key(key)) => {
@@ -288,14 +344,47 @@ impl App {
                     self.config.quit => self.should_quit = true,
                     self.config.help => self.text_popup.preset = Preset::Help,
                     self.config.marks.open => self.marks_popup.open(),
+                    self.config.jobs.open => self.jobs_popup.open(),
+                    self.config.watch.open => self.watches_popup.open(),
+                    self.config.open_tasks => self.open_tasks(),
+                    self.config.preview.search => self.input_box.operation = InputOperation::PreviewSearch,
                     Key::esc(), self.config.esc_to_close => self.should_quit = true,
                     self.config.kill_processes => self.queue.add(AppEvent::StopAllCommands),
                 };
             }
-            ExternalEvent::CommandOutput(out) => {
-                self.text_popup.preset = Preset::Nothing;
-                info!("output:");
-                info!("{}", if out.is_empty() { " " } else { out });
+            ExternalEvent::JobOutput { id, line } => info!("[{id}] {line}"),
+            ExternalEvent::PartialRefresh(data) => {
+                if tasks::task_files_changed(&self.path, data) {
+                    self.discovered_tasks = tasks::discover(&self.path);
+                }
+                if command_env::dotenv_changed(&self.path, data) {
+                    self.command_env.reload_dotenv();
+                }
+                let triggered = self.watches.borrow().triggered(&self.path, data);
+                for index in triggered {
+                    self.run_watch(index)?;
+                }
+            }
+            ExternalEvent::RefreshFiletree => {
+                self.discovered_tasks = tasks::discover(&self.path);
+                self.command_env.reload_dotenv();
+                self.command_env.reload_git_branch();
+            }
+            ExternalEvent::RefreshGitStatus => self.command_env.reload_git_branch(),
+            ExternalEvent::JobFinished {
+                id,
+                command,
+                status,
+                runtime,
+            } => {
+                log_finished(&format!("[{id}] \"{command}\""), *status, *runtime);
+                self.finish_task(TaskRun::Job(*id), *status);
+            }
+            ExternalEvent::ForegroundFinished { status, runtime } => {
+                let status = JobStatus::Finished(*status);
+                let command = self.foreground_command.take().unwrap_or_default();
+                log_finished(&format!("\"{command}\""), status, *runtime);
+                self.finish_task(TaskRun::Foreground, status);
             }
             _ => (),
         }
@@ -309,6 +398,230 @@ impl App {
     pub fn path(&self) -> &Path {
         &self.path
     }
+
+    /// Move git status refreshes off of the UI thread, sending results to `sender`
+    pub fn start_status_worker(&mut self, sender: Sender<ExternalEvent>) {
+        self.tree.start_status_worker(sender);
+    }
+
+    /// Send the output of background jobs to `sender`
+    pub fn connect_jobs(&mut self, sender: Sender<ExternalEvent>) {
+        self.jobs.set_sender(sender);
+    }
+
+    /// Swap the config of the app and every component for `config`, keeping the state of the
+    /// tree. Nothing is swapped if the watches of `config` are invalid.
+    pub fn set_config(&mut self, config: Rc<Config>) -> Result<()> {
+        self.watches
+            .borrow_mut()
+            .reload(&config.watch.commands)
+            .context("error setting up watches")?;
+        self.jobs.set_limits(
+            config.jobs.max_concurrent,
+            config.jobs.max_output,
+            config.jobs.max_history,
+        );
+        self.command_env.set_config(Rc::clone(&config));
+
+        self.tree.set_config(Rc::clone(&config));
+        self.pending.set_config(Rc::clone(&config));
+        self.input_box.set_config(Rc::clone(&config));
+        self.previewer.set_config(Rc::clone(&config));
+        self.text_popup.set_config(Rc::clone(&config));
+        self.file_cmd_popup.set_config(Rc::clone(&config));
+        self.marks_popup.set_config(Rc::clone(&config));
+        self.jobs_popup.set_config(Rc::clone(&config));
+        self.watches_popup.set_config(Rc::clone(&config));
+        self.fuzzy_matcher.set_config(Rc::clone(&config));
+        self.logger.set_config(Rc::clone(&config));
+        self.config = config;
+
+        // The preview command or its limits might have changed
+        if let Some(item) = self.tree.get_selected() {
+            self.queue
+                .add(AppEvent::PreviewFile(item.path().to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Run `cmd` in the background, unless it starts with `!!`. Foreground commands are given back
+    /// to be run by main.rs.
+    fn run_command(&mut self, cmd: &str, dir: Option<&Path>) -> Result<Either<JobId, Expression>> {
+        // Strip !!, and if it exists, run in foreground, not background
+        let (threaded, template) = cmd.strip_prefix("!!").map_or((true, cmd), |s| (false, s));
+        let cmd_str = self.interpolate(template)?;
+
+        let cmd = self.shell(&cmd_str)?;
+        let cmd = match dir {
+            Some(dir) => cmd.dir(dir),
+            None => cmd,
+        };
+        let cmd = self
+            .command_env
+            .apply(cmd, self.tree.get_selected().map(|item| item.path()));
+
+        if threaded {
+            let id = self.jobs.spawn(
+                cmd_str.to_string_lossy(),
+                cmd.stderr_to_stdout().stdin_null().unchecked(),
+            )?;
+            Ok(Either::Left(id))
+        } else {
+            self.foreground_command = Some(cmd_str.to_string_lossy().into_owned());
+            Ok(Either::Right(cmd.unchecked()))
+        }
+    }
+
+    /// Expression running `cmd` with the user's shell, or `exec_cmd` if set
+    fn shell(&self, cmd: &OsStr) -> Result<Expression> {
+        if let Some(cmd_start) = &self.config.exec_cmd {
+            return Ok(duct::cmd(
+                cmd_start
+                    .get(0)
+                    .context("problem executing user shell command: no first argument")?,
+                cmd_start
+                    .iter()
+                    .skip(1)
+                    .map(|s| s.as_os_str())
+                    .chain(iter::once(cmd)),
+            ));
+        }
+        #[cfg(not(target_os = "windows"))]
+        let cmd = cmd!(
+            env::var("SHELL").unwrap_or_else(|_| "sh".to_owned()),
+            "-c",
+            cmd
+        );
+        #[cfg(target_os = "windows")]
+        let cmd = cmd!("cmd.exe", "/C", cmd);
+        Ok(cmd)
+    }
+
+    /// Run the command of a watch, killing its last run if that is still going
+    fn run_watch(&mut self, index: usize) -> Result<()> {
+        let (command, last_run) = {
+            let watches = self.watches.borrow();
+            let watch = &watches.watches()[index];
+            (watch.command.clone(), watch.job)
+        };
+        if let Some(id) = last_run {
+            self.jobs.kill(id)?;
+        }
+        // Watch commands can't be foreground ones, so this is always a job
+        if let Either::Left(id) = self.run_command(&command, None)? {
+            self.watches.borrow_mut().get_mut(index).job = Some(id);
+        }
+        Ok(())
+    }
+
+    /// Tasks from the config and the build files of the project, by name
+    fn all_tasks(&self) -> HashMap<String, Task> {
+        self.config
+            .tasks
+            .clone()
+            .into_iter()
+            .chain(self.discovered_tasks.iter().cloned())
+            .collect()
+    }
+
+    fn open_tasks(&mut self) {
+        if self.config.tasks.is_empty() && self.discovered_tasks.is_empty() {
+            warn!("no tasks found");
+            return;
+        }
+        // Tasks from the config come first, then the discovered ones grouped by tool
+        let (names, items) = self
+            .config
+            .tasks
+            .iter()
+            .sorted_by_key(|(name, _)| *name)
+            .chain(
+                self.discovered_tasks
+                    .iter()
+                    .map(|(name, task)| (name, task)),
+            )
+            .map(|(name, task)| {
+                let item = if task.description.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{name}: {}", task.description)
+                };
+                (name.clone(), item)
+            })
+            .unzip();
+        self.fuzzy_matcher
+            .start(items, FuzzyOperation::RunTask(names));
+    }
+
+    /// Start the next task of the running chain. Foreground tasks are given back to be run by
+    /// main.rs.
+    fn run_next_task(&mut self) -> Result<Option<Expression>> {
+        let Some(name) = self.tasks.as_mut().and_then(TaskChain::next_task) else {
+            if self.tasks.take().is_some() {
+                info!("finished running tasks");
+            }
+            return Ok(None);
+        };
+        let Some(task) = self.all_tasks().remove(&name) else {
+            self.tasks = None;
+            bail!("unknown task \"{name}\"");
+        };
+        let cmd = if task.foreground {
+            format!("!!{}", task.command)
+        } else {
+            task.command
+        };
+        let dir = task.cwd.map(|cwd| self.path.join(cwd));
+
+        info!("running task \"{name}\"");
+        let started = self.run_command(&cmd, dir.as_deref());
+        let Some(chain) = self.tasks.as_mut() else {
+            return Ok(None);
+        };
+        match started {
+            Ok(Either::Left(id)) => {
+                chain.started(TaskRun::Job(id));
+                Ok(None)
+            }
+            Ok(Either::Right(expr)) => {
+                chain.started(TaskRun::Foreground);
+                Ok(Some(expr))
+            }
+            Err(err) => {
+                self.tasks = None;
+                Err(err).with_context(|| format!("failed to run task \"{name}\""))
+            }
+        }
+    }
+
+    /// Move on to the next task if `run` was the running task, or stop at the first failure
+    fn finish_task(&mut self, run: TaskRun, status: JobStatus) {
+        let Some(name) = self.tasks.as_ref().and_then(|chain| chain.running(run)) else {
+            return;
+        };
+        if status.success() {
+            self.queue.add(AppEvent::NextTask);
+        } else {
+            error!("task \"{name}\" did not succeed ({status}), stopping");
+            self.tasks = None;
+        }
+    }
+
+    /// Fill in the placeholders of a command with the selected path, project root, and marks
+    fn interpolate(&self, template: &str) -> Result<OsString> {
+        self.command_env
+            .interpolate(template, self.tree.get_selected().map(|item| item.path()))
+    }
+}
+
+/// Log how a command ended, as an error if it did not succeed
+fn log_finished(command: &str, status: JobStatus, runtime: Duration) {
+    let runtime = format!("{:.1}s", runtime.as_secs_f32());
+    match status {
+        _ if status.success() => info!("{command} finished in {runtime}"),
+        JobStatus::Killed => info!("{command} killed after {runtime}"),
+        _ => error!("{command} failed ({status}) after {runtime}"),
+    }
 }
 
 impl Drawable for App {
@@ -331,6 +644,8 @@ impl Drawable for App {
         self.text_popup.draw(f, area)?;
         self.file_cmd_popup.draw(f, area)?;
         self.marks_popup.draw(f, area)?;
+        self.jobs_popup.draw(f, area)?;
+        self.watches_popup.draw(f, area)?;
         self.fuzzy_matcher.draw(f, area)?;
 
         Ok(())
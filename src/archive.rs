@@ -0,0 +1,348 @@
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use std::{
+    fmt::Write,
+    fs::{self, File},
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+use zip::ZipArchive;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    /// The extensions of each kind of archive, longest first so `.tar.gz` wins over `.gz`
+    const EXTENSIONS: [(&'static str, ArchiveKind); 5] = [
+        (".tar.gz", ArchiveKind::TarGz),
+        (".crate", ArchiveKind::TarGz),
+        (".tgz", ArchiveKind::TarGz),
+        (".tar", ArchiveKind::Tar),
+        (".zip", ArchiveKind::Zip),
+    ];
+
+    /// Get the kind of archive `path` is, based on its file name
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let name = path.as_ref().file_name()?.to_str()?.to_lowercase();
+        Self::EXTENSIONS
+            .iter()
+            .find(|(ext, _)| name.ends_with(ext) && name.len() > ext.len())
+            .map(|(_, kind)| *kind)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub is_dir: bool,
+    /// Formatted as `YYYY-MM-DD HH:MM`
+    pub modified: Option<String>,
+}
+
+/// List every entry of the archive at `path`
+pub fn list(path: impl AsRef<Path>) -> Result<Vec<Entry>> {
+    let path = path.as_ref();
+    let kind = ArchiveKind::from_path(path).context("not a supported archive")?;
+    let file = BufReader::new(
+        File::open(path).with_context(|| format!("problem opening \"{}\"", path.display()))?,
+    );
+    match kind {
+        ArchiveKind::Zip => list_zip(file),
+        ArchiveKind::Tar => list_tar(file),
+        ArchiveKind::TarGz => list_tar(GzDecoder::new(file)),
+    }
+    .with_context(|| format!("problem reading archive \"{}\"", path.display()))
+}
+
+fn list_zip(reader: BufReader<File>) -> Result<Vec<Entry>> {
+    let mut archive = ZipArchive::new(reader)?;
+    let mut entries = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        let modified = file.last_modified();
+        entries.push(Entry {
+            path: PathBuf::from(file.name()),
+            size: file.size(),
+            is_dir: file.is_dir(),
+            modified: Some(format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}",
+                modified.year(),
+                modified.month(),
+                modified.day(),
+                modified.hour(),
+                modified.minute()
+            )),
+        });
+    }
+    Ok(entries)
+}
+
+fn list_tar(reader: impl Read) -> Result<Vec<Entry>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let header = entry.header();
+        entries.push(Entry {
+            path: entry.path()?.into_owned(),
+            size: header.size()?,
+            is_dir: header.entry_type().is_dir(),
+            modified: header.mtime().ok().map(format_timestamp),
+        });
+    }
+    Ok(entries)
+}
+
+/// Format a unix timestamp as `YYYY-MM-DD HH:MM` in UTC
+fn format_timestamp(secs: u64) -> String {
+    // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (secs / 86_400) as i64;
+    let minutes = (secs % 86_400) / 60;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// Render the entries of the archive at `path` as a table, for previewing
+pub fn preview(path: impl AsRef<Path>) -> Result<String> {
+    let entries = list(path)?;
+    let total: u64 = entries.iter().map(|entry| entry.size).sum();
+    let mut out = format!("{} entries, {total} bytes\n", entries.len());
+    for entry in entries {
+        writeln!(
+            out,
+            "{:>10}  {:<16}  {}{}",
+            entry.size,
+            entry.modified.as_deref().unwrap_or("-"),
+            entry.path.display(),
+            if entry.is_dir && !entry.path.ends_with("/") {
+                "/"
+            } else {
+                ""
+            }
+        )
+        .expect("writing to a string should not fail");
+    }
+    Ok(out)
+}
+
+/// The directory an archive is extracted into: next to the archive, named after it without its
+/// extension
+pub fn extract_dir(path: impl AsRef<Path>) -> Option<PathBuf> {
+    let path = path.as_ref();
+    let name = path.file_name()?.to_str()?;
+    let lowercase = name.to_lowercase();
+    let (ext, _) = ArchiveKind::EXTENSIONS
+        .iter()
+        .find(|(ext, _)| lowercase.ends_with(ext) && lowercase.len() > ext.len())?;
+    Some(path.with_file_name(&name[..name.len() - ext.len()]))
+}
+
+/// Extract the archive at `path` into its [`extract_dir`]. Returns every extracted path, ordered
+/// so that each is preceded by its parent directory and siblings end up sorted when added to the
+/// file listing one at a time.
+pub fn extract(path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    let path = path.as_ref();
+    let kind = ArchiveKind::from_path(path).context("not a supported archive")?;
+    let dest = extract_dir(path).context("archive should have a file name")?;
+    if dest.exists() {
+        bail!("\"{}\" already exists", dest.display());
+    }
+    let file = BufReader::new(
+        File::open(path).with_context(|| format!("problem opening \"{}\"", path.display()))?,
+    );
+    match kind {
+        ArchiveKind::Zip => ZipArchive::new(file)
+            .and_then(|mut archive| archive.extract(&dest))
+            .map_err(anyhow::Error::from),
+        ArchiveKind::Tar => tar::Archive::new(file)
+            .unpack(&dest)
+            .map_err(anyhow::Error::from),
+        ArchiveKind::TarGz => tar::Archive::new(GzDecoder::new(file))
+            .unpack(&dest)
+            .map_err(anyhow::Error::from),
+    }
+    .with_context(|| format!("problem extracting archive \"{}\"", path.display()))?;
+
+    let mut created = vec![dest.clone()];
+    push_children(&dest, &mut created)?;
+    Ok(created)
+}
+
+fn push_children(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    let mut children = fs::read_dir(dir)?
+        .map(|entry| entry.and_then(|entry| Ok((entry.path(), entry.file_type()?))))
+        .collect::<Result<Vec<_>, _>>()?;
+    // Children are inserted directly after their parent, so add them in reverse
+    children.sort_unstable_by(|(a, _), (b, _)| b.cmp(a));
+    for (child, file_type) in children {
+        paths.push(child.clone());
+        // Archives can contain symlinks to anywhere, including their own parents
+        if file_type.is_dir() {
+            push_children(&child, paths)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write as _;
+    use test_log::test;
+    use zip::{write::FileOptions, ZipWriter};
+
+    fn write_tar(writer: impl std::io::Write) {
+        let mut builder = tar::Builder::new(writer);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mtime(1_000_000_000);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "dir/hello.txt", &b"hello"[..])
+            .unwrap();
+        builder.into_inner().unwrap();
+    }
+
+    #[test]
+    fn recognizes_archive_kinds() {
+        assert_eq!(Some(ArchiveKind::Zip), ArchiveKind::from_path("test.zip"));
+        assert_eq!(Some(ArchiveKind::Tar), ArchiveKind::from_path("test.tar"));
+        assert_eq!(
+            Some(ArchiveKind::TarGz),
+            ArchiveKind::from_path("test.tar.gz")
+        );
+        assert_eq!(
+            Some(ArchiveKind::TarGz),
+            ArchiveKind::from_path("serde-1.0.0.crate")
+        );
+        assert_eq!(None, ArchiveKind::from_path("test.txt"));
+        assert_eq!(None, ArchiveKind::from_path(".zip"));
+    }
+
+    #[test]
+    fn extract_dir_strips_extension() {
+        assert_eq!(
+            Some(PathBuf::from("/root/test")),
+            extract_dir("/root/test.tar.gz")
+        );
+        assert_eq!(
+            Some(PathBuf::from("/root/serde-1.0.0")),
+            extract_dir("/root/serde-1.0.0.crate")
+        );
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!("1970-01-01 00:00", format_timestamp(0));
+        assert_eq!("2001-09-09 01:46", format_timestamp(1_000_000_000));
+        assert_eq!("2024-02-29 12:30", format_timestamp(1_709_209_800));
+    }
+
+    #[test]
+    fn lists_tar_gz_entries() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.child("test.tar.gz");
+        write_tar(GzEncoder::new(
+            File::create(archive.path()).unwrap(),
+            Compression::default(),
+        ));
+        assert_eq!(
+            vec![Entry {
+                path: "dir/hello.txt".into(),
+                size: 5,
+                is_dir: false,
+                modified: Some("2001-09-09 01:46".to_owned()),
+            }],
+            list(archive.path()).unwrap()
+        );
+    }
+
+    #[test]
+    fn lists_zip_entries() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.child("test.zip");
+        let mut zip = ZipWriter::new(File::create(archive.path()).unwrap());
+        zip.add_directory("dir/", FileOptions::default()).unwrap();
+        zip.start_file("dir/hello.txt", FileOptions::default())
+            .unwrap();
+        zip.write_all(b"hello").unwrap();
+        zip.finish().unwrap();
+
+        let entries = list(archive.path()).unwrap();
+        assert_eq!(2, entries.len());
+        assert!(entries[0].is_dir);
+        assert_eq!(PathBuf::from("dir/hello.txt"), entries[1].path);
+        assert_eq!(5, entries[1].size);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extract_does_not_follow_symlinks() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.child("test.tar");
+        let mut builder = tar::Builder::new(File::create(archive.path()).unwrap());
+        for (name, target) in [("dir/root", "/"), ("dir/self", ".")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            header.set_mode(0o777);
+            builder.append_link(&mut header, name, target).unwrap();
+        }
+        builder.into_inner().unwrap();
+
+        let created = extract(archive.path()).unwrap();
+        assert_eq!(
+            vec![
+                temp.path().join("test"),
+                temp.path().join("test/dir"),
+                temp.path().join("test/dir/self"),
+                temp.path().join("test/dir/root"),
+            ],
+            created
+        );
+        assert!(temp.path().join("test/dir/self").is_dir());
+    }
+
+    #[test]
+    fn extracts_next_to_archive() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.child("test.tar");
+        write_tar(File::create(archive.path()).unwrap());
+
+        let created = extract(archive.path()).unwrap();
+        assert_eq!(
+            vec![
+                temp.path().join("test"),
+                temp.path().join("test/dir"),
+                temp.path().join("test/dir/hello.txt"),
+            ],
+            created
+        );
+        temp.child("test/dir/hello.txt").assert("hello");
+        assert!(extract(archive.path()).is_err());
+    }
+}
@@ -0,0 +1,377 @@
+use crate::{
+    config::{Config, EnvPrecedence},
+    external_event::RefreshData,
+    interpolate,
+    marks::Marks,
+};
+use anyhow::{bail, Context, Result};
+use duct::Expression;
+use git2::Repository;
+use log::warn;
+use std::{
+    cell::RefCell,
+    env,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+/// Environment given to every spawned command, describing the project and what is selected.
+/// Clones share the variables loaded from `.env` and the git branch.
+#[derive(Debug, Clone)]
+pub struct CommandEnv {
+    root: PathBuf,
+    marks: Rc<RefCell<Marks>>,
+    config: Rc<Config>,
+    dotenv: Rc<RefCell<Vec<(String, String)>>>,
+    /// Checked out branch, empty if there is none
+    git_branch: Rc<RefCell<String>>,
+}
+
+impl CommandEnv {
+    pub fn new(root: PathBuf, marks: Rc<RefCell<Marks>>, config: Rc<Config>) -> Self {
+        let env = Self {
+            root,
+            marks,
+            config,
+            dotenv: Rc::default(),
+            git_branch: Rc::default(),
+        };
+        env.reload_dotenv();
+        env.reload_git_branch();
+        env
+    }
+
+    /// Use `config` from now on, which might turn loading `.env` on or off. Clones keep the
+    /// old config, but share the variables.
+    pub fn set_config(&mut self, config: Rc<Config>) {
+        self.config = config;
+        self.reload_dotenv();
+    }
+
+    /// Read the `.env` file of the project again, if loading it is enabled. Variables from a
+    /// file that fails to parse are dropped.
+    pub fn reload_dotenv(&self) {
+        if !self.config.env.dotenv {
+            self.dotenv.borrow_mut().clear();
+            return;
+        }
+        let vars = read_dotenv(&self.root).unwrap_or_else(|err| {
+            warn!("{err:#}");
+            Vec::new()
+        });
+        *self.dotenv.borrow_mut() = vars;
+    }
+
+    /// Look up the checked out branch again, after the repository changed
+    pub fn reload_git_branch(&self) {
+        *self.git_branch.borrow_mut() = git_branch(&self.root).unwrap_or_default();
+    }
+
+    /// Variables to set for a command run while `selected` is selected. Variables from `.env`
+    /// come first, so the `PRJ_` ones always win.
+    pub fn vars(&self, selected: Option<&Path>) -> Vec<(OsString, OsString)> {
+        let mut vars: Vec<(OsString, OsString)> = self
+            .dotenv
+            .borrow()
+            .iter()
+            .filter(|(key, _)| {
+                self.config.env.precedence == EnvPrecedence::Dotenv || env::var_os(key).is_none()
+            })
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+
+        let relative = selected
+            .and_then(|selected| selected.strip_prefix(&self.root).ok())
+            .unwrap_or_else(|| Path::new(""));
+        let mut marks = OsString::new();
+        for (i, mark) in self.marks.borrow().marks.iter().enumerate() {
+            if i > 0 {
+                marks.push("\n");
+            }
+            marks.push(mark);
+        }
+        vars.extend([
+            ("PRJ_ROOT".into(), self.root.clone().into()),
+            (
+                "PRJ_SELECTED".into(),
+                selected.map(OsString::from).unwrap_or_default(),
+            ),
+            ("PRJ_SELECTED_REL".into(), relative.into()),
+            ("PRJ_MARKS".into(), marks),
+            (
+                "PRJ_GIT_BRANCH".into(),
+                self.git_branch.borrow().clone().into(),
+            ),
+        ]);
+        vars
+    }
+
+    /// Fill in the placeholders of `template` for a command run while `selected` is selected
+    pub fn interpolate(&self, template: &str, selected: Option<&Path>) -> Result<OsString> {
+        let marks = self.marks.borrow();
+        let context = interpolate::Context {
+            selected,
+            root: &self.root,
+            marks: &marks.marks,
+        };
+        interpolate::interpolate(template, &context)
+            .with_context(|| format!("failed to fill in \"{template}\""))
+    }
+
+    /// Set the variables for `selected` on `expr`
+    pub fn apply(&self, expr: Expression, selected: Option<&Path>) -> Expression {
+        self.vars(selected)
+            .into_iter()
+            .fold(expr, |expr, (key, value)| expr.env(key, value))
+    }
+}
+
+/// Whether any of the changed files is the `.env` of the project at `root`
+pub fn dotenv_changed<'a>(root: &Path, data: impl IntoIterator<Item = &'a RefreshData>) -> bool {
+    let dotenv = root.join(".env");
+    data.into_iter().any(|data| match data {
+        RefreshData::Add(path) | RefreshData::Delete(path) | RefreshData::Modify(path) => {
+            *path == dotenv
+        }
+        RefreshData::Rename { from, to } => *from == dotenv || *to == dotenv,
+    })
+}
+
+/// Name of the branch checked out in `root`, if it is a git repository not in detached HEAD
+fn git_branch(root: &Path) -> Option<String> {
+    let repo = Repository::open(root.join(".git")).ok()?;
+    let head = repo.head().ok()?;
+    if !head.is_branch() {
+        return None;
+    }
+    head.shorthand().map(str::to_owned)
+}
+
+/// Variables of the `.env` file in `root`. Finds nothing if there is none.
+fn read_dotenv(root: &Path) -> Result<Vec<(String, String)>> {
+    let path = root.join(".env");
+    match fs::read_to_string(&path) {
+        Ok(contents) => parse_dotenv(&contents).context("error parsing .env"),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err).with_context(|| format!("error reading \"{}\"", path.display())),
+    }
+}
+
+/// Parse `KEY=value` lines, skipping blank lines and `#` comments. Values can be quoted with
+/// `'` or `"`, and an `export ` before the key is ignored.
+fn parse_dotenv(contents: &str) -> Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            bail!("line {} is not KEY=value", i + 1);
+        };
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            bail!("line {} has an invalid name \"{key}\"", i + 1);
+        }
+        let value = value.trim();
+        let value = ['"', '\'']
+            .into_iter()
+            .find_map(|quote| {
+                value
+                    .strip_prefix(quote)
+                    .and_then(|value| value.strip_suffix(quote))
+            })
+            .unwrap_or_else(|| {
+                // Unquoted values end at a comment
+                value.split(" #").next().unwrap_or_default().trim_end()
+            });
+        vars.push((key.to_owned(), value.to_owned()));
+    }
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use test_log::test;
+
+    fn env_config(precedence: EnvPrecedence) -> Rc<Config> {
+        let mut config = Config::default();
+        config.env.dotenv = true;
+        config.env.precedence = precedence;
+        Rc::new(config)
+    }
+
+    fn var<'a>(vars: &'a [(OsString, OsString)], key: &str) -> Option<&'a OsString> {
+        vars.iter().rev().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    #[test]
+    fn parses_dotenv() {
+        let contents = "\
+# Database
+DB_URL=postgres://localhost/db # local
+export NAME = \"my app\"
+QUOTED='a # b'
+
+EMPTY=
+";
+        assert_eq!(
+            vec![
+                ("DB_URL".to_owned(), "postgres://localhost/db".to_owned()),
+                ("NAME".to_owned(), "my app".to_owned()),
+                ("QUOTED".to_owned(), "a # b".to_owned()),
+                ("EMPTY".to_owned(), String::new()),
+            ],
+            parse_dotenv(contents).unwrap()
+        );
+        assert!(parse_dotenv("NOT A VAR").is_err());
+        assert!(parse_dotenv("BAD-NAME=1").is_err());
+    }
+
+    #[test]
+    fn describes_selection_and_marks() {
+        let mut marks = Marks::default();
+        marks.marks = vec!["/project/a".into(), "/project/b".into()];
+        let env = CommandEnv::new(
+            "/project".into(),
+            Rc::new(RefCell::new(marks)),
+            Rc::new(Config::default()),
+        );
+        let vars = env.vars(Some(Path::new("/project/src/main.rs")));
+        assert_eq!(Some(&"/project".into()), var(&vars, "PRJ_ROOT"));
+        assert_eq!(
+            Some(&"/project/src/main.rs".into()),
+            var(&vars, "PRJ_SELECTED")
+        );
+        assert_eq!(Some(&"src/main.rs".into()), var(&vars, "PRJ_SELECTED_REL"));
+        assert_eq!(
+            Some(&"/project/a\n/project/b".into()),
+            var(&vars, "PRJ_MARKS")
+        );
+        assert_eq!(Some(&"".into()), var(&vars, "PRJ_GIT_BRANCH"));
+
+        let vars = env.vars(None);
+        assert_eq!(Some(&"".into()), var(&vars, "PRJ_SELECTED"));
+    }
+
+    #[test]
+    fn knows_git_branch() {
+        let temp = TempDir::new().unwrap();
+        let repo = Repository::init(temp.path()).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+        let branch = repo.head().unwrap().shorthand().unwrap().to_owned();
+        assert_eq!(Some(branch), git_branch(temp.path()));
+        assert_eq!(None, git_branch(&temp.path().join("missing")));
+        temp.close().unwrap();
+    }
+
+    #[test]
+    fn git_branch_is_kept_until_reloaded() {
+        let temp = TempDir::new().unwrap();
+        let repo = Repository::init(temp.path()).unwrap();
+        repo.set_head("refs/heads/first").unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let commit = repo
+            .commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+        repo.branch("second", &repo.find_commit(commit).unwrap(), false)
+            .unwrap();
+        let env = CommandEnv::new(
+            temp.path().to_path_buf(),
+            Rc::default(),
+            Rc::new(Config::default()),
+        );
+        assert_eq!(
+            Some(&"first".into()),
+            var(&env.vars(None), "PRJ_GIT_BRANCH")
+        );
+
+        repo.set_head("refs/heads/second").unwrap();
+        assert_eq!(
+            Some(&"first".into()),
+            var(&env.vars(None), "PRJ_GIT_BRANCH")
+        );
+        env.reload_git_branch();
+        assert_eq!(
+            Some(&"second".into()),
+            var(&env.vars(None), "PRJ_GIT_BRANCH")
+        );
+        temp.close().unwrap();
+    }
+
+    #[test]
+    fn dotenv_precedence_is_configurable() {
+        let temp = TempDir::new().unwrap();
+        temp.child(".env")
+            .write_str("PATH=/nowhere\nPRJ_ROOT=/other\nPRJ_TEST_ONLY_IN_DOTENV=1\n")
+            .unwrap();
+        let marks = Rc::new(RefCell::new(Marks::default()));
+
+        let env = CommandEnv::new(
+            temp.path().to_path_buf(),
+            Rc::clone(&marks),
+            env_config(EnvPrecedence::Environment),
+        );
+        let vars = env.vars(None);
+        assert_eq!(None, var(&vars, "PATH"));
+        assert_eq!(Some(&"1".into()), var(&vars, "PRJ_TEST_ONLY_IN_DOTENV"));
+        assert_eq!(Some(&temp.path().into()), var(&vars, "PRJ_ROOT"));
+
+        let env = CommandEnv::new(
+            temp.path().to_path_buf(),
+            marks,
+            env_config(EnvPrecedence::Dotenv),
+        );
+        let vars = env.vars(None);
+        assert_eq!(Some(&"/nowhere".into()), var(&vars, "PATH"));
+        assert_eq!(Some(&temp.path().into()), var(&vars, "PRJ_ROOT"));
+        temp.close().unwrap();
+    }
+
+    #[test]
+    fn disabling_dotenv_drops_its_variables() {
+        let temp = TempDir::new().unwrap();
+        temp.child(".env").write_str("PRJ_TEST_DOTENV=1\n").unwrap();
+        let mut env = CommandEnv::new(
+            temp.path().to_path_buf(),
+            Rc::new(RefCell::new(Marks::default())),
+            env_config(EnvPrecedence::Environment),
+        );
+        assert!(var(&env.vars(None), "PRJ_TEST_DOTENV").is_some());
+
+        env.set_config(Rc::new(Config::default()));
+        assert_eq!(None, var(&env.vars(None), "PRJ_TEST_DOTENV"));
+        temp.close().unwrap();
+    }
+
+    #[test]
+    fn commands_get_the_variables() {
+        let env = CommandEnv::new(
+            "/project".into(),
+            Rc::new(RefCell::new(Marks::default())),
+            Rc::new(Config::default()),
+        );
+        #[cfg(unix)]
+        let expr = duct::cmd!("sh", "-c", "echo $PRJ_SELECTED_REL");
+        #[cfg(not(unix))]
+        let expr = duct::cmd!("cmd.exe", "/C", "echo %PRJ_SELECTED_REL%");
+        let out = env
+            .apply(expr, Some(Path::new("/project/a.txt")))
+            .read()
+            .unwrap();
+        assert_eq!("a.txt", out.trim());
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::Error;
+use anyhow::{Context, Error};
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use either::Either;
 use globset::{Glob, GlobSet, GlobSetBuilder};
@@ -78,15 +78,25 @@ pub enum Action<'a> {
     Help,
     PreviewDown,
     PreviewUp,
+    PreviewLeft,
+    PreviewRight,
+    PreviewToggleWrap,
+    PreviewSearch,
+    PreviewNextMatch,
+    PreviewPrevMatch,
     Down,
     Up,
     AllUp,
     AllDown,
     Open,
     OpenMarks,
+    OpenJobs,
+    OpenWatches,
+    OpenTasks,
     FiletreeDownThree,
     FiletreeUpThree,
     FiletreeExecCmd,
+    FiletreeRepeatCmd,
     FiletreeDelete,
     FiletreeSearch,
     FiletreeClear,
@@ -102,7 +112,23 @@ pub enum Action<'a> {
     FiletreeRename,
     FiletreeMove,
     FiletreeFocus,
+    FiletreeExtract,
     KillProcesses,
+    InputSubmit,
+    InputCancel,
+    InputCursorLeft,
+    InputCursorRight,
+    InputCursorStart,
+    InputCursorEnd,
+    InputDelete,
+    InputDeleteToStart,
+    InputHistoryOlder,
+    InputHistoryNewer,
+    InputSearchHistory,
+    InputVerticalSplit,
+    InputHorizontalSplit,
+    InputNewWindow,
+    InputFloatingWindow,
     Arbitrary(&'a str),
 }
 
@@ -180,9 +206,11 @@ pub struct Config {
     pub all_up: KeyBind,
     pub open: KeyBind,
     pub kill_processes: KeyBind,
+    pub open_tasks: KeyBind,
     pub exec_cmd: Option<Vec<OsString>>,
     pub special_commands: HashMap<String, Vec<String>>,
     pub commands: HashMap<Key, String>,
+    pub tasks: HashMap<String, Task>,
     pub project_roots: GlobList,
     pub esc_to_close: bool,
 
@@ -196,6 +224,11 @@ pub struct Config {
     pub filetree: FiletreeConfig,
     pub log: LogConfig,
     pub marks: MarksConfig,
+    pub jobs: JobsConfig,
+    pub watch: WatchConfig,
+    pub env: EnvConfig,
+    pub multiplexer: MultiplexerConfig,
+    pub input: InputConfig,
 }
 
 impl Config {
@@ -210,9 +243,16 @@ impl Config {
             (Action::AllUp, &self.all_up),
             (Action::PreviewDown, &self.preview.down_key),
             (Action::PreviewUp, &self.preview.up_key),
+            (Action::PreviewLeft, &self.preview.left_key),
+            (Action::PreviewRight, &self.preview.right_key),
+            (Action::PreviewToggleWrap, &self.preview.toggle_wrap),
+            (Action::PreviewSearch, &self.preview.search),
+            (Action::PreviewNextMatch, &self.preview.next_match),
+            (Action::PreviewPrevMatch, &self.preview.prev_match),
             (Action::FiletreeUpThree, &self.filetree.up_three),
             (Action::FiletreeDownThree, &self.filetree.down_three),
             (Action::FiletreeExecCmd, &self.filetree.exec_cmd),
+            (Action::FiletreeRepeatCmd, &self.filetree.repeat_cmd),
             (Action::FiletreeDelete, &self.filetree.delete),
             (Action::FiletreeSearch, &self.filetree.search),
             (Action::FiletreeClear, &self.filetree.clear),
@@ -226,6 +266,9 @@ impl Config {
             ),
             (Action::FiletreeMarkSelected, &self.filetree.mark_selected),
             (Action::OpenMarks, &self.marks.open),
+            (Action::OpenJobs, &self.jobs.open),
+            (Action::OpenWatches, &self.watch.open),
+            (Action::OpenTasks, &self.open_tasks),
             (Action::FiletreeOpenUnder, &self.filetree.open_under),
             (Action::FiletreeCloseUnder, &self.filetree.close_under),
             (Action::FiletreeRename, &self.filetree.rename),
@@ -233,45 +276,76 @@ impl Config {
             (Action::FiletreeMove, &self.filetree.move_path),
             (Action::KillProcesses, &self.kill_processes),
             (Action::FiletreeFocus, &self.filetree.focus),
+            (Action::FiletreeExtract, &self.filetree.extract),
         ];
-        let mut keys = Vec::with_capacity(keybinds.len());
-        for keybind in keybinds {
-            match keybind.1.all_bindings() {
-                Either::Left(ref key) => {
-                    keys.push((keybind.0, key));
-                }
-                Either::Right(other_keys) => {
-                    keys.extend(other_keys.iter().map(|key| (keybind.0, key)));
-                }
-            }
-        }
-        let mut uses: HashMap<&Key, Vec<Action>> = HashMap::with_capacity(keys.len());
+        let mut keys = bound_keys(keybinds);
         // Put custom key binds actions
         keys.extend(
             self.commands
                 .iter()
                 .map(|(key, cmd)| (Action::Arbitrary(cmd), key)),
         );
+        let mut conflicts = find_conflicts(keys);
+
+        // The input box takes every key while open, so its keys only conflict with each other
+        let input = &self.input;
+        let input_keybinds = [
+            (Action::InputSubmit, &input.submit),
+            (Action::InputCancel, &input.cancel),
+            (Action::InputCursorLeft, &input.cursor_left),
+            (Action::InputCursorRight, &input.cursor_right),
+            (Action::InputCursorStart, &input.cursor_start),
+            (Action::InputCursorEnd, &input.cursor_end),
+            (Action::InputDelete, &input.delete),
+            (Action::InputDeleteToStart, &input.delete_to_start),
+            (Action::InputHistoryOlder, &input.history_older),
+            (Action::InputHistoryNewer, &input.history_newer),
+            (Action::InputSearchHistory, &input.search_history),
+            (Action::InputVerticalSplit, &input.vertical_split),
+            (Action::InputHorizontalSplit, &input.horizontal_split),
+            (Action::InputNewWindow, &input.new_window),
+            (Action::InputFloatingWindow, &input.floating_window),
+        ];
+        conflicts.extend(find_conflicts(bound_keys(input_keybinds)));
+        conflicts
+    }
+}
 
-        for (name, key) in keys {
-            match uses.entry(key) {
-                Entry::Occupied(mut actions) => actions.get_mut().push(name),
-                Entry::Vacant(slot) => drop(slot.insert(vec![name])),
-            }
+/// Every key of each keybind, with the action it is for
+fn bound_keys<'a>(
+    keybinds: impl IntoIterator<Item = (Action<'a>, &'a KeyBind)>,
+) -> Vec<(Action<'a>, &'a Key)> {
+    let mut keys = Vec::new();
+    for (action, keybind) in keybinds {
+        match keybind.all_bindings() {
+            Either::Left(ref key) => keys.push((action, key)),
+            Either::Right(other_keys) => keys.extend(other_keys.iter().map(|key| (action, key))),
+        }
+    }
+    keys
+}
+
+/// Keys bound to more than one action
+fn find_conflicts<'a>(keys: Vec<(Action<'a>, &'a Key)>) -> Vec<KeyConflict<'a>> {
+    let mut uses: HashMap<&Key, Vec<Action>> = HashMap::with_capacity(keys.len());
+    for (name, key) in keys {
+        match uses.entry(key) {
+            Entry::Occupied(mut actions) => actions.get_mut().push(name),
+            Entry::Vacant(slot) => drop(slot.insert(vec![name])),
         }
+    }
 
-        uses.into_iter()
-            .filter_map(|(key, actions)| {
-                if actions.len() == 1 {
-                    return None;
-                }
-                Some(KeyConflict {
-                    on: key,
-                    conflictors: actions,
-                })
+    uses.into_iter()
+        .filter_map(|(key, actions)| {
+            if actions.len() == 1 {
+                return None;
+            }
+            Some(KeyConflict {
+                on: key,
+                conflictors: actions,
             })
-            .collect()
-    }
+        })
+        .collect()
 }
 
 impl Merge for Config {
@@ -286,6 +360,7 @@ impl Merge for Config {
             all_down,
             all_up,
             kill_processes,
+            open_tasks,
             selected,
             popup_border_style,
             help_key_style,
@@ -295,10 +370,16 @@ impl Merge for Config {
         );
         self.commands.merge(other.commands);
         self.special_commands.merge(other.special_commands);
+        self.tasks.merge(other.tasks);
         self.preview.merge(other.preview);
         self.filetree.merge(other.filetree);
         self.log.merge(other.log);
         self.marks.merge(other.marks);
+        self.jobs.merge(other.jobs);
+        self.watch.merge(other.watch);
+        self.env.merge(other.env);
+        self.multiplexer.merge(other.multiplexer);
+        self.input.merge(other.input);
     }
 }
 
@@ -313,6 +394,7 @@ impl Default for Config {
             all_up: KeyBind::key(Key::normal('g')),
             all_down: KeyBind::key(Key::normal('G')),
             kill_processes: KeyBind::key(Key::ctrl('c')),
+            open_tasks: KeyBind::key(Key::normal('p')),
             special_commands: HashMap::new(),
             selected: Style::bg(Color::Black, Color::Magenta),
             popup_border_style: Style::default(),
@@ -322,6 +404,7 @@ impl Default for Config {
                 mods: Modifier(TuiModifier::BOLD),
             },
             commands: HashMap::new(),
+            tasks: HashMap::new(),
             project_roots: GlobList::default(),
             exec_cmd: None,
 
@@ -331,11 +414,33 @@ impl Default for Config {
             filetree: FiletreeConfig::default(),
             log: LogConfig::default(),
             marks: MarksConfig::default(),
+            jobs: JobsConfig::default(),
+            watch: WatchConfig::default(),
+            env: EnvConfig::default(),
+            multiplexer: MultiplexerConfig::default(),
+            input: InputConfig::default(),
             esc_to_close: true,
         }
     }
 }
 
+/// A named command, run from the task picker
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Task {
+    pub command: String,
+    #[serde(default)]
+    pub description: String,
+    /// Directory to run in, relative to the project root
+    pub cwd: Option<PathBuf>,
+    /// Run in the foreground, like commands starting with `!!`
+    #[serde(default)]
+    pub foreground: bool,
+    /// Tasks that have to succeed before this one runs
+    #[serde(default)]
+    pub depends: Vec<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct KeyConflict<'a> {
     on: &'a Key,
@@ -373,10 +478,26 @@ pub struct PreviewConfig {
     pub git_pager: Option<String>,
     pub down_key: KeyBind,
     pub up_key: KeyBind,
+    pub left_key: KeyBind,
+    pub right_key: KeyBind,
+    pub toggle_wrap: KeyBind,
+    pub search: KeyBind,
+    pub next_match: KeyBind,
+    pub prev_match: KeyBind,
     pub scroll_amount: u16,
+    pub line_numbers: bool,
+    /// Whether to wrap long lines when first opened
+    pub wrap: bool,
+    pub max_size: u64,
+    pub truncated_lines: usize,
+    pub tail_lines: usize,
     pub border_color: Style,
     pub scroll_bar_color: Style,
     pub unreached_bar_color: Style,
+    pub truncated_style: Style,
+    pub line_number_style: Style,
+    pub match_style: Style,
+    pub current_match_style: Style,
 }
 
 impl Default for PreviewConfig {
@@ -390,10 +511,25 @@ impl Default for PreviewConfig {
             git_pager: None,
             down_key: KeyBind::key(Key::ctrl('d')),
             up_key: KeyBind::key(Key::ctrl('u')),
+            left_key: KeyBind::key(Key::key_code(KeyCode::Left)),
+            right_key: KeyBind::key(Key::key_code(KeyCode::Right)),
+            toggle_wrap: KeyBind::key(Key::normal('w')),
+            search: KeyBind::key(Key::ctrl('f')),
+            next_match: KeyBind::key(Key::alt('n')),
+            prev_match: KeyBind::key(Key::alt('p')),
             scroll_amount: 10,
+            line_numbers: false,
+            wrap: false,
+            max_size: 1_048_576,
+            truncated_lines: 1000,
+            tail_lines: 0,
             border_color: Style::color(Color::Cyan),
             scroll_bar_color: Style::color(Color::Magenta),
             unreached_bar_color: Style::color(Color::Blue),
+            truncated_style: Style::color(Color::Yellow),
+            line_number_style: Style::color(Color::Yellow),
+            match_style: Style::bg(Color::Black, Color::Yellow),
+            current_match_style: Style::bg(Color::Black, Color::LightRed),
         }
     }
 }
@@ -406,11 +542,26 @@ impl Merge for PreviewConfig {
             git_pager,
             down_key,
             up_key,
+            left_key,
+            right_key,
+            toggle_wrap,
+            search,
+            next_match,
+            prev_match,
             scroll_bar_color,
             scroll_amount,
+            line_numbers,
+            wrap,
+            max_size,
+            truncated_lines,
+            tail_lines,
             border_color,
             scroll_bar_color,
-            unreached_bar_color
+            unreached_bar_color,
+            truncated_style,
+            line_number_style,
+            match_style,
+            current_match_style
         );
     }
 }
@@ -422,6 +573,9 @@ pub struct FiletreeConfig {
     pub ignore: Vec<String>,
     pub use_gitignore: bool,
     pub refresh_time: u64,
+    pub watcher: WatcherKind,
+    pub poll_interval: u64,
+    pub eager_depth: Option<usize>,
     pub dirs_first: bool,
     pub show_hidden_by_default: bool,
     pub special_commands_fuzzy: bool,
@@ -438,6 +592,7 @@ pub struct FiletreeConfig {
     pub down_three: KeyBind,
     pub up_three: KeyBind,
     pub exec_cmd: KeyBind,
+    pub repeat_cmd: KeyBind,
     pub delete: KeyBind,
     pub search: KeyBind,
     pub clear: KeyBind,
@@ -454,6 +609,7 @@ pub struct FiletreeConfig {
     pub rename: KeyBind,
     pub move_path: KeyBind,
     pub focus: KeyBind,
+    pub extract: KeyBind,
 }
 
 impl Default for FiletreeConfig {
@@ -466,9 +622,13 @@ impl Default for FiletreeConfig {
             special_commands_fuzzy: true,
             ignore: Vec::new(),
             refresh_time: 1000,
+            watcher: WatcherKind::Native,
+            poll_interval: 2000,
+            eager_depth: None,
             down_three: KeyBind::key(Key::ctrl('n')),
             up_three: KeyBind::key(Key::ctrl('p')),
             exec_cmd: KeyBind::key(Key::normal('e')),
+            repeat_cmd: KeyBind::key(Key::normal('E')),
             delete: KeyBind::key(Key::normal('d')),
             search: KeyBind::key(Key::normal('/')),
             clear: KeyBind::key(Key::normal('\\')),
@@ -486,6 +646,7 @@ impl Default for FiletreeConfig {
             rename: KeyBind::key(Key::normal('r')),
             move_path: KeyBind::key(Key::normal('R')),
             focus: KeyBind::key(Key::normal('f')),
+            extract: KeyBind::key(Key::normal('X')),
 
             filtered_out_message: Style::color(Color::Yellow),
             border_color: Style::color(Color::Magenta),
@@ -511,9 +672,13 @@ impl Merge for FiletreeConfig {
             use_gitignore,
             dirs_first,
             refresh_time,
+            watcher,
+            poll_interval,
+            eager_depth,
             down_three,
             up_three,
             exec_cmd,
+            repeat_cmd,
             delete,
             search,
             clear,
@@ -534,7 +699,8 @@ impl Merge for FiletreeConfig {
             show_hidden_by_default,
             dir_style,
             rename,
-            move_path
+            move_path,
+            extract
         );
     }
 }
@@ -548,6 +714,9 @@ pub struct LogConfig {
     pub trace: Style,
     pub info: Style,
     pub border_color: Style,
+    pub line_numbers: bool,
+    pub wrap: bool,
+    pub line_number_style: Style,
 }
 
 impl Default for LogConfig {
@@ -559,13 +728,27 @@ impl Default for LogConfig {
             trace: Style::color(Color::Magenta),
             info: Style::default(),
             border_color: Style::color(Color::Blue),
+            line_numbers: false,
+            wrap: false,
+            line_number_style: Style::color(Color::Yellow),
         }
     }
 }
 
 impl Merge for LogConfig {
     fn merge(&mut self, other: Self) {
-        merge!(self, other; error, debug, warn, trace, info, border_color);
+        merge!(
+            self, other;
+            error,
+            debug,
+            warn,
+            trace,
+            info,
+            border_color,
+            line_numbers,
+            wrap,
+            line_number_style
+        );
     }
 }
 
@@ -605,6 +788,57 @@ impl Merge for MarksConfig {
     }
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct JobsConfig {
+    /// Commands started while this many are running wait for one to finish
+    pub max_concurrent: usize,
+    /// Bytes of output kept for each command
+    pub max_output: usize,
+    /// Finished commands kept in the jobs window, with their output
+    pub max_history: usize,
+
+    pub open: KeyBind,
+    pub kill: KeyBind,
+    pub save: KeyBind,
+    pub running_style: Style,
+    pub finished_style: Style,
+    pub failed_style: Style,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 4,
+            max_output: 1_048_576,
+            max_history: 100,
+            open: KeyBind::key(Key::normal('J')),
+            kill: KeyBind::key(Key::normal('d')),
+            save: KeyBind::key(Key::normal('s')),
+            running_style: Style::color(Color::Yellow),
+            finished_style: Style::color(Color::Green),
+            failed_style: Style::color(Color::Red),
+        }
+    }
+}
+
+impl Merge for JobsConfig {
+    fn merge(&mut self, other: Self) {
+        merge!(
+            self, other;
+            max_concurrent,
+            max_output,
+            max_history,
+            open,
+            kill,
+            save,
+            running_style,
+            finished_style,
+            failed_style
+        );
+    }
+}
+
 struct ColorVisitor;
 
 impl<'de> Visitor<'de> for ColorVisitor {
@@ -622,6 +856,193 @@ impl<'de> Visitor<'de> for ColorVisitor {
     }
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct WatchConfig {
+    /// Commands re-run when files matching the glob change
+    pub commands: HashMap<String, String>,
+
+    pub open: KeyBind,
+    pub add: KeyBind,
+    pub toggle: KeyBind,
+    pub delete: KeyBind,
+    pub enabled_style: Style,
+    pub disabled_style: Style,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            commands: HashMap::new(),
+            open: KeyBind::key(Key::normal('W')),
+            add: KeyBind::key(Key::normal('a')),
+            toggle: KeyBind::key(Key::normal('t')),
+            delete: KeyBind::key(Key::normal('d')),
+            enabled_style: Style::color(Color::Green),
+            disabled_style: Style::color(Color::White),
+        }
+    }
+}
+
+impl Merge for WatchConfig {
+    fn merge(&mut self, other: Self) {
+        merge!(
+            self, other;
+            open,
+            add,
+            toggle,
+            delete,
+            enabled_style,
+            disabled_style
+        );
+        self.commands.merge(other.commands);
+    }
+}
+
+/// Keys of the input box. Other keys are typed into it.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct InputConfig {
+    pub submit: KeyBind,
+    pub cancel: KeyBind,
+    pub cursor_left: KeyBind,
+    pub cursor_right: KeyBind,
+    pub cursor_start: KeyBind,
+    pub cursor_end: KeyBind,
+    /// Delete the character before the cursor
+    pub delete: KeyBind,
+    pub delete_to_start: KeyBind,
+    pub history_older: KeyBind,
+    pub history_newer: KeyBind,
+    pub search_history: KeyBind,
+    pub vertical_split: KeyBind,
+    pub horizontal_split: KeyBind,
+    pub new_window: KeyBind,
+    pub floating_window: KeyBind,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self {
+            submit: KeyBind::key(Key::key_code(KeyCode::Enter)),
+            cancel: KeyBind::key(Key::esc()),
+            cursor_left: KeyBind::key(Key::key_code(KeyCode::Left)),
+            cursor_right: KeyBind::key(Key::key_code(KeyCode::Right)),
+            cursor_start: KeyBind::keys(vec![Key::key_code(KeyCode::Home), Key::ctrl('a')]),
+            cursor_end: KeyBind::keys(vec![Key::key_code(KeyCode::End), Key::ctrl('e')]),
+            delete: KeyBind::keys(vec![
+                Key::key_code(KeyCode::Backspace),
+                Key::key_code(KeyCode::Delete),
+            ]),
+            delete_to_start: KeyBind::key(Key::ctrl('u')),
+            history_older: KeyBind::key(Key::key_code(KeyCode::Up)),
+            history_newer: KeyBind::key(Key::key_code(KeyCode::Down)),
+            search_history: KeyBind::key(Key::ctrl('r')),
+            vertical_split: KeyBind::key(Key::ctrl('v')),
+            horizontal_split: KeyBind::key(Key::ctrl('x')),
+            new_window: KeyBind::key(Key::ctrl('n')),
+            floating_window: KeyBind::key(Key::ctrl('f')),
+        }
+    }
+}
+
+impl Merge for InputConfig {
+    fn merge(&mut self, other: Self) {
+        merge!(
+            self, other;
+            submit,
+            cancel,
+            cursor_left,
+            cursor_right,
+            cursor_start,
+            cursor_end,
+            delete,
+            delete_to_start,
+            history_older,
+            history_newer,
+            search_history,
+            vertical_split,
+            horizontal_split,
+            new_window,
+            floating_window
+        );
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct EnvConfig {
+    /// Give commands the variables in the `.env` file of the project
+    pub dotenv: bool,
+    pub precedence: EnvPrecedence,
+}
+
+impl Merge for EnvConfig {
+    fn merge(&mut self, other: Self) {
+        merge!(self, other; dotenv, precedence);
+    }
+}
+
+/// Which value commands get when a variable is both in `.env` and the environment projectable
+/// was started in
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvPrecedence {
+    #[default]
+    Environment,
+    Dotenv,
+}
+
+/// Command templates for opening panes, each run with the shell. `{cmd}` is replaced with the
+/// command to run, quoted as a single word.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct MultiplexerConfig {
+    /// Detected from the environment when not set
+    pub kind: Option<MultiplexerKind>,
+    /// Templates that replace the ones of the multiplexer in use
+    pub vertical_split: Option<String>,
+    pub horizontal_split: Option<String>,
+    pub new_window: Option<String>,
+    pub floating_window: Option<String>,
+}
+
+impl Merge for MultiplexerConfig {
+    fn merge(&mut self, other: Self) {
+        merge!(
+            self, other;
+            kind,
+            vertical_split,
+            horizontal_split,
+            new_window,
+            floating_window
+        );
+    }
+}
+
+/// Terminal multiplexers with built-in command templates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum MultiplexerKind {
+    Tmux,
+    Zellij,
+    Screen,
+    Kitty,
+    Wezterm,
+}
+
+/// The backend used to watch the filesystem for changes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatcherKind {
+    /// The platform's file change notifications
+    #[default]
+    Native,
+    /// Periodically scan the filesystem. Works on network mounts and containers
+    Poll,
+}
+
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum Color {
     Rgb(u8, u8, u8),
@@ -712,14 +1133,20 @@ impl FromStr for Color {
                     let (input, _) = delimited(multispace0, tag(")"), multispace0)(input)?;
 
                     let [r, g, b] = digits[..] else {
-                        return Err(nom::Err::Error(nom::error::Error { input, code: nom::error::ErrorKind::SeparatedList }));
+                        return Err(nom::Err::Error(nom::error::Error {
+                            input,
+                            code: nom::error::ErrorKind::SeparatedList,
+                        }));
                     };
 
                     Ok((input, Color::Rgb(r, g, b)))
                 }
 
-                // Turn into owned string so it is 'static (for anyhow)
-                let (_, color) = alt((hex_color, rgb_color))(s).map_err(|err| err.to_owned())?;
+                let (_, color) = alt((hex_color, rgb_color))(s).ok().with_context(|| {
+                    format!(
+                        "invalid colour \"{s}\", expected a name, \"#rrggbb\" or \"rgb(r, g, b)\""
+                    )
+                })?;
                 color
             }
         })
@@ -898,6 +1325,10 @@ impl FromStr for Key {
                 tag("backspace"),
                 tag("tab"),
                 tag("backtab"),
+                tag("esc"),
+                tag("home"),
+                tag("end"),
+                tag("delete"),
                 take(1usize),
             ))(input)?;
 
@@ -910,6 +1341,10 @@ impl FromStr for Key {
                 "backspace" => KeyCode::Backspace,
                 "tab" => KeyCode::Tab,
                 "backtab" => KeyCode::BackTab,
+                "esc" => KeyCode::Esc,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "delete" => KeyCode::Delete,
                 k if k.len() == 1 => {
                     KeyCode::Char(k.chars().next().expect("checked in match guard"))
                 }
@@ -961,6 +1396,10 @@ impl Display for Key {
             KeyCode::Backspace => key_parts.push_str("backspace"),
             KeyCode::Tab => key_parts.push_str("tab"),
             KeyCode::BackTab => key_parts.push_str("backtab"),
+            KeyCode::Esc => key_parts.push_str("esc"),
+            KeyCode::Home => key_parts.push_str("home"),
+            KeyCode::End => key_parts.push_str("end"),
+            KeyCode::Delete => key_parts.push_str("delete"),
             _ => panic!("key conversion not set for: \"{:?}\"", self.code),
         }
 
@@ -983,6 +1422,13 @@ impl Key {
         }
     }
 
+    pub fn alt(c: char) -> Self {
+        Self {
+            code: KeyCode::Char(c),
+            mods: KeyModifiers::ALT,
+        }
+    }
+
     pub fn esc() -> Self {
         Self {
             code: KeyCode::Esc,
@@ -1242,6 +1688,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn merging_adds_tasks() {
+        let mut lhs: Config = toml::from_str(
+            r#"
+            [tasks.build]
+            command = "cargo build"
+            "#,
+        )
+        .unwrap();
+        let rhs: Config = toml::from_str(
+            r#"
+            [tasks.run]
+            command = "cargo run"
+            description = "Run it"
+            cwd = "app"
+            foreground = true
+            depends = ["build"]
+            "#,
+        )
+        .unwrap();
+        lhs.merge(rhs);
+        assert_eq!("cargo build", lhs.tasks["build"].command);
+        assert_eq!(
+            Task {
+                command: "cargo run".to_owned(),
+                description: "Run it".to_owned(),
+                cwd: Some("app".into()),
+                foreground: true,
+                depends: vec!["build".to_owned()],
+            },
+            lhs.tasks["run"]
+        );
+    }
+
     #[test]
     fn properly_reports_keybind_conflicts() {
         let config = Config {
@@ -1297,6 +1777,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn input_keys_only_conflict_with_each_other() {
+        let mut config = Config::default();
+        // Enter also opens files, but not while typing
+        config.input.submit = KeyBind::key(Key::key_code(KeyCode::Enter));
+        assert!(config.check_conflicts().is_empty());
+
+        config.input.new_window = KeyBind::key(Key::ctrl('r'));
+        assert_eq!(
+            vec![KeyConflict {
+                on: &Key::ctrl('r'),
+                conflictors: vec![Action::InputSearchHistory, Action::InputNewWindow]
+            }],
+            config.check_conflicts()
+        );
+    }
+
+    #[test]
+    fn can_parse_named_keys() {
+        for (name, code) in [
+            ("esc", KeyCode::Esc),
+            ("home", KeyCode::Home),
+            ("end", KeyCode::End),
+            ("delete", KeyCode::Delete),
+            ("enter", KeyCode::Enter),
+            ("e", KeyCode::Char('e')),
+        ] {
+            let key = name.parse::<Key>().expect("key should parse correctly");
+            assert_eq!(Key::key_code(code), key);
+            assert_eq!(name, key.to_string());
+        }
+    }
+
     #[test]
     fn can_parse_key_with_no_mods() {
         let keys = ["a", "b", "z", "r", "d", "?"];
@@ -0,0 +1,417 @@
+use crate::config::Config;
+use globset::Glob;
+use ignore::overrides::OverrideBuilder;
+use serde::{
+    de::{MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use std::{
+    fmt::{self, Display},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+use toml::Spanned;
+
+/// A problem in a config file, at a position if it is known
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    /// Line and column, starting at 1
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(file: &Path, contents: &str, span: Option<Range<usize>>, message: String) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            position: span.map(|span| position(contents, span.start)),
+            message,
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some((line, column)) = self.position {
+            write!(f, ":{line}:{column}")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Every key and value of a TOML file, with where they are
+enum Node {
+    Table(Vec<(Spanned<String>, Spanned<Node>)>),
+    Array(Vec<Spanned<Node>>),
+    String(String),
+    Other,
+}
+
+impl Node {
+    /// Value of `key`, if this is a table that has it
+    fn get(&self, key: &str) -> Option<&Node> {
+        let Node::Table(entries) = self else {
+            return None;
+        };
+        entries
+            .iter()
+            .find(|(name, _)| name.get_ref() == key)
+            .map(|(_, value)| value.get_ref())
+    }
+
+    /// Keys of this table
+    fn keys(&self) -> Vec<&Spanned<String>> {
+        match self {
+            Node::Table(entries) => entries.iter().map(|(key, _)| key).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Strings in this array, with where they are
+    fn strings(&self) -> Vec<(&str, Range<usize>)> {
+        let Node::Array(items) = self else {
+            return Vec::new();
+        };
+        items
+            .iter()
+            .filter_map(|item| match item.get_ref() {
+                Node::String(string) => Some((string.as_str(), item.span())),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl<'de> Deserialize<'de> for Node {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(NodeVisitor)
+    }
+}
+
+struct NodeVisitor;
+
+impl<'de> Visitor<'de> for NodeVisitor {
+    type Value = Node;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a TOML value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<Node, E> {
+        Ok(Node::Other)
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<Node, E> {
+        Ok(Node::Other)
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<Node, E> {
+        Ok(Node::Other)
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<Node, E> {
+        Ok(Node::Other)
+    }
+
+    fn visit_str<E>(self, string: &str) -> Result<Node, E> {
+        Ok(Node::String(string.to_owned()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Node, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Node::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Node, A::Error> {
+        let mut entries = Vec::new();
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(Node::Table(entries))
+    }
+}
+
+/// A glob of the config that does not compile
+struct InvalidGlob {
+    span: Range<usize>,
+    message: String,
+    /// Whether deserializing the config fails on it
+    fails_config: bool,
+}
+
+/// Every glob in `nodes` that does not compile the way it is compiled when it is used
+fn invalid_globs(nodes: &Node) -> Vec<InvalidGlob> {
+    let mut invalid = Vec::new();
+    let mut check = |glob: &str, span: Range<usize>, kind: &str, error: Option<String>| {
+        if let Some(error) = error {
+            invalid.push(InvalidGlob {
+                span,
+                message: format!("invalid {kind} glob \"{glob}\": {error}"),
+                fails_config: kind == "project root",
+            });
+        }
+    };
+    // Project roots and special commands match anywhere, like the config does
+    let anywhere = |glob: &str| {
+        Glob::new(&format!("**/{glob}"))
+            .err()
+            .map(|err| err.kind().to_string())
+    };
+
+    for (glob, span) in nodes
+        .get("project_roots")
+        .map_or_else(Vec::new, Node::strings)
+    {
+        check(glob, span, "project root", anywhere(glob));
+    }
+    let ignore = nodes
+        .get("filetree")
+        .and_then(|filetree| filetree.get("ignore"));
+    for (glob, span) in ignore.map_or_else(Vec::new, Node::strings) {
+        let error = OverrideBuilder::new("/").add(glob).err();
+        let error = error.map(|err| match err {
+            ignore::Error::Glob { err, .. } => err,
+            err => err.to_string(),
+        });
+        check(glob, span, "ignore", error);
+    }
+    for key in nodes
+        .get("special_commands")
+        .map_or_else(Vec::new, Node::keys)
+    {
+        let glob = key.get_ref();
+        check(glob, key.span(), "special command", anywhere(glob));
+    }
+    let watches = nodes.get("watch").and_then(|watch| watch.get("commands"));
+    for key in watches.map_or_else(Vec::new, Node::keys) {
+        let glob = key.get_ref();
+        let error = Glob::new(glob).err().map(|err| err.kind().to_string());
+        check(glob, key.span(), "watch", error);
+    }
+    invalid
+}
+
+/// Byte range of the innermost key and value, or array item, in `node` that has `offset`
+/// inside it
+fn entry_at(node: &Node, offset: usize) -> Option<Range<usize>> {
+    let contains = |span: &Range<usize>| span.contains(&offset);
+    match node {
+        Node::Table(entries) => entries.iter().find_map(|(key, value)| {
+            let entry = key.span().start..value.span().end;
+            if !contains(&entry) {
+                return None;
+            }
+            entry_at(value.get_ref(), offset).or(Some(entry))
+        }),
+        Node::Array(items) => items.iter().find_map(|item| {
+            let span = item.span();
+            if !contains(&span) {
+                return None;
+            }
+            entry_at(item.get_ref(), offset).or(Some(span))
+        }),
+        Node::String(_) | Node::Other => None,
+    }
+}
+
+/// Replace `span` of `contents` with spaces, along with the comma separating it from the next
+/// or previous item. A table with a `[header]` is blanked out up to the next header. Lines and
+/// columns of everything else stay the same.
+fn blank_out(contents: &mut String, span: Range<usize>) {
+    let whitespace = [' ', '\t', '\n', '\r'];
+    let mut span = span;
+    let before = contents[..span.start].trim_end_matches(whitespace);
+    if before.ends_with('[') && before[..before.len() - 1].ends_with(['\n', '[']) || before == "[" {
+        span.start = before.trim_end_matches('[').len();
+        let next_header = contents[span.start..]
+            .match_indices('\n')
+            .map(|(i, _)| span.start + i + 1)
+            .find(|&line| {
+                contents[line..]
+                    .trim_start_matches([' ', '\t'])
+                    .starts_with('[')
+            });
+        span.end = next_header.unwrap_or(contents.len());
+    } else {
+        let after = contents[span.end..].trim_start_matches(whitespace);
+        if after.starts_with(',') {
+            span.end = contents.len() - after.len() + 1;
+        } else if before.ends_with(',') {
+            span.start = before.len() - 1;
+        }
+    }
+    let blank: String = contents[span.clone()]
+        .chars()
+        .map(|c| if c == '\n' { '\n' } else { ' ' })
+        .collect();
+    contents.replace_range(span, &blank);
+}
+
+/// Parse the config file at `file` with `contents`. Gives everything wrong with it, and the
+/// config without the parts that have problems if the file is valid TOML.
+///
+/// Globs are checked first. Then each part that fails to deserialize, like an unknown key or
+/// an invalid colour, is blanked out and the file parsed again, to find the problems after it.
+pub fn check_file(file: &Path, contents: &str) -> (Option<Config>, Vec<Diagnostic>) {
+    let nodes = match toml::from_str::<Node>(contents) {
+        Ok(nodes) => nodes,
+        Err(err) => {
+            let diagnostic = Diagnostic::new(file, contents, err.span(), err.message().to_owned());
+            return (None, vec![diagnostic]);
+        }
+    };
+
+    let mut diagnostics = Vec::new();
+    let mut remaining = contents.to_owned();
+    for glob in invalid_globs(&nodes) {
+        if glob.fails_config {
+            blank_out(&mut remaining, glob.span.clone());
+        }
+        diagnostics.push(Diagnostic::new(
+            file,
+            contents,
+            Some(glob.span),
+            glob.message,
+        ));
+    }
+
+    let config = loop {
+        let err = match toml::from_str::<Config>(&remaining) {
+            Ok(config) => break Some(config),
+            Err(err) => err,
+        };
+        diagnostics.push(Diagnostic::new(
+            file,
+            contents,
+            err.span(),
+            err.message().to_owned(),
+        ));
+        let Some(entry) = err.span().and_then(|span| entry_at(&nodes, span.start)) else {
+            break None;
+        };
+        let before = remaining.clone();
+        blank_out(&mut remaining, entry);
+        // Blanking out should always leave valid TOML, but never loop forever
+        if remaining == before || toml::from_str::<Node>(&remaining).is_err() {
+            break None;
+        }
+    };
+    diagnostics.sort_by_key(|diagnostic| diagnostic.position);
+
+    (config, diagnostics)
+}
+
+/// Line and column of the byte at `offset`, starting at 1
+fn position(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset.min(contents.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::Itertools;
+    use test_log::test;
+
+    fn check(contents: &str) -> Vec<String> {
+        check_file(Path::new("config.toml"), contents)
+            .1
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn valid_config_has_no_problems() {
+        let (config, diagnostics) = check_file(
+            Path::new("config.toml"),
+            include_str!("./config_defaults/unix.toml"),
+        );
+        assert!(config.is_some());
+        assert_eq!(Vec::<Diagnostic>::new(), diagnostics);
+    }
+
+    #[test]
+    fn parse_errors_have_line_and_column() {
+        let problems = check("quit = \"q\"\n\n[filetree]\nnot_an_option = true\n");
+        assert_eq!(1, problems.len());
+        assert!(problems[0].starts_with("config.toml:4:1: unknown field `not_an_option`"));
+
+        let problems = check("quit = \"q\"\nselected = { color = \"notacolour\" }\n");
+        assert_eq!(1, problems.len(), "{problems:?}");
+        assert!(problems[0].starts_with("config.toml:2:22: invalid colour \"notacolour\""));
+
+        let problems = check("project_roots = [\"[\"]\n");
+        assert_eq!(1, problems.len());
+        assert!(problems[0].starts_with("config.toml:1:18: invalid project root glob \"[\""));
+    }
+
+    #[test]
+    fn finds_every_deserialize_error() {
+        let problems = check(concat!(
+            "quit = \"q\"\n",
+            "selected = { color = \"notacolour\" }\n",
+            "project_roots = [\"[\", \"ok\", \"{a\"]\n",
+            "\n",
+            "[filetree]\n",
+            "not_an_option = true\n",
+            "ignore = [\"[\"]\n",
+            "\n",
+            "[not_a_table]\n",
+            "a = 1\n",
+            "b = [\n",
+            "  2,\n",
+            "]\n",
+            "\n",
+            "[preview]\n",
+            "wrap = \"yes\"\n",
+        ));
+        let positions = problems
+            .iter()
+            .map(|problem| problem.split(": ").next().unwrap())
+            .collect_vec();
+        assert_eq!(
+            vec![
+                "config.toml:2:22",
+                "config.toml:3:18",
+                "config.toml:3:29",
+                "config.toml:6:1",
+                "config.toml:7:11",
+                "config.toml:9:2",
+                "config.toml:16:8",
+            ],
+            positions,
+            "{problems:#?}"
+        );
+        assert!(problems[2].contains("invalid project root glob \"{a\""));
+        assert!(problems[5].contains("unknown field `not_a_table`"));
+        assert!(problems[6].contains("invalid type: string \"yes\", expected a boolean"));
+    }
+
+    #[test]
+    fn finds_every_invalid_glob() {
+        let problems = check(
+            "[special_commands]\n\"{a\" = [\"ls\"]\n\"*.rs\" = [\"cargo\"]\n\n[filetree]\nignore = [\"ok\", \"[\"]\n",
+        );
+        assert_eq!(2, problems.len(), "{problems:?}");
+        assert!(problems[0].starts_with("config.toml:2:1: invalid special command glob \"{a\""));
+        assert!(problems[1]
+            .starts_with("config.toml:6:17: invalid ignore glob \"[\": unclosed character class"));
+    }
+
+    #[test]
+    fn finds_position_of_offset() {
+        assert_eq!((1, 1), position("", 0));
+        assert_eq!((2, 3), position("a\nbcd", 4));
+        assert_eq!((1, 3), position("éé", 4));
+    }
+}
@@ -0,0 +1,120 @@
+use super::ExternalEvent;
+use anyhow::Result;
+use crossbeam_channel::Sender;
+use itertools::Itertools;
+use log::debug;
+use notify_debouncer_full::{
+    new_debouncer,
+    notify::{event::ModifyKind, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher},
+    DebounceEventResult, Debouncer, FileIdMap,
+};
+use std::{path::PathBuf, time::Duration};
+
+/// Watches the config files. Dropping it stops watching
+pub struct ConfigWatcher {
+    _debouncer: Debouncer<RecommendedWatcher, FileIdMap>,
+}
+
+/// Send `ExternalEvent::ConfigChanged` to `event_sender` whenever one of `files` is written,
+/// created or removed, debounced for `debounce`.
+///
+/// The directories the files are in are watched instead of the files themselves, since editors
+/// tend to save by replacing the file. Files in directories that don't exist are not watched.
+pub fn config_watch(
+    files: &[PathBuf],
+    debounce: Duration,
+    event_sender: Sender<ExternalEvent>,
+) -> Result<ConfigWatcher> {
+    // Paths of events are under the watched directories, which are canonical
+    let files = files
+        .iter()
+        .filter_map(|file| Some(file.parent()?.canonicalize().ok()?.join(file.file_name()?)))
+        .collect_vec();
+    let handler_files = files.clone();
+    let handler = move |result: DebounceEventResult| match result {
+        Ok(events) => {
+            if events
+                .iter()
+                .any(|event| changes_config(event, &handler_files))
+            {
+                drop(event_sender.send(ExternalEvent::ConfigChanged));
+            }
+        }
+        Err(errs) => {
+            for err in errs {
+                drop(event_sender.send(ExternalEvent::Error(err.into())));
+            }
+        }
+    };
+    let mut debouncer = new_debouncer(debounce, None, handler)?;
+    for dir in files.iter().filter_map(|file| file.parent()).unique() {
+        if let Err(err) = debouncer.watcher().watch(dir, RecursiveMode::NonRecursive) {
+            debug!("failed to watch \"{}\": {err}", dir.display());
+        }
+    }
+    Ok(ConfigWatcher {
+        _debouncer: debouncer,
+    })
+}
+
+/// Whether `event` changed the contents of one of `files`. Reading them, like when the config
+/// is reloaded, does not count.
+fn changes_config(event: &Event, files: &[PathBuf]) -> bool {
+    let writes = match event.kind {
+        EventKind::Modify(ModifyKind::Metadata(_)) => false,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => true,
+        _ => false,
+    };
+    writes && event.paths.iter().any(|path| files.contains(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use crossbeam_channel::unbounded;
+    use notify_debouncer_full::notify::event::{AccessKind, DataChange, MetadataKind};
+    use test_log::test;
+
+    #[test]
+    fn only_writes_to_config_files_count() {
+        let files = [PathBuf::from("/home/.projectable.toml")];
+        let event = |kind| Event::new(kind).add_path("/home/.projectable.toml".into());
+        assert!(changes_config(
+            &event(EventKind::Modify(ModifyKind::Data(DataChange::Content))),
+            &files
+        ));
+        assert!(!changes_config(
+            &event(EventKind::Access(AccessKind::Read)),
+            &files
+        ));
+        assert!(!changes_config(
+            &event(EventKind::Modify(ModifyKind::Metadata(
+                MetadataKind::AccessTime
+            ))),
+            &files
+        ));
+        let other = Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Content)))
+            .add_path("/home/notes.toml".into());
+        assert!(!changes_config(&other, &files));
+    }
+
+    #[test]
+    fn reports_changed_config_file() {
+        let temp = TempDir::new().unwrap();
+        let (tx, rx) = unbounded();
+        let _watcher = config_watch(
+            &[temp.child("config.toml").to_path_buf()],
+            Duration::from_millis(50),
+            tx,
+        )
+        .unwrap();
+
+        temp.child("other.toml").write_str("quit = \"q\"").unwrap();
+        temp.child("config.toml").write_str("quit = \"q\"").unwrap();
+        let Ok(ExternalEvent::ConfigChanged) = rx.recv_timeout(Duration::from_secs(5)) else {
+            panic!("should have reported change");
+        };
+        temp.close().unwrap();
+    }
+}
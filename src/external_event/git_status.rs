@@ -0,0 +1,241 @@
+use super::ExternalEvent;
+use anyhow::{Context, Result};
+use crossbeam_channel::{unbounded, Sender};
+use git2::{Repository, Status, StatusOptions};
+use log::debug;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    thread,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatusRequest {
+    /// Get the status of the whole repository
+    Full,
+    /// Get the status of these paths and everything under them
+    Paths(Vec<PathBuf>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatusUpdate {
+    Full(HashMap<PathBuf, Status>),
+    /// Replaces the statuses of `paths` and everything under them. Paths that are not in
+    /// `statuses` are clean.
+    Partial {
+        paths: Vec<PathBuf>,
+        statuses: HashMap<PathBuf, Status>,
+    },
+}
+
+impl StatusUpdate {
+    pub fn apply(self, cache: &mut HashMap<PathBuf, Status>) {
+        match self {
+            Self::Full(statuses) => *cache = statuses,
+            Self::Partial { paths, statuses } => {
+                cache.retain(|path, _| !paths.iter().any(|changed| path.starts_with(changed)));
+                cache.extend(statuses);
+            }
+        }
+    }
+}
+
+/// Get the statuses asked for by `request`. Files are looked up directly, directories use a
+/// pathspec so only that part of the working tree is scanned.
+pub fn get_statuses(
+    repo: &Repository,
+    root: &Path,
+    request: StatusRequest,
+) -> Result<StatusUpdate> {
+    let to_map = |statuses: git2::Statuses| {
+        statuses
+            .iter()
+            .filter_map(|status| Some((root.join(status.path()?), status.status())))
+            .collect::<HashMap<_, _>>()
+    };
+    let paths = match request {
+        StatusRequest::Full => {
+            let statuses = repo
+                .statuses(None)
+                .context("failed to get repository status")?;
+            return Ok(StatusUpdate::Full(to_map(statuses)));
+        }
+        StatusRequest::Paths(paths) => paths,
+    };
+
+    let mut statuses = HashMap::new();
+    for path in &paths {
+        let Ok(relative) = path.strip_prefix(root) else {
+            continue;
+        };
+        if path.is_dir() {
+            let mut opts = StatusOptions::new();
+            // Directory names are not globs
+            opts.pathspec(relative)
+                .disable_pathspec_match(true)
+                .include_untracked(true)
+                .recurse_untracked_dirs(true)
+                .include_ignored(true);
+            let dir_statuses = repo
+                .statuses(Some(&mut opts))
+                .with_context(|| format!("failed to get status of \"{}\"", path.display()))?;
+            statuses.extend(to_map(dir_statuses));
+        } else {
+            // Fails for files that are neither on disk nor in the index
+            match repo.status_file(relative) {
+                Ok(status) if status != Status::CURRENT => {
+                    statuses.insert(path.clone(), status);
+                }
+                Ok(_) => {}
+                Err(err) => debug!("no status for \"{}\": {err}", path.display()),
+            }
+        }
+    }
+
+    Ok(StatusUpdate::Partial { paths, statuses })
+}
+
+/// Computes git statuses on a background thread, so large repositories don't block the UI.
+/// Results are sent back as [`ExternalEvent::GitStatus`].
+#[derive(Debug, Clone)]
+pub struct GitStatusWorker {
+    requests: Sender<StatusRequest>,
+}
+
+impl GitStatusWorker {
+    pub fn spawn(root: PathBuf, event_sender: Sender<ExternalEvent>) -> Result<Self> {
+        let repo =
+            Repository::open(root.join(".git")).context("failed to open repository for status")?;
+        let (tx, rx) = unbounded::<StatusRequest>();
+        thread::spawn(move || {
+            while let Ok(request) = rx.recv() {
+                // Combine requests that piled up while the last one was running
+                let request = rx
+                    .try_iter()
+                    .fold(request, |acc, request| match (acc, request) {
+                        (StatusRequest::Paths(mut paths), StatusRequest::Paths(more)) => {
+                            paths.extend(more);
+                            StatusRequest::Paths(paths)
+                        }
+                        _ => StatusRequest::Full,
+                    });
+                match get_statuses(&repo, &root, request) {
+                    Ok(update) => {
+                        if event_sender.send(ExternalEvent::GitStatus(update)).is_err() {
+                            return;
+                        }
+                    }
+                    // The index might be locked by a running git command. It will be refreshed
+                    // again once that command changes it.
+                    Err(err) => debug!("{err:#}"),
+                }
+            }
+        });
+
+        Ok(Self { requests: tx })
+    }
+
+    /// Ask the worker for statuses. Gives back `request` if the worker has stopped.
+    pub fn request(&self, request: StatusRequest) -> Result<(), StatusRequest> {
+        self.requests.send(request).map_err(|err| err.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use std::time::Duration;
+    use test_log::test;
+
+    fn temp_repo() -> (TempDir, Repository) {
+        let temp = TempDir::new().unwrap();
+        let repo = Repository::init(temp.path()).unwrap();
+        (temp, repo)
+    }
+
+    #[test]
+    fn partial_status_only_includes_requested_paths() {
+        let (temp, repo) = temp_repo();
+        temp.child("test.txt").touch().unwrap();
+        temp.child("dir/test2.txt").touch().unwrap();
+        temp.child("other/test3.txt").touch().unwrap();
+
+        let update = get_statuses(
+            &repo,
+            temp.path(),
+            StatusRequest::Paths(vec![temp.path().join("test.txt"), temp.path().join("dir")]),
+        )
+        .unwrap();
+        let StatusUpdate::Partial { statuses, .. } = update else {
+            panic!("should be a partial update");
+        };
+        assert_eq!(
+            HashMap::from([
+                (temp.path().join("test.txt"), Status::WT_NEW),
+                (temp.path().join("dir/test2.txt"), Status::WT_NEW),
+            ]),
+            statuses
+        );
+    }
+
+    #[test]
+    fn directories_are_not_treated_as_globs() {
+        let (temp, repo) = temp_repo();
+        temp.child("[d]/test.txt").touch().unwrap();
+        temp.child("d/test2.txt").touch().unwrap();
+
+        let update = get_statuses(
+            &repo,
+            temp.path(),
+            StatusRequest::Paths(vec![temp.path().join("[d]")]),
+        )
+        .unwrap();
+        let StatusUpdate::Partial { statuses, .. } = update else {
+            panic!("should be a partial update");
+        };
+        assert_eq!(
+            HashMap::from([(temp.path().join("[d]/test.txt"), Status::WT_NEW)]),
+            statuses
+        );
+    }
+
+    #[test]
+    fn partial_update_replaces_statuses_under_paths() {
+        let mut cache = HashMap::from([
+            (PathBuf::from("/root/dir/test.txt"), Status::WT_NEW),
+            (PathBuf::from("/root/test.txt"), Status::WT_MODIFIED),
+        ]);
+        StatusUpdate::Partial {
+            paths: vec!["/root/dir".into()],
+            statuses: HashMap::from([(PathBuf::from("/root/dir/test2.txt"), Status::WT_NEW)]),
+        }
+        .apply(&mut cache);
+        assert_eq!(
+            HashMap::from([
+                (PathBuf::from("/root/dir/test2.txt"), Status::WT_NEW),
+                (PathBuf::from("/root/test.txt"), Status::WT_MODIFIED),
+            ]),
+            cache
+        );
+    }
+
+    #[test]
+    fn worker_sends_statuses() {
+        let (temp, _repo) = temp_repo();
+        temp.child("test.txt").touch().unwrap();
+        let (tx, rx) = unbounded();
+        let worker = GitStatusWorker::spawn(temp.path().to_path_buf(), tx).unwrap();
+
+        worker.request(StatusRequest::Full).unwrap();
+        let Ok(ExternalEvent::GitStatus(StatusUpdate::Full(statuses))) =
+            rx.recv_timeout(Duration::from_secs(5))
+        else {
+            panic!("should have sent status");
+        };
+        assert_eq!(
+            Some(&Status::WT_NEW),
+            statuses.get(&temp.path().join("test.txt"))
+        );
+    }
+}
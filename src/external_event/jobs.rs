@@ -0,0 +1,605 @@
+use super::ExternalEvent;
+use anyhow::{Context, Result};
+use crossbeam_channel::Sender;
+use duct::{Expression, ReaderHandle};
+use itertools::Itertools;
+use log::error;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::{self, Display},
+    io::{BufRead, BufReader},
+    process,
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+pub type JobId = usize;
+
+/// How a command that ran to completion exited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Code(i32),
+    /// Ended by a signal, which only happens on Unix
+    Signal(i32),
+    Unknown,
+}
+
+impl ExitStatus {
+    pub fn success(&self) -> bool {
+        *self == Self::Code(0)
+    }
+}
+
+impl From<process::ExitStatus> for ExitStatus {
+    fn from(status: process::ExitStatus) -> Self {
+        if let Some(code) = status.code() {
+            return Self::Code(code);
+        }
+        #[cfg(unix)]
+        if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+            return Self::Signal(signal);
+        }
+        Self::Unknown
+    }
+}
+
+impl Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Code(code) => write!(f, "exit {code}"),
+            Self::Signal(signal) => write!(f, "signal {signal}"),
+            Self::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    /// Waiting for other jobs to finish, because too many are running
+    Queued,
+    Running,
+    Finished(ExitStatus),
+    Killed,
+    /// The process could not be started or waited on
+    Failed,
+}
+
+impl JobStatus {
+    pub fn success(&self) -> bool {
+        matches!(self, Self::Finished(status) if status.success())
+    }
+}
+
+impl Display for JobStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Queued => write!(f, "queued"),
+            Self::Running => write!(f, "running"),
+            Self::Finished(status) => write!(f, "{status}"),
+            Self::Killed => write!(f, "killed"),
+            Self::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// A background command started by projectable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    pub id: JobId,
+    pub command: String,
+    pub pid: Option<u32>,
+    pub status: JobStatus,
+    started: Option<Instant>,
+    ended: Option<Instant>,
+}
+
+impl Job {
+    /// How long the job has been running for, or how long it ran for if it is done. `None` if
+    /// it never started.
+    pub fn runtime(&self) -> Option<Duration> {
+        let started = self.started?;
+        Some(self.ended.unwrap_or_else(Instant::now) - started)
+    }
+
+    pub fn is_done(&self) -> bool {
+        !matches!(self.status, JobStatus::Queued | JobStatus::Running)
+    }
+}
+
+/// Output of a job, oldest lines first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JobOutput {
+    lines: VecDeque<String>,
+    size: usize,
+    truncated: bool,
+}
+
+impl JobOutput {
+    /// Add a line, forgetting the oldest ones while over `max_size` bytes
+    fn push(&mut self, line: String, max_size: usize) {
+        self.size += line.len() + 1;
+        self.lines.push_back(line);
+        while self.size > max_size {
+            let Some(oldest) = self.lines.pop_front() else {
+                break;
+            };
+            self.size -= oldest.len() + 1;
+            self.truncated = true;
+        }
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().map(String::as_str)
+    }
+
+    pub fn text(&self) -> String {
+        self.lines().join("\n")
+    }
+
+    /// Whether the oldest lines were forgotten
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+#[derive(Default)]
+struct JobTable {
+    jobs: Vec<Job>,
+    outputs: HashMap<JobId, JobOutput>,
+    /// Bytes of output kept for each job
+    max_output: usize,
+    /// Finished jobs kept, with their output
+    max_history: usize,
+    handles: HashMap<JobId, Arc<ReaderHandle>>,
+    queue: VecDeque<(JobId, Expression)>,
+    max_concurrent: usize,
+    next_id: JobId,
+    sender: Option<Sender<ExternalEvent>>,
+}
+
+impl JobTable {
+    /// Forget the oldest finished jobs and their output while there are more than
+    /// `max_history`. Jobs still being read from are kept.
+    fn forget_old(&mut self) {
+        let done = self.jobs.iter().filter(|job| job.is_done()).count();
+        let mut excess = done.saturating_sub(self.max_history);
+        if excess == 0 {
+            return;
+        }
+        let mut forgotten = Vec::new();
+        self.jobs.retain(|job| {
+            let forget = excess > 0 && job.is_done() && !self.handles.contains_key(&job.id);
+            if forget {
+                excess -= 1;
+                forgotten.push(job.id);
+            }
+            !forget
+        });
+        for id in forgotten {
+            self.outputs.remove(&id);
+        }
+    }
+
+    fn get_mut(&mut self, id: JobId) -> &mut Job {
+        self.jobs
+            .iter_mut()
+            .find(|job| job.id == id)
+            .expect("job should be in table")
+    }
+
+    fn running(&self) -> usize {
+        self.jobs
+            .iter()
+            .filter(|job| job.status == JobStatus::Running)
+            .count()
+    }
+
+    fn send(&self, event: ExternalEvent) {
+        if let Some(sender) = &self.sender {
+            // The receiver is only gone when projectable is shutting down
+            drop(sender.send(event));
+        }
+    }
+}
+
+/// Keeps track of every background command. At most `max_concurrent` jobs run at once, the
+/// rest wait in a queue. Cloning gives another handle to the same jobs.
+#[derive(Clone)]
+pub struct Jobs {
+    table: Arc<Mutex<JobTable>>,
+}
+
+impl Jobs {
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            table: Arc::new(Mutex::new(JobTable {
+                max_concurrent: max_concurrent.max(1),
+                max_output: usize::MAX,
+                max_history: usize::MAX,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Only keep the last `bytes` of output for each job
+    #[must_use]
+    pub fn max_output(self, bytes: usize) -> Self {
+        self.lock().max_output = bytes;
+        self
+    }
+
+    /// Only keep the last `jobs` finished jobs
+    #[must_use]
+    pub fn max_history(self, jobs: usize) -> Self {
+        self.lock().max_history = jobs;
+        self
+    }
+
+    /// Change how many jobs run at once, how much output is kept of each and how many finished
+    /// ones are kept. Queued jobs start if there is room for them now.
+    pub fn set_limits(&self, max_concurrent: usize, max_output: usize, max_history: usize) {
+        let mut table = self.lock();
+        table.max_concurrent = max_concurrent.max(1);
+        table.max_output = max_output;
+        table.max_history = max_history;
+        table.forget_old();
+        self.start_queued(&mut table);
+    }
+
+    /// Send job output, completions and errors to `sender`
+    pub fn set_sender(&self, sender: Sender<ExternalEvent>) {
+        self.lock().sender = Some(sender);
+    }
+
+    /// Start `expr`, or queue it if too many jobs are running. `command` is what is shown to
+    /// the user. Only the stdout of `expr` is read, so stderr should be redirected to it.
+    pub fn spawn(&self, command: impl Into<String>, expr: Expression) -> Result<JobId> {
+        let mut table = self.lock();
+        let id = table.next_id;
+        table.next_id += 1;
+        table.jobs.push(Job {
+            id,
+            command: command.into(),
+            pid: None,
+            status: JobStatus::Queued,
+            started: None,
+            ended: None,
+        });
+        if table.running() < table.max_concurrent {
+            self.start(&mut table, id, expr)?;
+        } else {
+            table.queue.push_back((id, expr));
+        }
+        Ok(id)
+    }
+
+    /// Kill a running job, or take it out of the queue. Does nothing if it is already done or
+    /// was forgotten.
+    pub fn kill(&self, id: JobId) -> Result<()> {
+        let mut table = self.lock();
+        let Some(job) = table.jobs.iter_mut().find(|job| job.id == id) else {
+            return Ok(());
+        };
+        match job.status {
+            JobStatus::Queued => {
+                job.status = JobStatus::Killed;
+                table.queue.retain(|(queued, _)| *queued != id);
+            }
+            JobStatus::Running => {
+                job.status = JobStatus::Killed;
+                job.ended = Some(Instant::now());
+                if let Some(handle) = table.handles.get(&id) {
+                    handle
+                        .kill()
+                        .with_context(|| format!("failed to kill job {id}"))?;
+                }
+                self.start_queued(&mut table);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Kill every running job and empty the queue
+    pub fn kill_all(&self) -> Result<()> {
+        let ids = {
+            let mut table = self.lock();
+            for (id, _) in std::mem::take(&mut table.queue) {
+                table.get_mut(id).status = JobStatus::Killed;
+            }
+            table
+                .jobs
+                .iter()
+                .filter(|job| job.status == JobStatus::Running)
+                .map(|job| job.id)
+                .collect::<Vec<_>>()
+        };
+        for id in ids {
+            self.kill(id)?;
+        }
+        Ok(())
+    }
+
+    /// A snapshot of every job, in the order they were spawned
+    pub fn jobs(&self) -> Vec<Job> {
+        self.lock().jobs.clone()
+    }
+
+    pub fn running(&self) -> usize {
+        self.lock().running()
+    }
+
+    /// Everything a job printed, as far as it is kept
+    pub fn output(&self, id: JobId) -> JobOutput {
+        self.lock().outputs.get(&id).cloned().unwrap_or_default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, JobTable> {
+        self.table.lock().expect("job table should not be poisoned")
+    }
+
+    fn start(&self, table: &mut JobTable, id: JobId, expr: Expression) -> Result<()> {
+        let job = table.get_mut(id);
+        let handle = match expr.reader() {
+            Ok(handle) => Arc::new(handle),
+            Err(err) => {
+                job.status = JobStatus::Failed;
+                return Err(err).with_context(|| format!("failed to start \"{}\"", job.command));
+            }
+        };
+        job.pid = handle.pids().first().copied();
+        job.status = JobStatus::Running;
+        job.started = Some(Instant::now());
+        table.handles.insert(id, Arc::clone(&handle));
+
+        let jobs = self.clone();
+        thread::spawn(move || jobs.stream(id, &handle));
+        Ok(())
+    }
+
+    /// Start queued jobs until the limit is reached again
+    fn start_queued(&self, table: &mut JobTable) {
+        while table.running() < table.max_concurrent {
+            let Some((id, expr)) = table.queue.pop_front() else {
+                return;
+            };
+            if let Err(err) = self.start(table, id, expr) {
+                error!("{err:#}");
+            }
+        }
+    }
+
+    /// Send each line of output as it arrives, then record how the job ended
+    fn stream(&self, id: JobId, handle: &ReaderHandle) {
+        let sender = self.lock().sender.clone();
+        let send = |event| {
+            if let Some(sender) = &sender {
+                drop(sender.send(event));
+            }
+        };
+
+        // Must not hold the lock while reading, the job might be killed in the meantime
+        let mut reader = BufReader::new(handle);
+        let mut line = Vec::new();
+        let result = loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                // The reader waits for the process once it hits EOF
+                Ok(0) => {
+                    break handle
+                        .try_wait()
+                        .map(|out| out.map_or(ExitStatus::Unknown, |out| out.status.into()))
+                }
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(trim_newline(&line)).into_owned();
+                    {
+                        let mut table = self.lock();
+                        let max_output = table.max_output;
+                        table
+                            .outputs
+                            .entry(id)
+                            .or_default()
+                            .push(line.clone(), max_output);
+                    }
+                    send(ExternalEvent::JobOutput { id, line });
+                }
+                Err(err) => break Err(err),
+            }
+        };
+
+        let mut table = self.lock();
+        table.handles.remove(&id);
+        let job = table.get_mut(id);
+        let killed = job.status == JobStatus::Killed;
+        let mut wait_error = None;
+        if !killed {
+            job.ended = Some(Instant::now());
+            job.status = match result {
+                Ok(status) => JobStatus::Finished(status),
+                Err(err) => {
+                    wait_error = Some(err);
+                    JobStatus::Failed
+                }
+            };
+        }
+        let finished = ExternalEvent::JobFinished {
+            id,
+            command: job.command.clone(),
+            status: job.status,
+            runtime: job.runtime().unwrap_or_default(),
+        };
+        if let Some(err) = wait_error {
+            table.send(ExternalEvent::Error(err.into()));
+        }
+        table.send(finished);
+        table.forget_old();
+        // Killed jobs already made room for queued ones
+        if !killed {
+            self.start_queued(&mut table);
+        }
+    }
+}
+
+fn trim_newline(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use duct::cmd;
+    use test_log::test;
+
+    /// Wait until `id` has `status`, panicking if it takes too long
+    fn wait_for(jobs: &Jobs, id: JobId, status: JobStatus) {
+        let start = Instant::now();
+        while !jobs
+            .jobs()
+            .iter()
+            .any(|job| job.id == id && job.status == status)
+        {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "job {id} never reached {status}"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn finished_jobs_keep_exit_code() {
+        let jobs = Jobs::new(2);
+        let id = jobs
+            .spawn("exit 3", cmd!("sh", "-c", "exit 3").unchecked())
+            .unwrap();
+        wait_for(&jobs, id, JobStatus::Finished(ExitStatus::Code(3)));
+        let job = &jobs.jobs()[id];
+        assert!(job.pid.is_some());
+        assert!(job.is_done());
+        assert!(job.runtime().is_some());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn jobs_ended_by_signal_keep_signal() {
+        let jobs = Jobs::new(1);
+        let id = jobs
+            .spawn("kill", cmd!("sh", "-c", "kill -9 $$").unchecked())
+            .unwrap();
+        wait_for(&jobs, id, JobStatus::Finished(ExitStatus::Signal(9)));
+        assert_eq!("signal 9", jobs.jobs()[id].status.to_string());
+        assert!(!jobs.jobs()[id].status.success());
+    }
+
+    #[test]
+    fn output_is_kept_up_to_max_size() {
+        let jobs = Jobs::new(1).max_output(9);
+        let id = jobs
+            .spawn(
+                "seq",
+                cmd!("sh", "-c", "echo 1; echo 22; echo 333; echo 4444"),
+            )
+            .unwrap();
+        wait_for(&jobs, id, JobStatus::Finished(ExitStatus::Code(0)));
+        let output = jobs.output(id);
+        assert_eq!("333\n4444", output.text());
+        assert!(output.is_truncated());
+        assert_eq!(JobOutput::default(), jobs.output(id + 1));
+    }
+
+    #[test]
+    fn jobs_over_limit_are_queued() {
+        let jobs = Jobs::new(1);
+        let first = jobs.spawn("sleep", cmd!("sleep", "5")).unwrap();
+        let second = jobs.spawn("sleep", cmd!("sleep", "5")).unwrap();
+        assert_eq!(JobStatus::Running, jobs.jobs()[first].status);
+        assert_eq!(JobStatus::Queued, jobs.jobs()[second].status);
+        assert_eq!(None, jobs.jobs()[second].runtime());
+
+        jobs.kill(first).unwrap();
+        assert_eq!(JobStatus::Killed, jobs.jobs()[first].status);
+        assert_eq!(JobStatus::Running, jobs.jobs()[second].status);
+        jobs.kill_all().unwrap();
+        assert_eq!(0, jobs.running());
+    }
+
+    #[test]
+    fn raising_limit_starts_queued_jobs() {
+        let jobs = Jobs::new(1);
+        jobs.spawn("sleep", cmd!("sleep", "5")).unwrap();
+        let second = jobs.spawn("sleep", cmd!("sleep", "5")).unwrap();
+        assert_eq!(JobStatus::Queued, jobs.jobs()[second].status);
+
+        jobs.set_limits(2, usize::MAX, usize::MAX);
+        assert_eq!(JobStatus::Running, jobs.jobs()[second].status);
+        jobs.kill_all().unwrap();
+    }
+
+    #[test]
+    fn oldest_finished_jobs_are_forgotten() {
+        let jobs = Jobs::new(2).max_history(1);
+        let first = jobs.spawn("echo", cmd!("echo", "first")).unwrap();
+        wait_for(&jobs, first, JobStatus::Finished(ExitStatus::Code(0)));
+        let second = jobs.spawn("echo", cmd!("echo", "second")).unwrap();
+        wait_for(&jobs, second, JobStatus::Finished(ExitStatus::Code(0)));
+
+        assert_eq!(
+            vec![second],
+            jobs.jobs().iter().map(|job| job.id).collect_vec()
+        );
+        assert_eq!(JobOutput::default(), jobs.output(first));
+        assert_eq!("second", jobs.output(second).text());
+        jobs.kill(first).expect("forgotten jobs should be ignored");
+    }
+
+    #[test]
+    fn killing_queued_job_removes_it_from_queue() {
+        let jobs = Jobs::new(1);
+        let first = jobs.spawn("sleep", cmd!("sleep", "5")).unwrap();
+        let second = jobs.spawn("sleep", cmd!("sleep", "5")).unwrap();
+        jobs.kill(second).unwrap();
+        jobs.kill(first).unwrap();
+        assert_eq!(JobStatus::Killed, jobs.jobs()[second].status);
+        assert_eq!(0, jobs.running());
+    }
+
+    #[test]
+    fn streams_stdout_and_stderr_lines() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let jobs = Jobs::new(1);
+        jobs.set_sender(tx);
+        let id = jobs
+            .spawn(
+                "echo",
+                cmd!("sh", "-c", "echo out; echo err >&2; printf last").stderr_to_stdout(),
+            )
+            .unwrap();
+
+        let mut lines = Vec::new();
+        loop {
+            match rx.recv_timeout(Duration::from_secs(5)) {
+                Ok(ExternalEvent::JobOutput { id: from, line }) => {
+                    assert_eq!(id, from);
+                    lines.push(line);
+                }
+                Ok(ExternalEvent::JobFinished {
+                    id: finished,
+                    status,
+                    ..
+                }) => {
+                    assert_eq!(id, finished);
+                    assert!(status.success());
+                    break;
+                }
+                event => panic!("unexpected event: {event:?}"),
+            }
+        }
+        assert_eq!(vec!["out", "err", "last"], lines);
+        assert_eq!(
+            JobStatus::Finished(ExitStatus::Code(0)),
+            jobs.jobs()[id].status
+        );
+    }
+}
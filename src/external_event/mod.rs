@@ -1,27 +1,58 @@
+mod config_watch;
 mod crossterm_event;
+mod git_status;
+mod jobs;
 mod refresh;
-mod run_cmd;
 
 use anyhow::Error;
+pub use config_watch::{config_watch, ConfigWatcher};
 use crossterm::event::Event;
 pub use crossterm_event::*;
-pub use refresh::fs_watch;
-pub use run_cmd::*;
+pub use git_status::*;
+pub use jobs::*;
+pub use refresh::{fs_watch, FsWatcher};
 use smallvec::SmallVec;
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum RefreshData {
     Delete(PathBuf),
     Add(PathBuf),
+    /// The contents of a file changed
+    Modify(PathBuf),
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
 }
 
 #[derive(Debug)]
 pub enum ExternalEvent {
     RefreshFiletree,
     PartialRefresh(SmallVec<[RefreshData; 2]>),
+    /// The git index or HEAD changed, so every status might be different
+    RefreshGitStatus,
+    /// The global or local config file changed
+    ConfigChanged,
+    GitStatus(StatusUpdate),
     /// Wrapper for crossterm events
     Crossterm(Event),
-    CommandOutput(String),
+    /// A line printed by a background job, to either stdout or stderr
+    JobOutput {
+        id: JobId,
+        line: String,
+    },
+    /// A background job is done, either on its own or because it was killed
+    JobFinished {
+        id: JobId,
+        command: String,
+        status: JobStatus,
+        runtime: Duration,
+    },
+    /// A command run in the foreground exited
+    ForegroundFinished {
+        status: ExitStatus,
+        runtime: Duration,
+    },
     Error(Error),
 }
@@ -1,13 +1,24 @@
 use super::{ExternalEvent, RefreshData};
-use anyhow::Result;
+use crate::{
+    config::{FiletreeConfig, WatcherKind},
+    ignorer::Ignorer,
+};
+use anyhow::{Context, Result};
 use crossbeam_channel::{unbounded, Sender};
+use log::{debug, warn};
 use notify_debouncer_full::{
-    new_debouncer,
-    notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher},
+    new_debouncer, new_debouncer_opt,
+    notify::{
+        self,
+        event::{ModifyKind, RenameMode},
+        Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher,
+    },
     DebounceEventResult, Debouncer, FileIdMap,
 };
+use smallvec::{smallvec, SmallVec};
 use std::{
-    path::{Path, PathBuf},
+    fs,
+    path::Path,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, Mutex,
@@ -18,111 +29,462 @@ use std::{
 
 #[derive(Debug, Clone, Default)]
 pub struct ChangeBuffer {
-    create_buf: Arc<Mutex<Vec<PathBuf>>>,
-    remove_buf: Arc<Mutex<Vec<PathBuf>>>,
+    buf: Arc<Mutex<Vec<RefreshData>>>,
 }
 
 impl ChangeBuffer {
     pub fn new() -> Self {
         Self {
-            create_buf: Arc::new(Mutex::new(Vec::new())),
-            remove_buf: Arc::new(Mutex::new(Vec::new())),
+            buf: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
-    pub fn add_created(&mut self, path: Vec<PathBuf>) {
-        self.create_buf
-            .lock()
-            .expect("failed to lock create buffer")
-            .extend(path);
+    pub fn add(&mut self, data: impl IntoIterator<Item = RefreshData>) {
+        let mut buf = self.buf.lock().expect("failed to lock change buffer");
+        for data in data {
+            // Files tend to be modified many times while suspended
+            if matches!(data, RefreshData::Modify(_)) && buf.contains(&data) {
+                continue;
+            }
+            buf.push(data);
+        }
     }
 
-    pub fn add_removed(&mut self, path: Vec<PathBuf>) {
-        self.remove_buf
-            .lock()
-            .expect("failed to lock remove buffer")
-            .extend(path);
+    pub fn flush(&mut self, sender: &Sender<ExternalEvent>) {
+        let mut buf = self.buf.lock().expect("failed to lock change buffer");
+        if buf.is_empty() {
+            return;
+        }
+        let res = sender.send(ExternalEvent::PartialRefresh(buf.drain(..).collect()));
+        if let Err(err) = res {
+            sender
+                .send(ExternalEvent::Error(err.into()))
+                .expect("sending error failed");
+        }
     }
+}
 
-    pub fn flush(&mut self, sender: &Sender<ExternalEvent>) {
-        macro_rules! send_buffer {
-            ($buffer:expr, $refresh_type:ident) => {
-                if !$buffer.is_empty() {
-                    let res = sender.send(ExternalEvent::PartialRefresh(
-                        $buffer.drain(..).map(RefreshData::$refresh_type).collect(),
-                    ));
-                    if let Err(err) = res {
-                        sender
-                            .send(ExternalEvent::Error(err.into()))
-                            .expect("sending error failed");
-                    }
+/// Convert a notify event to the changes it represents
+fn to_refresh_data(event: Event) -> SmallVec<[RefreshData; 2]> {
+    let mut paths = event.paths.into_iter();
+    match event.kind {
+        // Renames with only one side were moved in or out of the watched directory
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            paths.map(RefreshData::Add).collect()
+        }
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            paths.map(RefreshData::Delete).collect()
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            match (paths.next(), paths.next()) {
+                (Some(from), Some(to)) => smallvec![RefreshData::Rename { from, to }],
+                _ => SmallVec::new(),
+            }
+        }
+        // Some platforms do not say which side of the rename a path is on
+        EventKind::Modify(ModifyKind::Name(_)) => paths
+            .map(|path| {
+                if path.exists() {
+                    RefreshData::Add(path)
+                } else {
+                    RefreshData::Delete(path)
                 }
-            };
+            })
+            .collect(),
+        EventKind::Modify(_) => paths.map(RefreshData::Modify).collect(),
+        _ => SmallVec::new(),
+    }
+}
+
+/// The debouncer of one of the watcher backends
+enum Backend {
+    Native(Debouncer<RecommendedWatcher, FileIdMap>),
+    Poll(Debouncer<PollWatcher, FileIdMap>),
+}
+
+impl Backend {
+    fn watch(&mut self, path: &Path) -> notify::Result<()> {
+        match self {
+            Self::Native(debouncer) => debouncer.watcher().watch(path, RecursiveMode::NonRecursive),
+            Self::Poll(debouncer) => debouncer.watcher().watch(path, RecursiveMode::NonRecursive),
         }
+    }
+}
+
+/// A debounced filesystem watcher. Dropping it stops watching
+pub struct FsWatcher {
+    backend: Arc<Mutex<Backend>>,
+}
+
+impl FsWatcher {
+    pub fn kind(&self) -> WatcherKind {
+        match *self.backend.lock().expect("failed to lock watcher") {
+            Backend::Native(_) => WatcherKind::Native,
+            Backend::Poll(_) => WatcherKind::Poll,
+        }
+    }
+}
+
+/// Create the callback that forwards debounced events to the refresh thread
+fn event_handler(
+    tx: Sender<Event>,
+    event_sender: Sender<ExternalEvent>,
+) -> impl FnMut(DebounceEventResult) + Send + 'static {
+    move |result: DebounceEventResult| match result {
+        Ok(events) => {
+            for event in events {
+                tx.send(event.event).unwrap();
+            }
+        }
+        Err(errs) => {
+            for err in errs {
+                event_sender.send(ExternalEvent::Error(err.into())).unwrap();
+            }
+        }
+    }
+}
+
+fn watch_native(
+    path: &Path,
+    debounce: Duration,
+    tx: Sender<Event>,
+    event_sender: Sender<ExternalEvent>,
+) -> Result<Backend> {
+    let debouncer = new_debouncer(debounce, None, event_handler(tx, event_sender))?;
+    let mut backend = Backend::Native(debouncer);
+    backend
+        .watch(path)
+        .with_context(|| format!("failed to watch \"{}\"", path.display()))?;
+    Ok(backend)
+}
+
+fn watch_poll(
+    path: &Path,
+    debounce: Duration,
+    poll_interval: Duration,
+    tx: Sender<Event>,
+    event_sender: Sender<ExternalEvent>,
+) -> Result<Backend> {
+    let debouncer = new_debouncer_opt::<_, PollWatcher, _>(
+        debounce,
+        None,
+        event_handler(tx, event_sender),
+        FileIdMap::new(),
+        notify::Config::default().with_poll_interval(poll_interval),
+    )?;
+    let mut backend = Backend::Poll(debouncer);
+    backend
+        .watch(path)
+        .with_context(|| format!("failed to poll \"{}\"", path.display()))?;
+    Ok(backend)
+}
 
-        send_buffer!(self.create_buf.lock().unwrap(), Add);
-        send_buffer!(self.remove_buf.lock().unwrap(), Delete);
+/// Watch every directory under `dir` that is not ignored. Ignored trees, like `target/` or
+/// `node_modules/`, never get a watch registered.
+fn watch_subdirs(backend: &mut Backend, ignorer: &mut Ignorer, dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        // Symlinks are not followed, same as the tree
+        let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+        if !is_dir || ignorer.is_ignored(&path, true) {
+            continue;
+        }
+        if let Err(err) = backend.watch(&path) {
+            debug!("failed to watch \"{}\": {err}", path.display());
+            continue;
+        }
+        watch_subdirs(backend, ignorer, &path);
     }
 }
 
-/// Watch for changes to the filesystem at `path`, sending results to `event_sender`
+/// Drop changes to paths that the tree does not show
+fn filter_ignored(
+    ignorer: &mut Ignorer,
+    data: SmallVec<[RefreshData; 2]>,
+) -> SmallVec<[RefreshData; 2]> {
+    data.into_iter()
+        .filter_map(|data| match data {
+            RefreshData::Add(ref path) | RefreshData::Modify(ref path) => {
+                (!ignorer.is_ignored(path, path.is_dir())).then_some(data)
+            }
+            // The path is gone, so there is no way to know if it was a directory
+            RefreshData::Delete(ref path) => (!ignorer.is_ignored(path, false)).then_some(data),
+            RefreshData::Rename { from, to } => {
+                let is_dir = to.is_dir();
+                match (
+                    ignorer.is_ignored(&from, is_dir),
+                    ignorer.is_ignored(&to, is_dir),
+                ) {
+                    (false, false) => Some(RefreshData::Rename { from, to }),
+                    (true, false) => Some(RefreshData::Add(to)),
+                    (false, true) => Some(RefreshData::Delete(from)),
+                    (true, true) => None,
+                }
+            }
+        })
+        .collect()
+}
+
+/// Watch for changes to the filesystem at `path`, sending results to `event_sender`.
+///
+/// Events are debounced for `refresh_time` milliseconds. If the native watcher
+/// cannot be set up, the polling watcher is used instead. Changes to paths
+/// ignored by the tree are not reported.
 pub fn fs_watch(
     path: &Path,
     event_sender: Sender<ExternalEvent>,
-    _refresh_time: u64,
+    config: &FiletreeConfig,
     is_suspended: Arc<AtomicBool>,
-) -> Result<(Debouncer<RecommendedWatcher, FileIdMap>, ChangeBuffer)> {
+) -> Result<(FsWatcher, ChangeBuffer)> {
     let (tx, rx) = unbounded();
-    let mut watcher = {
-        let event_sender = event_sender.clone();
-        new_debouncer(
-            Duration::from_secs(2),
-            None,
-            move |result: DebounceEventResult| match result {
-                Ok(events) => {
-                    for event in events {
-                        tx.send(event.event).unwrap();
-                    }
-                }
-                Err(errs) => {
-                    for err in errs {
-                        event_sender.send(ExternalEvent::Error(err.into())).unwrap();
-                    }
+    // A zero timeout would make the debouncer spin
+    let debounce = Duration::from_millis(config.refresh_time.max(1));
+    let poll_interval = Duration::from_millis(config.poll_interval.max(1));
+    let mut backend = match config.watcher {
+        WatcherKind::Native => {
+            match watch_native(path, debounce, tx.clone(), event_sender.clone()) {
+                Ok(backend) => backend,
+                Err(err) => {
+                    warn!("native file watcher unavailable, falling back to polling: {err:#}");
+                    watch_poll(path, debounce, poll_interval, tx, event_sender.clone())?
                 }
-            },
-        )?
+            }
+        }
+        WatcherKind::Poll => watch_poll(path, debounce, poll_interval, tx, event_sender.clone())?,
     };
-    // Swallow errors related to watching
-    let _ = watcher.watcher().watch(path, RecursiveMode::Recursive);
+    let mut ignorer = Ignorer::new(path, &config.ignore)?;
+    watch_subdirs(&mut backend, &mut ignorer, path);
+    // Only the top level of .git is needed to know when statuses change
+    let git_dir = path.join(".git");
+    if git_dir.is_dir() {
+        if let Err(err) = backend.watch(&git_dir) {
+            debug!("failed to watch \"{}\": {err}", git_dir.display());
+        }
+    }
+    let git_files = [git_dir.join("index"), git_dir.join("HEAD")];
+    let backend = Arc::new(Mutex::new(backend));
+
     let buffer = ChangeBuffer::new();
     let mut thread_buffer = buffer.clone();
+    // A strong reference would keep the watcher, and this thread, alive forever
+    let thread_backend = Arc::downgrade(&backend);
     thread::spawn(move || {
         for event in rx {
-            match event.kind {
-                EventKind::Create(_) => {
-                    if is_suspended.load(Ordering::Acquire) {
-                        thread_buffer.add_created(event.paths);
-                    } else {
-                        let data = ExternalEvent::PartialRefresh(
-                            event.paths.into_iter().map(RefreshData::Add).collect(),
-                        );
-                        event_sender.send(data).unwrap();
-                    }
-                }
-                EventKind::Remove(_) => {
-                    if is_suspended.load(Ordering::Acquire) {
-                        thread_buffer.add_removed(event.paths);
-                    } else {
-                        let data = ExternalEvent::PartialRefresh(
-                            event.paths.into_iter().map(RefreshData::Delete).collect(),
-                        );
-                        event_sender.send(data).unwrap();
-                    }
+            for path in &event.paths {
+                ignorer.invalidate(path);
+            }
+            if event.paths.iter().any(|path| git_files.contains(path)) {
+                event_sender.send(ExternalEvent::RefreshGitStatus).unwrap();
+            }
+            let data = filter_ignored(&mut ignorer, to_refresh_data(event));
+            if data.is_empty() {
+                continue;
+            }
+            // New directories need to be watched too
+            for data in &data {
+                let (RefreshData::Add(path) | RefreshData::Rename { to: path, .. }) = data else {
+                    continue;
+                };
+                let Some(backend) = thread_backend.upgrade().filter(|_| path.is_dir()) else {
+                    continue;
+                };
+                let mut backend = backend.lock().expect("failed to lock watcher");
+                if let Err(err) = backend.watch(path) {
+                    debug!("failed to watch \"{}\": {err}", path.display());
                 }
-                _ => {}
+                watch_subdirs(&mut backend, &mut ignorer, path);
+            }
+            if is_suspended.load(Ordering::Acquire) {
+                thread_buffer.add(data);
+            } else {
+                event_sender
+                    .send(ExternalEvent::PartialRefresh(data))
+                    .unwrap();
             }
         }
     });
 
-    Ok((watcher, buffer))
+    Ok((FsWatcher { backend }, buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use notify_debouncer_full::notify::event::{CreateKind, DataChange};
+    use std::path::PathBuf;
+    use test_log::test;
+
+    #[test]
+    fn renames_are_converted_to_refresh_data() {
+        let event = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path("/root/from".into())
+            .add_path("/root/to".into());
+        assert_eq!(
+            vec![RefreshData::Rename {
+                from: PathBuf::from("/root/from"),
+                to: PathBuf::from("/root/to"),
+            }],
+            to_refresh_data(event).into_vec()
+        );
+
+        let event = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::From)))
+            .add_path("/root/from".into());
+        assert_eq!(
+            vec![RefreshData::Delete("/root/from".into())],
+            to_refresh_data(event).into_vec()
+        );
+    }
+
+    #[test]
+    fn modifications_are_converted_to_refresh_data() {
+        let event = Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Content)))
+            .add_path("/root/test.txt".into());
+        assert_eq!(
+            vec![RefreshData::Modify("/root/test.txt".into())],
+            to_refresh_data(event).into_vec()
+        );
+        let event =
+            Event::new(EventKind::Create(CreateKind::File)).add_path("/root/test.txt".into());
+        assert_eq!(
+            vec![RefreshData::Add("/root/test.txt".into())],
+            to_refresh_data(event).into_vec()
+        );
+    }
+
+    #[test]
+    fn change_buffer_deduplicates_modifications() {
+        let (tx, rx) = unbounded();
+        let mut buffer = ChangeBuffer::new();
+        buffer.add([
+            RefreshData::Modify("/root/test.txt".into()),
+            RefreshData::Add("/root/test2.txt".into()),
+        ]);
+        buffer.add([RefreshData::Modify("/root/test.txt".into())]);
+        buffer.flush(&tx);
+        let Ok(ExternalEvent::PartialRefresh(data)) = rx.try_recv() else {
+            panic!("should have sent refresh");
+        };
+        assert_eq!(
+            vec![
+                RefreshData::Modify("/root/test.txt".into()),
+                RefreshData::Add("/root/test2.txt".into()),
+            ],
+            data.into_vec()
+        );
+        buffer.flush(&tx);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn poll_watcher_reports_changes() {
+        let temp = TempDir::new().unwrap();
+        let config = FiletreeConfig {
+            watcher: WatcherKind::Poll,
+            poll_interval: 50,
+            refresh_time: 50,
+            ..Default::default()
+        };
+        let (tx, rx) = unbounded();
+        let (watcher, _) =
+            fs_watch(temp.path(), tx, &config, Arc::new(AtomicBool::new(false))).unwrap();
+        assert_eq!(WatcherKind::Poll, watcher.kind());
+
+        temp.child("test.txt").touch().unwrap();
+        let path = temp.child("test.txt").path().canonicalize().unwrap();
+        let Ok(ExternalEvent::PartialRefresh(data)) = rx.recv_timeout(Duration::from_secs(5))
+        else {
+            panic!("should have sent refresh");
+        };
+        assert!(data.iter().any(|data| {
+            matches!(data, RefreshData::Add(added) if added.canonicalize().ok().as_ref() == Some(&path))
+        }));
+    }
+
+    #[test]
+    fn falls_back_to_polling_when_native_watcher_fails() {
+        let temp = TempDir::new().unwrap();
+        let (tx, _rx) = unbounded();
+        // Watching a path that does not exist fails for the native watcher,
+        // but the poll watcher will pick it up once it is created
+        let (watcher, _) = fs_watch(
+            &temp.path().join("missing"),
+            tx,
+            &FiletreeConfig::default(),
+            Arc::new(AtomicBool::new(false)),
+        )
+        .unwrap();
+        assert_eq!(WatcherKind::Poll, watcher.kind());
+    }
+
+    #[test]
+    fn changes_to_ignored_paths_are_filtered() {
+        let temp = TempDir::new().unwrap();
+        temp.child(".git").create_dir_all().unwrap();
+        temp.child(".gitignore").write_str("target/").unwrap();
+        temp.child("target").create_dir_all().unwrap();
+        let mut ignorer = Ignorer::new(temp.path(), &[]).unwrap();
+
+        let target = temp.path().join("target");
+        let data = filter_ignored(
+            &mut ignorer,
+            smallvec![
+                RefreshData::Add(target.join("debug")),
+                RefreshData::Modify(temp.path().join("test.txt")),
+                RefreshData::Rename {
+                    from: target.join("test.txt"),
+                    to: temp.path().join("test2.txt"),
+                },
+            ],
+        );
+        assert_eq!(
+            vec![
+                RefreshData::Modify(temp.path().join("test.txt")),
+                RefreshData::Add(temp.path().join("test2.txt")),
+            ],
+            data.into_vec()
+        );
+    }
+
+    #[test]
+    fn ignored_directories_are_not_watched() {
+        let temp = TempDir::new().unwrap();
+        temp.child(".git").create_dir_all().unwrap();
+        temp.child(".gitignore").write_str("target/").unwrap();
+        temp.child("target").create_dir_all().unwrap();
+        temp.child("src").create_dir_all().unwrap();
+        let config = FiletreeConfig {
+            watcher: WatcherKind::Poll,
+            poll_interval: 50,
+            refresh_time: 50,
+            ..Default::default()
+        };
+        let (tx, rx) = unbounded();
+        let (_watcher, _) =
+            fs_watch(temp.path(), tx, &config, Arc::new(AtomicBool::new(false))).unwrap();
+
+        temp.child("target/test.txt").touch().unwrap();
+        temp.child("src/test.txt").touch().unwrap();
+        let target = temp.path().join("target");
+        let added = temp.path().join("src/test.txt");
+        loop {
+            let Ok(event) = rx.recv_timeout(Duration::from_secs(5)) else {
+                panic!("should have sent refresh");
+            };
+            let ExternalEvent::PartialRefresh(data) = event else {
+                continue;
+            };
+            assert!(data.iter().all(|data| match data {
+                RefreshData::Add(path) | RefreshData::Modify(path) | RefreshData::Delete(path) =>
+                    !path.starts_with(&target),
+                RefreshData::Rename { .. } => false,
+            }));
+            if data.contains(&RefreshData::Add(added.clone())) {
+                break;
+            }
+        }
+    }
 }
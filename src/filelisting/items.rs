@@ -5,7 +5,7 @@ use log::debug;
 use std::{
     borrow::Cow,
     cmp::Ordering,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     iter,
     ops::RangeInclusive,
     path::{Component, Path, PathBuf},
@@ -17,16 +17,21 @@ pub enum Item {
     Dir(PathBuf),
 }
 
+/// Compare paths the way they are ordered in the tree
+pub(crate) fn compare_paths(a: &Path, b: &Path) -> Ordering {
+    let cow1 = a.as_os_str().to_string_lossy();
+    let cow2 = b.as_os_str().to_string_lossy();
+    match (cow1, cow2) {
+        (Cow::Borrowed(s1), Cow::Borrowed(s2)) => human_sort::compare(s1, s2),
+        (Cow::Borrowed(s1), Cow::Owned(s2)) => human_sort::compare(s1, &s2),
+        (Cow::Owned(s1), Cow::Borrowed(s2)) => human_sort::compare(&s1, s2),
+        (Cow::Owned(s1), Cow::Owned(s2)) => human_sort::compare(&s1, &s2),
+    }
+}
+
 impl Ord for Item {
     fn cmp(&self, other: &Self) -> Ordering {
-        let cow1 = self.path().as_os_str().to_string_lossy();
-        let cow2 = other.path().as_os_str().to_string_lossy();
-        match (cow1, cow2) {
-            (Cow::Borrowed(s1), Cow::Borrowed(s2)) => human_sort::compare(s1, s2),
-            (Cow::Borrowed(s1), Cow::Owned(s2)) => human_sort::compare(s1, &s2),
-            (Cow::Owned(s1), Cow::Borrowed(s2)) => human_sort::compare(&s1, s2),
-            (Cow::Owned(s1), Cow::Owned(s2)) => human_sort::compare(&s1, &s2),
-        }
+        compare_paths(self.path(), other.path())
     }
 }
 
@@ -94,6 +99,16 @@ pub struct Items {
 
 impl Items {
     pub fn new<T: AsRef<Path>>(files: &[T], dirs_first: bool) -> Self {
+        Self::with_empty_dirs(files, &[] as &[PathBuf], dirs_first)
+    }
+
+    /// Like `Items::new`, but `empty_dirs` are made directories even though nothing in `files`
+    /// is under them. Each of them must also be in `files`.
+    pub fn with_empty_dirs<T: AsRef<Path>, U: AsRef<Path>>(
+        files: &[T],
+        empty_dirs: &[U],
+        dirs_first: bool,
+    ) -> Self {
         let mut root = files
             .get(0)
             .map_or(Some(Path::new("")), |path| path.as_ref().parent())
@@ -136,6 +151,13 @@ impl Items {
                 .or_default()
                 .push(Item::File(file.to_path_buf()));
         }
+        for dir in empty_dirs {
+            let dir = dir.as_ref();
+            if let Some(parent) = dir.parent().and_then(|parent| items.get_mut(parent)) {
+                parent.retain(|item| item.path() != dir);
+            }
+            items.entry(dir.to_path_buf()).or_default();
+        }
 
         let items = if !dirs_first {
             // Sort items first by directory name, then flatten into an iterator of `Item`s
@@ -278,17 +300,90 @@ impl Items {
         Ok(insertion_index)
     }
 
+    /// Insert the direct `children` of `dir` under it, skipping any that are already there. They
+    /// are merged with the items already in `dir` and ordered like in `Items::new`. Returns the
+    /// indices of the inserted items, in order.
+    pub fn insert_children(
+        &mut self,
+        dir: &Path,
+        children: Vec<Item>,
+        dirs_first: bool,
+    ) -> Result<Vec<usize>> {
+        let start = if dir == self.root {
+            0
+        } else {
+            match self.resolve_index(dir) {
+                Some(idx) if !self.items[idx].is_file() => idx + 1,
+                _ => bail!("directory to insert into not found"),
+            }
+        };
+        let end = self.items[start..]
+            .iter()
+            .position(|item| !item.path().starts_with(dir))
+            .map_or(self.items.len(), |pos| start + pos);
+        let mut listed: HashSet<PathBuf> = self.items[start..end]
+            .iter()
+            .filter(|item| item.path().parent() == Some(dir))
+            .map(|item| item.path().to_path_buf())
+            .collect();
+        let children = children
+            .into_iter()
+            .filter(|child| {
+                child.path().parent() == Some(dir) && listed.insert(child.path().to_path_buf())
+            })
+            .collect_vec();
+        if children.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Each direct child of `dir` with everything under it, and whether it is new
+        let mut groups: Vec<(bool, Vec<Item>)> = Vec::new();
+        for item in self.items.drain(start..end) {
+            match groups.last_mut() {
+                Some((_, group)) if item.path().parent() != Some(dir) => group.push(item),
+                _ => groups.push((false, vec![item])),
+            }
+        }
+        groups.extend(children.into_iter().map(|child| (true, vec![child])));
+        groups.sort_by(|(_, a), (_, b)| {
+            let kind = a[0].is_file().cmp(&b[0].is_file());
+            let kind = if dirs_first { kind } else { kind.reverse() };
+            kind.then_with(|| a[0].cmp(&b[0]))
+        });
+
+        let mut inserted = Vec::new();
+        let mut merged = Vec::with_capacity(end - start + groups.len());
+        for (is_new, group) in groups {
+            if is_new {
+                inserted.push(start + merged.len());
+            }
+            merged.extend(group);
+        }
+        self.items.splice(start..start, merged);
+        Ok(inserted)
+    }
+
     pub fn rename<'a, T>(&mut self, from: T, to: impl AsRef<Path>) -> Result<()>
     where
         T: Into<ItemsIndex<'a>>,
     {
         let index = self.resolve_index(from).context("item not found")?;
-        let item = &mut self.items[index];
-        if to.as_ref().components().count() != item.path().components().count() {
+        let from = self.items[index].path().to_path_buf();
+        if to.as_ref().components().count() != from.components().count() {
             bail!("cannot move item, just rename");
         }
 
-        *item.path_mut() = to.as_ref().to_path_buf();
+        // Children of directories need their paths updated, too
+        let renamed = self.get_children(index)?;
+        for item in &mut self.items[renamed] {
+            let suffix = item.path().strip_prefix(&from)?;
+            let new_path = if suffix.as_os_str().is_empty() {
+                to.as_ref().to_path_buf()
+            } else {
+                to.as_ref().join(suffix)
+            };
+            *item.path_mut() = new_path;
+        }
 
         Ok(())
     }
@@ -305,7 +400,10 @@ impl Items {
         let first = self.items[first_idx].clone();
         let items_to_move = self.get_children(first_idx)?;
 
-        let (move_to, mut insertion_index) = {
+        let (move_to, mut insertion_index) = if new_name.as_ref() == self.root {
+            // Items are moved to the top of the root, like in `Items::add`
+            (self.root.clone(), 0)
+        } else {
             let mut move_to = None;
             let mut insertion_index = None;
 
@@ -332,7 +430,7 @@ impl Items {
             *item.path_mut() = new_path;
         }
 
-        if insertion_index < *items_to_move.start() {
+        if insertion_index < *items_to_move.start() && move_to != self.root {
             insertion_index += 1;
         }
         self.items
@@ -848,6 +946,34 @@ mod tests {
         assert_eq!(vec![Item::File("/root/test2.txt".into())], items.items);
     }
 
+    #[test]
+    fn renaming_directory_renames_children() {
+        let mut items = Items::new(&["/root/test/test.txt", "/root/test2.txt"], false);
+        assert!(items.rename("/root/test", "/root/renamed").is_ok());
+        assert_eq!(
+            vec![
+                Item::File("/root/test2.txt".into()),
+                Item::Dir("/root/renamed".into()),
+                Item::File("/root/renamed/test.txt".into()),
+            ],
+            items.items
+        );
+    }
+
+    #[test]
+    fn can_move_items_to_root() {
+        let mut items = Items::new(&["/root/test/test.txt", "/root/test2.txt"], false);
+        assert!(items.mv("/root/test/test.txt", "/root").is_ok());
+        assert_eq!(
+            vec![
+                Item::File("/root/test.txt".into()),
+                Item::File("/root/test2.txt".into()),
+                Item::Dir("/root/test".into()),
+            ],
+            items.items
+        );
+    }
+
     #[test]
     fn can_order_dirs_first() {
         let items = Items::new(
@@ -875,4 +1001,102 @@ mod tests {
             items.items
         );
     }
+
+    #[test]
+    fn empty_dirs_are_not_files() {
+        let files = &["/root/dir", "/root/test.txt"];
+        let items = Items::with_empty_dirs(files, &["/root/dir"], false);
+        assert_eq!(
+            vec![
+                Item::File("/root/test.txt".into()),
+                Item::Dir("/root/dir".into()),
+            ],
+            items.into_items()
+        );
+    }
+
+    #[test]
+    fn insert_children_places_them_below_dir() {
+        let files = &["/root/dir", "/root/test.txt"];
+        let mut items = Items::with_empty_dirs(files, &["/root/dir"], false);
+        let inserted = items
+            .insert_children(
+                Path::new("/root/dir"),
+                vec![
+                    Item::Dir("/root/dir/b".into()),
+                    Item::File("/root/dir/test.txt".into()),
+                    Item::Dir("/root/dir/a".into()),
+                ],
+                false,
+            )
+            .unwrap();
+        assert_eq!(vec![2, 3, 4], inserted);
+        assert_eq!(
+            vec![
+                Item::File("/root/test.txt".into()),
+                Item::Dir("/root/dir".into()),
+                Item::File("/root/dir/test.txt".into()),
+                Item::Dir("/root/dir/a".into()),
+                Item::Dir("/root/dir/b".into()),
+            ],
+            items.into_items()
+        );
+    }
+
+    #[test]
+    fn insert_children_skips_existing() {
+        let files = &["/root/dir/test.txt", "/root/test.txt"];
+        let mut items = Items::new(files, true);
+        let inserted = items
+            .insert_children(
+                Path::new("/root/dir"),
+                vec![
+                    Item::File("/root/dir/test.txt".into()),
+                    Item::File("/root/dir/test2.txt".into()),
+                ],
+                true,
+            )
+            .unwrap();
+        assert_eq!(vec![2], inserted);
+        assert_eq!(
+            vec![
+                Item::Dir("/root/dir".into()),
+                Item::File("/root/dir/test.txt".into()),
+                Item::File("/root/dir/test2.txt".into()),
+                Item::File("/root/test.txt".into()),
+            ],
+            items.into_items()
+        );
+    }
+
+    #[test]
+    fn insert_children_merges_with_existing() {
+        let files = &["/root/b/test.txt", "/root/d/test.txt", "/root/c.txt"];
+        let mut items = Items::new(files, false);
+        let inserted = items
+            .insert_children(
+                Path::new("/root"),
+                vec![
+                    Item::Dir("/root/c".into()),
+                    Item::File("/root/a.txt".into()),
+                    Item::File("/root/d.txt".into()),
+                ],
+                false,
+            )
+            .unwrap();
+        assert_eq!(vec![0, 2, 5], inserted);
+        assert_eq!(
+            vec![
+                Item::File("/root/a.txt".into()),
+                Item::File("/root/c.txt".into()),
+                Item::File("/root/d.txt".into()),
+                Item::Dir("/root/b".into()),
+                Item::File("/root/b/test.txt".into()),
+                Item::Dir("/root/c".into()),
+                Item::Dir("/root/d".into()),
+                Item::File("/root/d/test.txt".into()),
+            ],
+            items.into_items()
+        );
+    }
 }
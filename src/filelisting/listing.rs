@@ -2,7 +2,7 @@ use super::items::*;
 use anyhow::{anyhow, Context, Result};
 use bitvec::prelude::*;
 use log::debug;
-use std::path::Path;
+use std::{mem, path::Path};
 
 #[derive(Debug, Default)]
 pub struct FileListing {
@@ -18,7 +18,16 @@ pub struct FileListing {
 
 impl FileListing {
     pub fn new<T: AsRef<Path>>(items: &[T], dirs_first: bool) -> Self {
-        let items = Items::new(items, dirs_first);
+        Self::with_empty_dirs(items, &[] as &[&Path], dirs_first)
+    }
+
+    /// See `Items::with_empty_dirs`
+    pub fn with_empty_dirs<T: AsRef<Path>, U: AsRef<Path>>(
+        items: &[T],
+        empty_dirs: &[U],
+        dirs_first: bool,
+    ) -> Self {
+        let items = Items::with_empty_dirs(items, empty_dirs, dirs_first);
         let len = items.len();
         let mut listing = Self {
             items,
@@ -121,7 +130,9 @@ impl FileListing {
     pub fn select_next_n(&mut self, n: usize) {
         let Some(new_selected) = self.cache.get(self.selected().unwrap_or_default() + n) else {
             // Set to last if the jump is over the limit
-            self.selected = self.relative_to_absolute(self.len() - 1).unwrap_or_default();
+            self.selected = self
+                .relative_to_absolute(self.len() - 1)
+                .unwrap_or_default();
             self.selected_cache = Some(self.len() - 1);
             return;
         };
@@ -209,6 +220,40 @@ impl FileListing {
         self.populate_cache();
     }
 
+    /// Add the direct children of `dir` as a batch. New directories are folded.
+    pub fn add_children(
+        &mut self,
+        dir: &Path,
+        children: Vec<Item>,
+        dirs_first: bool,
+    ) -> Result<()> {
+        let was_empty = self.items.is_empty();
+        let inserted = self.items.insert_children(dir, children, dirs_first)?;
+        let old_folded = mem::take(&mut self.folded);
+        let mut old_idx = 0;
+        let mut selected = self.selected;
+        for idx in 0..self.items.len() {
+            if inserted.binary_search(&idx).is_ok() {
+                let is_dir = !self.items.get(idx).expect("was just inserted").is_file();
+                self.folded.push(is_dir);
+            } else {
+                if old_idx == self.selected {
+                    selected = idx;
+                }
+                self.folded.push(old_folded[old_idx]);
+                old_idx += 1;
+            }
+        }
+
+        if !was_empty {
+            self.selected = selected;
+        }
+        self.populate_cache();
+        self.selected_cache = self.cache.iter().position(|idx| *idx == self.selected);
+
+        Ok(())
+    }
+
     pub fn remove<'a, T>(&mut self, index: T) -> Result<()>
     where
         T: Into<ItemsIndex<'a>>,
@@ -880,4 +925,53 @@ mod tests {
         items.unfold_all();
         assert_eq!(bitvec![1, 0, 0, 1, 0], items.folded);
     }
+
+    #[test]
+    fn add_children_keeps_selection_and_folds_new_dirs() {
+        let mut listing =
+            FileListing::with_empty_dirs(&["/root/dir", "/root/test.txt"], &["/root/dir"], true);
+        listing.select("/root/test.txt");
+        listing
+            .add_children(
+                Path::new("/root/dir"),
+                vec![
+                    Item::Dir("/root/dir/sub".into()),
+                    Item::File("/root/dir/test.txt".into()),
+                ],
+                true,
+            )
+            .unwrap();
+
+        assert_eq!(
+            Some(&Item::File("/root/test.txt".into())),
+            listing.selected_item()
+        );
+        assert_eq!(Some(3), listing.selected());
+        assert_eq!(Some(true), listing.is_folded("/root/dir/sub"));
+        assert_eq!(Some(false), listing.is_folded("/root/dir/test.txt"));
+    }
+
+    #[test]
+    fn add_children_merges_with_existing_items() {
+        let mut listing = FileListing::new(&["/root/b/test.txt", "/root/c.txt"], false);
+        listing.fold("/root/b");
+        listing.select("/root/b");
+        listing
+            .add_children(
+                Path::new("/root"),
+                vec![
+                    Item::Dir("/root/d".into()),
+                    Item::File("/root/a.txt".into()),
+                ],
+                false,
+            )
+            .unwrap();
+
+        assert_eq!(Some(&Item::Dir("/root/b".into())), listing.selected_item());
+        assert_eq!(Some(2), listing.selected());
+        assert_eq!(Some(true), listing.is_folded("/root/b"));
+        assert_eq!(Some(true), listing.is_folded("/root/d"));
+        assert_eq!(Some(false), listing.is_folded("/root/a.txt"));
+        assert_eq!(Some(false), listing.is_folded("/root/c.txt"));
+    }
 }
@@ -1,5 +1,7 @@
 mod items;
 mod listing;
+mod walk;
 
 pub use items::Item;
 pub use listing::*;
+pub use walk::*;
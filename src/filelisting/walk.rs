@@ -0,0 +1,168 @@
+use super::items::compare_paths;
+use crate::ignorer::build_override_ignorer;
+use anyhow::Result;
+use crossbeam_channel::unbounded;
+use ignore::{DirEntry, WalkBuilder, WalkState};
+use std::path::{Path, PathBuf};
+
+/// The paths found by a `Walker`, sorted
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Walked {
+    pub paths: Vec<PathBuf>,
+    /// Directories at the max depth, whose children were not read
+    pub unloaded: Vec<PathBuf>,
+}
+
+/// Walks directories of a project, using the same ignore rules as the file tree
+#[derive(Debug, Clone)]
+pub struct Walker<'a> {
+    root: &'a Path,
+    ignore: &'a [String],
+    show_hidden: bool,
+    max_depth: Option<usize>,
+}
+
+impl<'a> Walker<'a> {
+    /// `ignore` globs are relative to `root`
+    pub fn new(root: &'a Path, ignore: &'a [String]) -> Self {
+        Self {
+            root,
+            ignore,
+            show_hidden: false,
+            max_depth: None,
+        }
+    }
+
+    pub fn show_hidden(mut self, show_hidden: bool) -> Self {
+        self.show_hidden = show_hidden;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Walk `dir` on multiple threads
+    pub fn walk(&self, dir: &Path) -> Result<Walked> {
+        let (tx, rx) = unbounded();
+        self.builder(dir)?.build_parallel().run(|| {
+            let tx = tx.clone();
+            Box::new(move |entry| {
+                if let Some(entry) = entry.ok().and_then(|entry| self.visit(dir, entry)) {
+                    tx.send(entry).expect("receiver should outlive walk");
+                }
+                WalkState::Continue
+            })
+        });
+        drop(tx);
+
+        Ok(Self::collect(rx))
+    }
+
+    /// Walk `dir` on the current thread. Gives the same results as `Walker::walk`
+    pub fn walk_serial(&self, dir: &Path) -> Result<Walked> {
+        Ok(Self::collect(self.builder(dir)?.build().filter_map(
+            |entry| entry.ok().and_then(|entry| self.visit(dir, entry)),
+        )))
+    }
+
+    fn builder(&self, dir: &Path) -> Result<WalkBuilder> {
+        let overrides = build_override_ignorer(self.root, self.ignore)?;
+        let mut builder = WalkBuilder::new(dir);
+        builder
+            .overrides(overrides)
+            .hidden(!self.show_hidden)
+            .max_depth(self.max_depth);
+        Ok(builder)
+    }
+
+    /// Returns the path of `entry` and whether it is an unloaded directory
+    fn visit(&self, dir: &Path, entry: DirEntry) -> Option<(PathBuf, bool)> {
+        if entry.path() == dir {
+            return None;
+        }
+        let is_dir = entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_dir());
+        let unloaded = is_dir && self.max_depth == Some(entry.depth());
+        Some((entry.into_path(), unloaded))
+    }
+
+    /// Sort visited entries once, so the order doesn't depend on how the walk was done
+    fn collect(entries: impl IntoIterator<Item = (PathBuf, bool)>) -> Walked {
+        let mut walked = Walked::default();
+        for (path, unloaded) in entries {
+            if unloaded {
+                walked.unloaded.push(path.clone());
+            }
+            walked.paths.push(path);
+        }
+        walked.paths.sort_unstable_by(|a, b| compare_paths(a, b));
+        walked.unloaded.sort_unstable_by(|a, b| compare_paths(a, b));
+        walked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use test_log::test;
+
+    #[test]
+    fn parallel_walk_matches_serial_walk() {
+        let temp = TempDir::new().unwrap();
+        temp.child(".git").create_dir_all().unwrap();
+        temp.child(".gitignore").write_str("*.log").unwrap();
+        for i in 0..10 {
+            temp.child(format!("dir{i}/file{i}.txt")).touch().unwrap();
+            temp.child(format!("dir{i}/sub/file.log")).touch().unwrap();
+            temp.child(format!("dir{i}/.hidden/file.txt"))
+                .touch()
+                .unwrap();
+            temp.child(format!("dir{i}/ignored/file.txt"))
+                .touch()
+                .unwrap();
+            temp.child(format!("file{i}.rs")).touch().unwrap();
+        }
+        let ignore = vec!["ignored".to_owned()];
+
+        for show_hidden in [false, true] {
+            for max_depth in [None, Some(1), Some(2)] {
+                let walker = Walker::new(temp.path(), &ignore)
+                    .show_hidden(show_hidden)
+                    .max_depth(max_depth);
+                let walked = walker.walk(temp.path()).unwrap();
+                assert_eq!(walker.walk_serial(temp.path()).unwrap(), walked);
+                assert!(!walked.paths.contains(&temp.path().join("dir0/ignored")));
+                assert!(!walked
+                    .paths
+                    .contains(&temp.path().join("dir0/sub/file.log")));
+                assert_eq!(
+                    show_hidden && max_depth != Some(1),
+                    walked.paths.contains(&temp.path().join("dir0/.hidden"))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn walk_reports_unloaded_dirs() {
+        let temp = TempDir::new().unwrap();
+        temp.child("dir/sub/test.txt").touch().unwrap();
+        temp.child("test.txt").touch().unwrap();
+
+        let walked = Walker::new(temp.path(), &[])
+            .max_depth(Some(1))
+            .walk(temp.path())
+            .unwrap();
+        assert_eq!(
+            Walked {
+                paths: vec![temp.path().join("dir"), temp.path().join("test.txt")],
+                unloaded: vec![temp.path().join("dir")],
+            },
+            walked
+        );
+    }
+}
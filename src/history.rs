@@ -0,0 +1,167 @@
+use crate::marks::get_data_home;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Oldest entries are forgotten past this many
+const MAX_ENTRIES: usize = 1000;
+
+pub fn get_history_file() -> Option<PathBuf> {
+    get_data_home().map(|dir| dir.join("history.json"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryKind {
+    /// Commands typed into the command input, before `{}` is substituted
+    Command,
+    /// Input given to commands with `{...}`
+    Input,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+struct ProjectHistory {
+    commands: Vec<String>,
+    inputs: Vec<String>,
+}
+
+/// Command history of a single project, oldest entries first
+#[derive(Debug, Default)]
+pub struct History {
+    project: PathBuf,
+    /// Where the history is written to. History that didn't come from a file is never written.
+    file: Option<PathBuf>,
+    entries: ProjectHistory,
+}
+
+impl History {
+    pub fn from_history_file(project: impl AsRef<Path>) -> Result<Self> {
+        let project = project.as_ref().to_path_buf();
+        let Some(file) = get_history_file() else {
+            return Ok(Self {
+                project,
+                ..Default::default()
+            });
+        };
+        let mut all = read_all(&file)?;
+        Ok(Self {
+            entries: all.remove(&project).unwrap_or_default(),
+            project,
+            file: Some(file),
+        })
+    }
+
+    pub fn write(&self) -> Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let mut all = read_all(file)?;
+        all.insert(self.project.clone(), self.entries.clone());
+        fs::create_dir_all(file.parent().expect("history file should have parent"))
+            .context("error creating history dir")?;
+        fs::write(file, serde_json::to_string(&all)?).context("error writing history file")
+    }
+
+    pub fn entries(&self, kind: HistoryKind) -> &[String] {
+        match kind {
+            HistoryKind::Command => &self.entries.commands,
+            HistoryKind::Input => &self.entries.inputs,
+        }
+    }
+
+    /// Add `entry` as the newest of its kind, removing it from further back in the history
+    pub fn add(&mut self, kind: HistoryKind, entry: String) {
+        let entries = match kind {
+            HistoryKind::Command => &mut self.entries.commands,
+            HistoryKind::Input => &mut self.entries.inputs,
+        };
+        entries.retain(|existing| *existing != entry);
+        entries.push(entry);
+        if entries.len() > MAX_ENTRIES {
+            entries.drain(..entries.len() - MAX_ENTRIES);
+        }
+    }
+
+    pub fn last(&self, kind: HistoryKind) -> Option<&str> {
+        self.entries(kind).last().map(String::as_str)
+    }
+
+    pub fn project(&self) -> &Path {
+        &self.project
+    }
+}
+
+fn read_all(file: &Path) -> Result<HashMap<PathBuf, ProjectHistory>> {
+    let contents = match fs::read_to_string(file) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(err) => return Err(err).context("error reading history file"),
+    };
+    serde_json::from_str(&contents).context("error parsing history file")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use scopeguard::defer;
+    use serial_test::serial;
+    use std::env;
+    use test_log::test;
+
+    #[test]
+    fn adding_moves_duplicates_to_newest() {
+        let mut history = History::default();
+        history.add(HistoryKind::Command, "ls".to_owned());
+        history.add(HistoryKind::Command, "pwd".to_owned());
+        history.add(HistoryKind::Command, "ls".to_owned());
+        assert_eq!(["pwd", "ls"], history.entries(HistoryKind::Command));
+        assert!(history.entries(HistoryKind::Input).is_empty());
+        assert_eq!(Some("ls"), history.last(HistoryKind::Command));
+    }
+
+    #[test]
+    fn forgets_oldest_entries() {
+        let mut history = History::default();
+        for i in 0..=MAX_ENTRIES {
+            history.add(HistoryKind::Input, i.to_string());
+        }
+        let entries = history.entries(HistoryKind::Input);
+        assert_eq!(MAX_ENTRIES, entries.len());
+        assert_eq!("1", entries[0]);
+    }
+
+    #[test]
+    #[serial]
+    fn writes_next_to_marks_without_touching_other_projects() {
+        let temp = TempDir::new().unwrap();
+        temp.child("history.json")
+            .write_str("{\"/other\": {\"commands\": [\"make\"]}}")
+            .unwrap();
+        env::set_var("PROJECTABLE_DATA_DIR", temp.path());
+        defer! {
+            env::remove_var("PROJECTABLE_DATA_DIR");
+        }
+
+        let mut history = History::from_history_file("/").unwrap();
+        assert!(history.entries(HistoryKind::Command).is_empty());
+        history.add(HistoryKind::Command, "cargo build".to_owned());
+        history.write().unwrap();
+
+        let history = History::from_history_file("/").unwrap();
+        assert_eq!(["cargo build"], history.entries(HistoryKind::Command));
+        let other = History::from_history_file("/other").unwrap();
+        assert_eq!(["make"], other.entries(HistoryKind::Command));
+    }
+
+    #[test]
+    fn history_without_file_is_not_written() {
+        let mut history = History::default();
+        history.add(HistoryKind::Command, "ls".to_owned());
+        assert!(history.write().is_ok());
+    }
+}
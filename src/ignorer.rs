@@ -0,0 +1,192 @@
+use anyhow::{Context, Result};
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    overrides::{Override, OverrideBuilder},
+    Match,
+};
+use std::{
+    collections::HashMap,
+    iter,
+    path::{Path, PathBuf},
+};
+
+/// Builds an `Override` that ignores certain paths
+pub fn build_override_ignorer(root: impl AsRef<Path>, ignore: &[String]) -> Result<Override> {
+    let mut override_builder = OverrideBuilder::new(root.as_ref());
+
+    for pat in ignore.iter().map(|x| x.as_str()).chain(iter::once("/.git")) {
+        override_builder
+            .add(&format!("!{pat}"))
+            .with_context(|| format!("failed to add glob for: \"!{pat}\""))?
+            .add(&format!("!{pat}/**"))
+            .with_context(|| format!("failed to add glob for: \"!{pat}/**\""))?;
+    }
+    override_builder
+        .build()
+        .context("failed to build override ignorer")
+}
+
+/// Decides whether paths in a project are left out of the file tree. Uses the same rules as
+/// the tree's walker: `.gitignore` and `.ignore` files, `.git/info/exclude` and the configured
+/// ignore globs.
+#[derive(Debug)]
+pub struct Ignorer {
+    root: PathBuf,
+    overrides: Override,
+    in_repo: bool,
+    exclude: Gitignore,
+    /// Rules from the ignore files of each directory, read lazily
+    dir_rules: HashMap<PathBuf, Gitignore>,
+}
+
+impl Ignorer {
+    pub fn new(root: impl AsRef<Path>, ignore: &[String]) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let overrides = build_override_ignorer(&root, ignore)?;
+        // Like the walker, gitignore rules only apply inside of a git repository
+        let in_repo = root.ancestors().any(|dir| dir.join(".git").exists());
+        let mut exclude = GitignoreBuilder::new(&root);
+        exclude.add(root.join(".git").join("info").join("exclude"));
+        let exclude = exclude.build().unwrap_or_else(|_| Gitignore::empty());
+
+        Ok(Self {
+            root,
+            overrides,
+            in_repo,
+            exclude,
+            dir_rules: HashMap::new(),
+        })
+    }
+
+    /// Check if `path` is ignored, either directly or because one of its parent directories is
+    pub fn is_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        if self.overrides.matched(path, is_dir).is_ignore() {
+            return true;
+        }
+
+        let mut current = self.root.clone();
+        let mut components = relative.components().peekable();
+        while let Some(component) = components.next() {
+            current.push(component);
+            let is_dir = is_dir || components.peek().is_some();
+            if self.matched(&current, is_dir) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Forget cached rules if `path` is an ignore file
+    pub fn invalidate(&mut self, path: &Path) {
+        let is_ignore_file = path
+            .file_name()
+            .is_some_and(|name| name == ".gitignore" || name == ".ignore");
+        if let (true, Some(dir)) = (is_ignore_file, path.parent()) {
+            self.dir_rules.remove(dir);
+        }
+    }
+
+    /// Check the rules of every directory from the parent of `path` up to the root. Rules in
+    /// deeper directories take precedence.
+    fn matched(&mut self, path: &Path, is_dir: bool) -> bool {
+        for dir in path.ancestors().skip(1) {
+            match self.rules(dir).matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+            if dir == self.root {
+                break;
+            }
+        }
+        self.in_repo && self.exclude.matched(path, is_dir).is_ignore()
+    }
+
+    fn rules(&mut self, dir: &Path) -> &Gitignore {
+        let in_repo = self.in_repo;
+        self.dir_rules.entry(dir.to_path_buf()).or_insert_with(|| {
+            let mut builder = GitignoreBuilder::new(dir);
+            if in_repo {
+                builder.add(dir.join(".gitignore"));
+            }
+            // Added last so it takes precedence over .gitignore
+            builder.add(dir.join(".ignore"));
+            builder.build().unwrap_or_else(|_| Gitignore::empty())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use test_log::test;
+
+    fn temp_repo() -> TempDir {
+        let temp = TempDir::new().unwrap();
+        temp.child(".git").create_dir_all().unwrap();
+        temp
+    }
+
+    #[test]
+    fn ignores_paths_from_gitignore() {
+        let temp = temp_repo();
+        temp.child(".gitignore")
+            .write_str("target/\n*.log")
+            .unwrap();
+        temp.child("target").create_dir_all().unwrap();
+        let mut ignorer = Ignorer::new(temp.path(), &[]).unwrap();
+
+        assert!(ignorer.is_ignored(&temp.path().join("target"), true));
+        assert!(ignorer.is_ignored(&temp.path().join("target/debug/build"), false));
+        assert!(ignorer.is_ignored(&temp.path().join("dir/test.log"), false));
+        assert!(!ignorer.is_ignored(&temp.path().join("test.txt"), false));
+    }
+
+    #[test]
+    fn ignores_git_dir_and_configured_globs() {
+        let temp = temp_repo();
+        let mut ignorer = Ignorer::new(temp.path(), &["node_modules".to_owned()]).unwrap();
+
+        assert!(ignorer.is_ignored(&temp.path().join(".git/index"), false));
+        assert!(ignorer.is_ignored(&temp.path().join("node_modules/pkg/index.js"), false));
+        assert!(!ignorer.is_ignored(&temp.path().join("src/main.rs"), false));
+    }
+
+    #[test]
+    fn nested_gitignore_takes_precedence() {
+        let temp = temp_repo();
+        temp.child(".gitignore").write_str("*.txt").unwrap();
+        temp.child("dir/.gitignore").write_str("!keep.txt").unwrap();
+        let mut ignorer = Ignorer::new(temp.path(), &[]).unwrap();
+
+        assert!(ignorer.is_ignored(&temp.path().join("dir/test.txt"), false));
+        assert!(!ignorer.is_ignored(&temp.path().join("dir/keep.txt"), false));
+    }
+
+    #[test]
+    fn gitignore_is_not_used_outside_of_repo() {
+        let temp = TempDir::new().unwrap();
+        temp.child(".gitignore").write_str("*.txt").unwrap();
+        temp.child(".ignore").write_str("*.log").unwrap();
+        let mut ignorer = Ignorer::new(temp.path(), &[]).unwrap();
+
+        assert!(!ignorer.is_ignored(&temp.path().join("test.txt"), false));
+        assert!(ignorer.is_ignored(&temp.path().join("test.log"), false));
+    }
+
+    #[test]
+    fn invalidate_rereads_ignore_files() {
+        let temp = temp_repo();
+        temp.child(".gitignore").write_str("*.txt").unwrap();
+        let mut ignorer = Ignorer::new(temp.path(), &[]).unwrap();
+        assert!(ignorer.is_ignored(&temp.path().join("test.txt"), false));
+
+        temp.child(".gitignore").write_str("").unwrap();
+        ignorer.invalidate(&temp.path().join(".gitignore"));
+        assert!(!ignorer.is_ignored(&temp.path().join("test.txt"), false));
+    }
+}
@@ -0,0 +1,287 @@
+use anyhow::{bail, Result};
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+};
+
+/// Every placeholder, without braces. `{...}` is the input prompted for.
+const PLACEHOLDERS: [&str; 9] = [
+    "", "name", "stem", "ext", "dir", "rel", "root", "marks", "...",
+];
+
+/// What placeholders are filled in with
+#[derive(Debug, Clone, Copy)]
+pub struct Context<'a> {
+    pub selected: Option<&'a Path>,
+    pub root: &'a Path,
+    pub marks: &'a [PathBuf],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Text(&'a str),
+    /// Name of the placeholder, without braces
+    Placeholder(&'a str),
+}
+
+fn tokenize(template: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        if i > 0 {
+            tokens.push(Token::Text(&rest[..i]));
+        }
+        let tail = &rest[i..];
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            tokens.push(Token::Text(&tail[..1]));
+            rest = &tail[2..];
+            continue;
+        }
+        let placeholder = tail
+            .strip_prefix('{')
+            .and_then(|inner| inner.find('}').map(|end| &inner[..end]))
+            .filter(|name| PLACEHOLDERS.contains(name));
+        if let Some(name) = placeholder {
+            tokens.push(Token::Placeholder(name));
+            rest = &tail[name.len() + 2..];
+        } else {
+            tokens.push(Token::Text(&tail[..1]));
+            rest = &tail[1..];
+        }
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+    tokens
+}
+
+/// Whether `template` has a `{...}` placeholder, so input should be asked for
+pub fn needs_input(template: &str) -> bool {
+    tokenize(template).contains(&Token::Placeholder("..."))
+}
+
+/// Replace `{...}` in `template` with `input`. Braces in `input` are escaped, so the result can
+/// still be passed to [`interpolate`].
+pub fn fill_input(template: &str, input: &str) -> String {
+    let escape = |text: &str| text.replace('{', "{{").replace('}', "}}");
+    tokenize(template)
+        .into_iter()
+        .map(|token| match token {
+            Token::Text(text) => escape(text),
+            Token::Placeholder("...") => escape(input),
+            Token::Placeholder(name) => format!("{{{name}}}"),
+        })
+        .collect()
+}
+
+/// Fill in every placeholder of `template`, shell-quoting paths. `{{` and `}}` become a literal
+/// `{` and `}`, and braces that aren't part of a placeholder, like in `${HOME}`, are left alone.
+/// `{...}` is kept as is, use [`fill_input`] first.
+pub fn interpolate(template: &str, context: &Context) -> Result<OsString> {
+    let mut out = OsString::with_capacity(template.len());
+    for token in tokenize(template) {
+        let name = match token {
+            Token::Text(text) => {
+                out.push(text);
+                continue;
+            }
+            Token::Placeholder("...") => {
+                out.push("{...}");
+                continue;
+            }
+            Token::Placeholder("root") => {
+                out.push(quote(context.root.as_os_str()));
+                continue;
+            }
+            Token::Placeholder("marks") => {
+                for (i, mark) in context.marks.iter().enumerate() {
+                    if i > 0 {
+                        out.push(" ");
+                    }
+                    out.push(quote(mark.as_os_str()));
+                }
+                continue;
+            }
+            Token::Placeholder(name) => name,
+        };
+
+        let Some(selected) = context.selected else {
+            bail!("nothing is selected to fill in \"{{{name}}}\"");
+        };
+        let value = match name {
+            "" => Some(selected.as_os_str()),
+            "name" => selected.file_name(),
+            "stem" => selected.file_stem(),
+            "ext" => Some(selected.extension().unwrap_or_default()),
+            "dir" => selected.parent().map(Path::as_os_str),
+            "rel" => selected.strip_prefix(context.root).ok().map(|rel| {
+                if rel.as_os_str().is_empty() {
+                    OsStr::new(".")
+                } else {
+                    rel.as_os_str()
+                }
+            }),
+            _ => unreachable!("every placeholder should be handled"),
+        };
+        let Some(value) = value else {
+            bail!("\"{}\" has no value for \"{{{name}}}\"", selected.display());
+        };
+        out.push(quote(value));
+    }
+    Ok(out)
+}
+
+/// Quote `arg` so the shell sees it as a single word. Words made up of only safe characters
+/// are left as is, for readability.
+#[cfg(unix)]
+pub fn quote(arg: &OsStr) -> OsString {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    let bytes = arg.as_bytes();
+    let is_safe = |byte: &u8| byte.is_ascii_alphanumeric() || b"/._-+:@%,".contains(byte);
+    if !bytes.is_empty() && bytes.iter().all(is_safe) {
+        return arg.to_os_string();
+    }
+    let mut quoted = Vec::with_capacity(bytes.len() + 2);
+    quoted.push(b'\'');
+    for &byte in bytes {
+        if byte == b'\'' {
+            // Close the quote, add an escaped quote, and open it again
+            quoted.extend_from_slice(b"'\\''");
+        } else {
+            quoted.push(byte);
+        }
+    }
+    quoted.push(b'\'');
+    OsString::from_vec(quoted)
+}
+
+/// Quote `arg` so the shell sees it as a single word. Words made up of only safe characters
+/// are left as is, for readability.
+#[cfg(not(unix))]
+pub fn quote(arg: &OsStr) -> OsString {
+    // cmd.exe can't escape double quotes, but they can't be in Windows paths either
+    let arg = arg.to_string_lossy();
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "\\/._-+:@,".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        return OsString::from(arg.as_ref());
+    }
+    OsString::from(format!("\"{arg}\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    fn context<'a>(selected: &'a str, marks: &'a [PathBuf]) -> Context<'a> {
+        Context {
+            selected: Some(Path::new(selected)),
+            root: Path::new("/project"),
+            marks,
+        }
+    }
+
+    fn interpolated(template: &str, selected: &str) -> String {
+        interpolate(template, &context(selected, &[]))
+            .unwrap()
+            .into_string()
+            .unwrap()
+    }
+
+    #[test]
+    fn fills_in_path_placeholders() {
+        let selected = "/project/src/main.rs";
+        assert_eq!("cat /project/src/main.rs", interpolated("cat {}", selected));
+        assert_eq!("main.rs", interpolated("{name}", selected));
+        assert_eq!("main", interpolated("{stem}", selected));
+        assert_eq!("rs", interpolated("{ext}", selected));
+        assert_eq!("/project/src", interpolated("{dir}", selected));
+        assert_eq!("src/main.rs", interpolated("{rel}", selected));
+        assert_eq!("/project", interpolated("{root}", selected));
+        assert_eq!(".", interpolated("{rel}", "/project"));
+        assert_eq!("''", interpolated("{ext}", "/project/Makefile"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn quotes_paths_for_the_shell() {
+        assert_eq!(
+            "cat '/project/my file.txt'",
+            interpolated("cat {}", "/project/my file.txt")
+        );
+        assert_eq!(
+            r"cat '/project/it'\''s.txt'",
+            interpolated("cat {}", "/project/it's.txt")
+        );
+        assert_eq!(
+            "echo '$HOME'",
+            interpolated("echo {name}", "/project/$HOME")
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn keeps_non_utf8_paths_intact() {
+        use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+        let selected = Path::new(OsStr::from_bytes(b"/project/caf\xe9"));
+        let context = Context {
+            selected: Some(selected),
+            root: Path::new("/project"),
+            marks: &[],
+        };
+        assert_eq!(
+            b"cat '/project/caf\xe9'".to_vec(),
+            interpolate("cat {}", &context).unwrap().into_vec()
+        );
+    }
+
+    #[test]
+    fn fills_in_marks() {
+        let marks = ["/project/a".into(), "/project/b c".into()];
+        let out = interpolate("ls {marks}", &context("/project", &marks)).unwrap();
+        #[cfg(unix)]
+        assert_eq!("ls /project/a '/project/b c'", out);
+        #[cfg(not(unix))]
+        assert_eq!("ls /project/a \"/project/b c\"", out);
+    }
+
+    #[test]
+    fn braces_can_be_escaped() {
+        assert_eq!("echo {}", interpolated("echo {{}}", "/project/a"));
+        assert_eq!("echo {name}", interpolated("echo {{name}}", "/project/a"));
+    }
+
+    #[test]
+    fn unknown_placeholders_are_left_alone() {
+        assert_eq!("echo ${HOME}", interpolated("echo ${HOME}", "/project/a"));
+        assert_eq!(
+            "awk '{print $1}' a",
+            interpolated("awk '{print $1}' {name}", "/project/a")
+        );
+        assert_eq!("{", interpolated("{", "/project/a"));
+    }
+
+    #[test]
+    fn input_is_filled_in_literally() {
+        assert!(needs_input("cargo add {...}"));
+        assert!(!needs_input("echo {{...}}"));
+        let filled = fill_input("echo {...} {}", "{name} x");
+        assert_eq!(
+            "echo {name} x /project/a",
+            interpolated(&filled, "/project/a")
+        );
+    }
+
+    #[test]
+    fn errors_without_selection() {
+        let context = Context {
+            selected: None,
+            root: Path::new("/project"),
+            marks: &[],
+        };
+        assert!(interpolate("cat {}", &context).is_err());
+        assert_eq!("ls", interpolate("ls", &context).unwrap());
+    }
+}
@@ -88,10 +88,19 @@
 )]
 
 pub mod app;
+pub mod archive;
+pub mod command_env;
 pub mod config;
+pub mod config_check;
 pub mod external_event;
 pub mod filelisting;
+pub mod history;
+pub mod ignorer;
+pub mod interpolate;
 pub mod logger;
 pub mod marks;
+pub mod multiplexer;
 pub mod queue;
+pub mod tasks;
 pub mod ui;
+pub mod watch;
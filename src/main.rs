@@ -1,11 +1,12 @@
 use anyhow::{bail, Context, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use crossbeam_channel::unbounded;
-use log::{error, warn, LevelFilter};
+use log::{error, info, warn, LevelFilter};
 use projectable::{
     app::{component::Drawable, App, TerminalEvent},
-    config::{self, Config, GlobList, Merge},
-    external_event,
+    config::{self, Config, FiletreeConfig, GlobList, Merge},
+    config_check,
+    external_event::{self, ExternalEvent},
     logger::EVENT_LOGGER,
     marks::{self, Marks},
 };
@@ -13,7 +14,7 @@ use std::{
     cell::RefCell,
     env, fs,
     io::{self, Stdout},
-    panic,
+    iter, panic,
     path::PathBuf,
     process::Command,
     rc::Rc,
@@ -21,7 +22,7 @@ use std::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crossterm::{
@@ -36,6 +37,9 @@ use tui::{backend::CrosstermBackend, Terminal};
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     dir: Option<PathBuf>,
 
     #[arg(long, help = "Debug mode")]
@@ -50,8 +54,17 @@ struct Args {
     make_config: bool,
 }
 
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Check the global and local config files for problems, without starting the TUI
+    CheckConfig,
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
+    if let Some(Commands::CheckConfig) = args.command {
+        return check_config();
+    }
     if args.config {
         println!(
             "{}",
@@ -156,26 +169,104 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Read and merge the global and local config files. Errors on the first problem in either.
 fn get_config() -> Result<Config> {
-    let mut config = config::get_config_home()
-        .map(|path| -> Result<Option<Config>> {
-            if !path.join("config.toml").exists() {
-                return Ok(None);
-            }
-            let contents = fs::read_to_string(path.join("config.toml"))?;
-            Ok(Some(toml::from_str::<Config>(&contents)?))
-        })
-        .unwrap_or(Ok(Some(Config::default())))?
-        .unwrap_or(Config::default());
-    if let Some(local_config) = find_local_config()? {
-        let contents = fs::read_to_string(local_config)?;
-        let local_config = toml::from_str(&contents)?;
-        config.merge(local_config);
+    let mut config = Config::default();
+    for file in config_files()? {
+        let contents = fs::read_to_string(&file)
+            .with_context(|| format!("error reading \"{}\"", file.display()))?;
+        let (file_config, diagnostics) = config_check::check_file(&file, &contents);
+        if let Some(diagnostic) = diagnostics.into_iter().next() {
+            bail!("{diagnostic}");
+        }
+        config.merge(file_config.expect("config without problems should be parsed"));
     }
 
     Ok(config)
 }
 
+/// Load the config files again and swap them into `app`. Errors if they are invalid, in which
+/// case the app keeps its config.
+fn reload_config(app: &mut App) -> Result<Rc<Config>> {
+    let config = Rc::new(get_config()?);
+    app.set_config(Rc::clone(&config))?;
+    for conflict in config.check_conflicts() {
+        warn!("{conflict}");
+    }
+    info!("reloaded config");
+    Ok(config)
+}
+
+/// The global and local config files that exist, in the order they are merged
+fn config_files() -> Result<Vec<PathBuf>> {
+    Ok(config::get_config_home()
+        .map(|path| path.join("config.toml"))
+        .filter(|path| path.exists())
+        .into_iter()
+        .chain(find_local_config()?)
+        .collect())
+}
+
+/// The config files to reload on changes, including ones that aren't created yet
+fn watched_config_files() -> Result<Vec<PathBuf>> {
+    let local = match find_local_config()? {
+        Some(path) => path,
+        None => env::current_dir()?.join(".projectable.toml"),
+    };
+    Ok(config::get_config_home()
+        .map(|path| path.join("config.toml"))
+        .into_iter()
+        .chain(iter::once(local))
+        .collect())
+}
+
+/// Whether the filesystem watcher has to be started again for `new` to take effect
+fn fs_watch_changed(old: &FiletreeConfig, new: &FiletreeConfig) -> bool {
+    old.ignore != new.ignore
+        || old.watcher != new.watcher
+        || old.refresh_time != new.refresh_time
+        || old.poll_interval != new.poll_interval
+}
+
+/// Print every problem in the global and local config files. Errors if there were any.
+fn check_config() -> Result<()> {
+    let mut problems = 0;
+    let mut config = Config::default();
+    let mut checked = Vec::new();
+    for file in config_files()? {
+        let contents = fs::read_to_string(&file)
+            .with_context(|| format!("error reading \"{}\"", file.display()))?;
+        let (file_config, diagnostics) = config_check::check_file(&file, &contents);
+        problems += diagnostics.len();
+        for diagnostic in diagnostics {
+            println!("{diagnostic}");
+        }
+        if let Some(file_config) = file_config {
+            config.merge(file_config);
+        }
+        checked.push(file);
+    }
+
+    // Keys can conflict with ones from the other file, so check them merged
+    let conflicts = config.check_conflicts();
+    problems += conflicts.len();
+    for conflict in conflicts {
+        println!("{conflict}");
+    }
+
+    if problems > 0 {
+        bail!("found {problems} problem(s) in the config");
+    }
+    if checked.is_empty() {
+        println!("No config files found, using the default config");
+    } else {
+        for file in checked {
+            println!("{} is valid", file.display());
+        }
+    }
+    Ok(())
+}
+
 /// Get the project root. This function searches for a `.git` directory. Errors if the current
 /// directory is invalid, and returns `None` if there was no root found.
 fn find_project_root(globs: &GlobList) -> Result<Option<PathBuf>> {
@@ -209,7 +300,7 @@ fn find_local_config() -> Result<Option<PathBuf>> {
 fn run_app(
     terminal: &mut Terminal<CrosstermBackend<Stdout>>,
     app: &mut App,
-    config: Rc<Config>,
+    mut config: Rc<Config>,
     marks: Rc<RefCell<Marks>>,
 ) -> Result<()> {
     // Set up event channel
@@ -217,16 +308,28 @@ fn run_app(
 
     let stop = Arc::new(AtomicBool::new(false));
     let mut input_handle = external_event::crossterm_watch(event_send.clone(), Arc::clone(&stop));
-    let (_watcher, mut change_buffer) = external_event::fs_watch(
+    let (mut _watcher, mut change_buffer) = external_event::fs_watch(
         app.path(),
         event_send.clone(),
-        config.filetree.refresh_time,
+        &config.filetree,
         Arc::clone(&stop),
     )
     .context("error starting filesystem refresh watcher")?;
-
-    // When set to true, will stop any running child processes of projectable
-    let thread_stop = Arc::new(AtomicBool::new(false));
+    let _config_watcher = match watched_config_files().and_then(|files| {
+        external_event::config_watch(
+            &files,
+            Duration::from_millis(config.filetree.refresh_time.max(1)),
+            event_send.clone(),
+        )
+    }) {
+        Ok(watcher) => Some(watcher),
+        Err(err) => {
+            warn!("config changes won't be reloaded: {err:#}");
+            None
+        }
+    };
+    app.start_status_worker(event_send.clone());
+    app.connect_jobs(event_send.clone());
 
     let mut first_run = true;
     loop {
@@ -234,6 +337,21 @@ fn run_app(
             first_run = false;
         } else {
             match event_recv.recv().context("error receiving event") {
+                Ok(ExternalEvent::ConfigChanged) => match reload_config(app) {
+                    Ok(new_config) => {
+                        if fs_watch_changed(&config.filetree, &new_config.filetree) {
+                            (_watcher, change_buffer) = external_event::fs_watch(
+                                app.path(),
+                                event_send.clone(),
+                                &new_config.filetree,
+                                Arc::clone(&stop),
+                            )
+                            .context("error restarting filesystem refresh watcher")?;
+                        }
+                        config = new_config;
+                    }
+                    Err(err) => error!("{:#}", err.context("keeping the previous config")),
+                },
                 Ok(event) => {
                     if let Err(err) = app.handle_event(&event) {
                         error!("{err:#}");
@@ -268,16 +386,6 @@ fn run_app(
                     input_handle =
                         external_event::crossterm_watch(event_send.clone(), Arc::clone(&stop));
                 }
-                TerminalEvent::RunCommandThreaded(expr) => {
-                    thread_stop.store(false, Ordering::Release);
-                    external_event::run_cmd(
-                        expr,
-                        event_send.clone(),
-                        Duration::from_millis(300),
-                        thread_stop.clone(),
-                    )
-                    .context("error starting command")?
-                }
                 TerminalEvent::RunCommand(expr) => {
                     execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)
                         .context("error leaving current screen")?;
@@ -291,17 +399,24 @@ fn run_app(
                     // Join the input receiving thread by setting `stop_flag` to true
                     stop.store(true, Ordering::Release);
                     input_handle.join().expect("error joining thread");
-                    expr.start()
+                    let started = Instant::now();
+                    let status = expr
+                        .start()
                         .context("error starting command")?
                         .wait()
-                        .context("error waiting for command completion")?;
+                        .context("error waiting for command completion")?
+                        .status;
+                    // Reported once the screen is back, and lets waiting tasks continue
+                    drop(event_send.send(ExternalEvent::ForegroundFinished {
+                        status: status.into(),
+                        runtime: started.elapsed(),
+                    }));
                     // Resume input receiving thread again
                     stop.store(false, Ordering::Release);
                     change_buffer.flush(&event_send);
                     input_handle =
                         external_event::crossterm_watch(event_send.clone(), Arc::clone(&stop));
                 }
-                TerminalEvent::StopAllCommands => thread_stop.store(true, Ordering::Release),
             },
             Err(err) => {
                 error!("{err:#}");
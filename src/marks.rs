@@ -5,9 +5,10 @@ use std::{
     path::{Path, PathBuf},
 };
 
-pub fn get_marks_file() -> Option<PathBuf> {
+/// The directory where projectable keeps its data, like marks and command history
+pub fn get_data_home() -> Option<PathBuf> {
     if let Some(dir) = env::var_os("PROJECTABLE_DATA_DIR") {
-        return Some(PathBuf::from(dir).join("marks.json"));
+        return Some(PathBuf::from(dir));
     }
 
     #[cfg(target_os = "macos")]
@@ -18,7 +19,11 @@ pub fn get_marks_file() -> Option<PathBuf> {
     #[cfg(not(target_os = "macos"))]
     let dir = dirs_next::data_dir()?;
 
-    Some(dir.join("projectable/marks.json"))
+    Some(dir.join("projectable"))
+}
+
+pub fn get_marks_file() -> Option<PathBuf> {
+    get_data_home().map(|dir| dir.join("marks.json"))
 }
 
 #[derive(Debug)]
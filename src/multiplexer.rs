@@ -0,0 +1,201 @@
+use crate::{
+    config::{MultiplexerConfig, MultiplexerKind},
+    interpolate,
+};
+use anyhow::{bail, Result};
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    fmt::{self, Display},
+};
+
+/// Where a command run in the terminal multiplexer is opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MultiplexerOpts {
+    VerticalSplit,
+    HorizontalSplit,
+    NewWindow,
+    FloatingWindow,
+}
+
+impl Display for MultiplexerOpts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::VerticalSplit => "vertical split",
+            Self::HorizontalSplit => "horizontal split",
+            Self::NewWindow => "new window",
+            Self::FloatingWindow => "floating window",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// The multiplexer projectable is running in. The innermost one wins, so tmux running inside
+/// of kitty is detected as tmux.
+pub fn detect() -> Option<MultiplexerKind> {
+    detect_with(|var| env::var_os(var).is_some())
+}
+
+fn detect_with(is_set: impl Fn(&str) -> bool) -> Option<MultiplexerKind> {
+    [
+        ("TMUX", MultiplexerKind::Tmux),
+        ("ZELLIJ", MultiplexerKind::Zellij),
+        ("STY", MultiplexerKind::Screen),
+        ("KITTY_WINDOW_ID", MultiplexerKind::Kitty),
+        ("WEZTERM_PANE", MultiplexerKind::Wezterm),
+    ]
+    .into_iter()
+    .find(|(var, _)| is_set(var))
+    .map(|(_, kind)| kind)
+}
+
+/// Built-in template of `kind` for `opts`, if it can open one
+fn preset(kind: MultiplexerKind, opts: MultiplexerOpts) -> Option<&'static str> {
+    use MultiplexerKind::{Kitty, Screen, Tmux, Wezterm, Zellij};
+    use MultiplexerOpts::{FloatingWindow, HorizontalSplit, NewWindow, VerticalSplit};
+
+    let template = match (kind, opts) {
+        (Tmux, VerticalSplit) => "tmux split-window -h {cmd}",
+        (Tmux, HorizontalSplit) => "tmux split-window -v {cmd}",
+        (Tmux, NewWindow) => "tmux new-window {cmd}",
+        (Tmux, FloatingWindow) => "tmux display-popup -E {cmd}",
+        (Zellij, VerticalSplit) => "zellij run --direction right -- sh -c {cmd}",
+        (Zellij, HorizontalSplit) => "zellij run --direction down -- sh -c {cmd}",
+        (Zellij, NewWindow) => "zellij action new-tab && zellij run -- sh -c {cmd}",
+        (Zellij, FloatingWindow) => "zellij run --floating -- sh -c {cmd}",
+        (Screen, VerticalSplit) => {
+            "screen -X split -v && screen -X focus right && screen -X screen sh -c {cmd}"
+        }
+        (Screen, HorizontalSplit) => {
+            "screen -X split && screen -X focus down && screen -X screen sh -c {cmd}"
+        }
+        (Screen, NewWindow) => "screen -X screen sh -c {cmd}",
+        (Kitty, VerticalSplit) => "kitty @ launch --cwd=current --location=vsplit sh -c {cmd}",
+        (Kitty, HorizontalSplit) => "kitty @ launch --cwd=current --location=hsplit sh -c {cmd}",
+        (Kitty, NewWindow) => "kitty @ launch --cwd=current --type=tab sh -c {cmd}",
+        (Kitty, FloatingWindow) => "kitty @ launch --cwd=current --type=overlay sh -c {cmd}",
+        (Wezterm, VerticalSplit) => "wezterm cli split-pane --right -- sh -c {cmd}",
+        (Wezterm, HorizontalSplit) => "wezterm cli split-pane --bottom -- sh -c {cmd}",
+        (Wezterm, NewWindow) => "wezterm cli spawn -- sh -c {cmd}",
+        (Screen | Wezterm, FloatingWindow) => return None,
+    };
+    Some(template)
+}
+
+/// Template to open `opts` with. Templates in the config win over the ones of the multiplexer,
+/// which is detected if the config doesn't name one.
+pub fn template(config: &MultiplexerConfig, opts: MultiplexerOpts) -> Result<String> {
+    let custom = match opts {
+        MultiplexerOpts::VerticalSplit => &config.vertical_split,
+        MultiplexerOpts::HorizontalSplit => &config.horizontal_split,
+        MultiplexerOpts::NewWindow => &config.new_window,
+        MultiplexerOpts::FloatingWindow => &config.floating_window,
+    };
+    if let Some(custom) = custom {
+        return Ok(custom.clone());
+    }
+    let Some(kind) = config.kind.or_else(detect) else {
+        bail!("not in a terminal multiplexer, set `multiplexer.kind` if it wasn't detected");
+    };
+    let Some(template) = preset(kind, opts) else {
+        bail!("{kind} can't open a {opts}, set `multiplexer` templates to use one");
+    };
+    Ok(template.to_owned())
+}
+
+/// Replace `{cmd}` in `template` with `cmd`, quoted for the shell
+pub fn fill(template: &str, cmd: &OsStr) -> OsString {
+    let mut out = OsString::with_capacity(template.len() + cmd.len());
+    for (i, part) in template.split("{cmd}").enumerate() {
+        if i > 0 {
+            out.push(interpolate::quote(cmd));
+        }
+        out.push(part);
+    }
+    out
+}
+
+/// Export `vars` before running `cmd`. Multiplexers start commands from their server, so they
+/// wouldn't get the environment otherwise.
+#[cfg(unix)]
+pub fn with_env(cmd: &OsStr, vars: &[(OsString, OsString)]) -> OsString {
+    if vars.is_empty() {
+        return cmd.to_os_string();
+    }
+    let mut out = OsString::from("export");
+    for (key, value) in vars {
+        out.push(" ");
+        out.push(key);
+        out.push("=");
+        out.push(interpolate::quote(value));
+    }
+    out.push("; ");
+    out.push(cmd);
+    out
+}
+
+/// Export `vars` before running `cmd`. cmd.exe can't, so the multiplexer has to pass its own
+/// environment along.
+#[cfg(not(unix))]
+pub fn with_env(cmd: &OsStr, _vars: &[(OsString, OsString)]) -> OsString {
+    cmd.to_os_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn detects_innermost_multiplexer() {
+        let detect_in = |vars: &[&str]| detect_with(|var| vars.contains(&var));
+        assert_eq!(
+            Some(MultiplexerKind::Tmux),
+            detect_in(&["KITTY_WINDOW_ID", "TMUX"])
+        );
+        assert_eq!(Some(MultiplexerKind::Zellij), detect_in(&["ZELLIJ"]));
+        assert_eq!(Some(MultiplexerKind::Wezterm), detect_in(&["WEZTERM_PANE"]));
+        assert_eq!(None, detect_in(&["TERM"]));
+    }
+
+    #[test]
+    fn config_templates_win_over_presets() {
+        let config = MultiplexerConfig {
+            kind: Some(MultiplexerKind::Tmux),
+            floating_window: Some("my-popup {cmd}".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            "my-popup {cmd}",
+            template(&config, MultiplexerOpts::FloatingWindow).unwrap()
+        );
+        assert_eq!(
+            "tmux new-window {cmd}",
+            template(&config, MultiplexerOpts::NewWindow).unwrap()
+        );
+    }
+
+    #[test]
+    fn missing_presets_are_errors() {
+        let config = MultiplexerConfig {
+            kind: Some(MultiplexerKind::Screen),
+            ..Default::default()
+        };
+        assert!(template(&config, MultiplexerOpts::FloatingWindow).is_err());
+        assert!(template(&config, MultiplexerOpts::NewWindow).is_ok());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn fills_in_quoted_command() {
+        assert_eq!(
+            "zellij run -- sh -c 'echo hi'",
+            fill("zellij run -- sh -c {cmd}", OsStr::new("echo hi"))
+        );
+        let vars = [("PRJ_ROOT".into(), "/my project".into())];
+        assert_eq!(
+            "export PRJ_ROOT='/my project'; make",
+            with_env(OsStr::new("make"), &vars)
+        );
+    }
+}
@@ -1,6 +1,10 @@
 use std::{cell::RefCell, collections::VecDeque, path::PathBuf, rc::Rc};
 
-use crate::app::{FuzzyOperation, InputOperation, PendingOperation};
+use crate::{
+    app::{FuzzyOperation, InputOperation, PendingOperation},
+    external_event::JobId,
+    multiplexer::MultiplexerOpts,
+};
 
 /// Single-threaded queue for events within the app
 #[derive(Debug, Clone)]
@@ -40,8 +44,15 @@ pub enum AppEvent {
     NewDir(PathBuf),
     PreviewFile(PathBuf),
     RunCommand(String),
-    RunCommandWithTmux(String, TmuxOpts),
+    /// Run the last command from the command input on the selected path
+    RepeatCommand,
+    RunCommandInMultiplexer(String, MultiplexerOpts),
+    /// Run a task from the config, after the tasks it depends on
+    RunTask(String),
+    /// Start the next task of the running tasks
+    NextTask,
     SearchFiles(Vec<PathBuf>),
+    SearchPreview(String),
     TogglePreviewMode,
     SpecialCommand(PathBuf),
     SpecialCommandFuzzy(PathBuf),
@@ -52,12 +63,9 @@ pub enum AppEvent {
     RenameFile(PathBuf, PathBuf),
     MoveFile(PathBuf, PathBuf),
     StopAllCommands,
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum TmuxOpts {
-    VerticalSplit,
-    HorizontalSplit,
-    NewWindow,
-    FloatingWindow,
+    /// Write everything a job printed to a file
+    SaveOutput(JobId, PathBuf),
+    /// Add a watch, given as a glob followed by the command
+    AddWatch(String),
+    ExtractArchive(PathBuf),
 }
@@ -0,0 +1,386 @@
+use crate::{config::Task, external_event::RefreshData, interpolate};
+use anyhow::{Context, Result};
+use log::warn;
+use serde::Deserialize;
+use std::{collections::BTreeMap, ffi::OsStr, fs, io, mem, path::Path};
+
+/// Makefiles in the order make looks for them
+const MAKEFILES: [&str; 3] = ["GNUmakefile", "makefile", "Makefile"];
+const JUSTFILES: [&str; 3] = ["justfile", "Justfile", ".justfile"];
+const CARGO_CONFIGS: [&str; 2] = [".cargo/config.toml", ".cargo/config"];
+
+/// Whether `path` is one of the files tasks are discovered from, in the project at `root`
+pub fn is_task_file(root: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return false;
+    };
+    MAKEFILES
+        .iter()
+        .chain(&JUSTFILES)
+        .chain(&CARGO_CONFIGS)
+        .chain(&["package.json"])
+        .any(|file| relative == Path::new(file))
+}
+
+/// Whether any of the changed files is one tasks are discovered from
+pub fn task_files_changed<'a>(
+    root: &Path,
+    data: impl IntoIterator<Item = &'a RefreshData>,
+) -> bool {
+    data.into_iter().any(|data| match data {
+        RefreshData::Add(path) | RefreshData::Delete(path) | RefreshData::Modify(path) => {
+            is_task_file(root, path)
+        }
+        RefreshData::Rename { from, to } => is_task_file(root, from) || is_task_file(root, to),
+    })
+}
+
+/// Find the tasks declared by the build files in `root`, grouped by the tool they are for.
+/// Each is named `<tool>:<name>`, like `make:build`. Files that fail to parse are skipped.
+pub fn discover(root: &Path) -> Vec<(String, Task)> {
+    let sources = [
+        (
+            "make",
+            "make".to_owned(),
+            parse_first(root, &MAKEFILES, |contents| Ok(make_targets(contents))),
+        ),
+        (
+            "just",
+            "just".to_owned(),
+            parse_first(root, &JUSTFILES, |contents| Ok(just_recipes(contents))),
+        ),
+        (
+            "npm",
+            format!("{} run", package_manager(root)),
+            parse_first(root, &["package.json"], |contents| {
+                npm_scripts(contents).context("error parsing package.json scripts")
+            }),
+        ),
+        (
+            "cargo",
+            "cargo".to_owned(),
+            parse_first(root, &CARGO_CONFIGS, |contents| {
+                cargo_aliases(contents).context("error parsing cargo aliases")
+            }),
+        ),
+    ];
+
+    let mut tasks = Vec::new();
+    for (tool, program, found) in sources {
+        let entries = match found {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!("{err:#}");
+                continue;
+            }
+        };
+        tasks.extend(entries.into_iter().map(|(name, description)| {
+            let task = Task {
+                command: format!("{program} {}", quote(&name)),
+                description,
+                cwd: None,
+                foreground: false,
+                depends: Vec::new(),
+            };
+            (format!("{tool}:{name}"), task)
+        }));
+    }
+    tasks
+}
+
+/// Parse the first of `files` in `root` that exists. Finds nothing if none of them do.
+fn parse_first(
+    root: &Path,
+    files: &[&str],
+    parse: impl FnOnce(&str) -> Result<Vec<(String, String)>>,
+) -> Result<Vec<(String, String)>> {
+    read_first(root, files)?.map_or_else(|| Ok(Vec::new()), |contents| parse(&contents))
+}
+
+/// Contents of the first of `files` in `root` that exists
+fn read_first(root: &Path, files: &[&str]) -> Result<Option<String>> {
+    for file in files {
+        let path = root.join(file);
+        match fs::read_to_string(&path) {
+            Ok(contents) => return Ok(Some(contents)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(err).with_context(|| format!("error reading \"{}\"", path.display()))
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn quote(name: &str) -> String {
+    interpolate::quote(OsStr::new(name))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Explicit targets of a Makefile, described by a `##` comment after the target
+fn make_targets(contents: &str) -> Vec<(String, String)> {
+    let mut targets: Vec<(String, String)> = Vec::new();
+    for line in contents.lines() {
+        // Recipe lines start with a tab
+        if line.starts_with(|c: char| c.is_whitespace() || c == '#' || c == '.') {
+            continue;
+        }
+        let Some((names, rest)) = line.split_once(':') else {
+            continue;
+        };
+        // `:=` and `::=` assign variables
+        if rest.starts_with('=') || rest.starts_with(":=") || names.contains(['=', '$', '%']) {
+            continue;
+        }
+        let description = rest
+            .split_once("##")
+            .map(|(_, description)| description.trim().to_owned())
+            .unwrap_or_default();
+        for name in names.split_whitespace() {
+            if !targets.iter().any(|(target, _)| target == name) {
+                targets.push((name.to_owned(), description.clone()));
+            }
+        }
+    }
+    targets
+}
+
+/// Public recipes of a justfile, described by the comment line above them
+fn just_recipes(contents: &str) -> Vec<(String, String)> {
+    let mut recipes = Vec::new();
+    let mut comment = None;
+    let mut private = false;
+    for line in contents.lines() {
+        if let Some(text) = line.strip_prefix('#') {
+            comment = Some(text.trim().to_owned());
+            continue;
+        }
+        // Attributes like `[private]` sit between the comment and the recipe
+        if line.starts_with('[') {
+            private |= line.contains("private");
+            continue;
+        }
+        let description = comment.take().unwrap_or_default();
+        let private = mem::take(&mut private);
+        if line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let Some((header, rest)) = line.split_once(':') else {
+            continue;
+        };
+        if rest.starts_with('=') {
+            continue;
+        }
+        let mut words = header.split_whitespace();
+        let Some(name) = words.next().map(|name| name.trim_start_matches('@')) else {
+            continue;
+        };
+        let is_recipe = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        let is_keyword = matches!(name, "alias" | "set" | "export" | "import" | "mod");
+        if !is_recipe || is_keyword || private || name.is_empty() || name.starts_with('_') {
+            continue;
+        }
+        recipes.push((name.to_owned(), description));
+    }
+    recipes
+}
+
+/// The `scripts` of a package.json, described by what they run
+fn npm_scripts(contents: &str) -> Result<Vec<(String, String)>> {
+    #[derive(Deserialize)]
+    struct Package {
+        #[serde(default)]
+        scripts: BTreeMap<String, String>,
+    }
+
+    let package: Package = serde_json::from_str(contents)?;
+    Ok(package.scripts.into_iter().collect())
+}
+
+/// The package manager a JavaScript project uses, based on its lock file
+fn package_manager(root: &Path) -> &'static str {
+    [
+        ("pnpm-lock.yaml", "pnpm"),
+        ("yarn.lock", "yarn"),
+        ("bun.lockb", "bun"),
+    ]
+    .into_iter()
+    .find(|(lock_file, _)| root.join(lock_file).exists())
+    .map_or("npm", |(_, manager)| manager)
+}
+
+/// The `[alias]` table of a cargo config, described by what they expand to
+fn cargo_aliases(contents: &str) -> Result<Vec<(String, String)>> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Alias {
+        Line(String),
+        Args(Vec<String>),
+    }
+
+    #[derive(Deserialize)]
+    struct CargoConfig {
+        #[serde(default)]
+        alias: BTreeMap<String, Alias>,
+    }
+
+    let config: CargoConfig = toml::from_str(contents)?;
+    Ok(config
+        .alias
+        .into_iter()
+        .map(|(name, alias)| {
+            let expansion = match alias {
+                Alias::Line(line) => line,
+                Alias::Args(args) => args.join(" "),
+            };
+            (name, format!("cargo {expansion}"))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::{prelude::*, TempDir};
+    use test_log::test;
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, description)| ((*name).to_owned(), (*description).to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn finds_make_targets() {
+        let makefile = "\
+CC := gcc
+VERSION = 1
+.PHONY: build test
+build: main.o ## Build the binary
+\t$(CC) -o main main.o
+test clean:
+\techo done
+%.o: %.c
+\t$(CC) -c $<
+$(OUT): build
+";
+        assert_eq!(
+            pairs(&[("build", "Build the binary"), ("test", ""), ("clean", "")]),
+            make_targets(makefile)
+        );
+    }
+
+    #[test]
+    fn finds_public_just_recipes() {
+        let justfile = "\
+set shell := [\"bash\", \"-c\"]
+alias b := build
+version := \"1\"
+
+# Build the project
+build:
+    cargo build
+
+[private]
+helper:
+    echo hidden
+
+_hidden:
+    echo hidden
+
+@test filter='': build
+    cargo test {{filter}}
+";
+        assert_eq!(
+            pairs(&[("build", "Build the project"), ("test", "")]),
+            just_recipes(justfile)
+        );
+    }
+
+    #[test]
+    fn finds_npm_scripts() {
+        let package = r#"{"name": "app", "scripts": {"dev": "vite", "build": "vite build"}}"#;
+        assert_eq!(
+            pairs(&[("build", "vite build"), ("dev", "vite")]),
+            npm_scripts(package).unwrap()
+        );
+        assert!(npm_scripts(r#"{"name": "app"}"#).unwrap().is_empty());
+    }
+
+    #[test]
+    fn finds_cargo_aliases() {
+        let config = r#"
+            [alias]
+            b = "build --release"
+            lint = ["clippy", "--", "-D", "warnings"]
+
+            [build]
+            jobs = 4
+        "#;
+        assert_eq!(
+            pairs(&[
+                ("b", "cargo build --release"),
+                ("lint", "cargo clippy -- -D warnings")
+            ]),
+            cargo_aliases(config).unwrap()
+        );
+    }
+
+    #[test]
+    fn discovers_tasks_grouped_by_tool() {
+        let temp = TempDir::new().unwrap();
+        temp.child("Makefile").write_str("all:\n\techo\n").unwrap();
+        temp.child("package.json")
+            .write_str(r#"{"scripts": {"dev": "vite"}}"#)
+            .unwrap();
+        temp.child("yarn.lock").touch().unwrap();
+        temp.child(".cargo/config.toml")
+            .write_str("[alias]\nb = \"build\"\n")
+            .unwrap();
+
+        let tasks = discover(temp.path());
+        let commands = tasks
+            .iter()
+            .map(|(name, task)| (name.as_str(), task.command.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("make:all", "make all"),
+                ("npm:dev", "yarn run dev"),
+                ("cargo:b", "cargo b")
+            ],
+            commands
+        );
+        temp.close().unwrap();
+    }
+
+    #[test]
+    fn broken_files_are_skipped() {
+        let temp = TempDir::new().unwrap();
+        temp.child("package.json").write_str("{").unwrap();
+        temp.child("justfile").write_str("run:\n  echo\n").unwrap();
+        let tasks = discover(temp.path());
+        assert_eq!(1, tasks.len());
+        assert_eq!("just:run", tasks[0].0);
+        temp.close().unwrap();
+    }
+
+    #[test]
+    fn knows_task_files() {
+        let root = Path::new("/project");
+        assert!(is_task_file(root, Path::new("/project/Makefile")));
+        assert!(is_task_file(root, Path::new("/project/.cargo/config.toml")));
+        assert!(!is_task_file(root, Path::new("/project/src/Makefile")));
+        assert!(task_files_changed(
+            root,
+            &[RefreshData::Rename {
+                from: "/project/package.json.tmp".into(),
+                to: "/project/package.json".into(),
+            }]
+        ));
+    }
+}
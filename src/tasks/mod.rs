@@ -0,0 +1,159 @@
+mod discover;
+
+pub use self::discover::*;
+use crate::{config::Task, external_event::JobId};
+use anyhow::{bail, Result};
+use std::collections::{HashMap, VecDeque};
+
+/// Names of every task that has to run for `name`, dependencies first and `name` last. Tasks
+/// depended on more than once only run once.
+pub fn run_order(tasks: &HashMap<String, Task>, name: &str) -> Result<Vec<String>> {
+    fn visit(
+        tasks: &HashMap<String, Task>,
+        name: &str,
+        visiting: &mut Vec<String>,
+        order: &mut Vec<String>,
+    ) -> Result<()> {
+        if order.iter().any(|done| done == name) {
+            return Ok(());
+        }
+        if visiting.iter().any(|parent| parent == name) {
+            bail!(
+                "task \"{name}\" depends on itself: {} -> {name}",
+                visiting.join(" -> ")
+            );
+        }
+        let Some(task) = tasks.get(name) else {
+            match visiting.last() {
+                Some(parent) => bail!("task \"{parent}\" depends on unknown task \"{name}\""),
+                None => bail!("unknown task \"{name}\""),
+            }
+        };
+        visiting.push(name.to_owned());
+        for dependency in &task.depends {
+            visit(tasks, dependency, visiting, order)?;
+        }
+        visiting.pop();
+        order.push(name.to_owned());
+        Ok(())
+    }
+
+    let mut order = Vec::new();
+    visit(tasks, name, &mut Vec::new(), &mut order)?;
+    Ok(order)
+}
+
+/// What the running task of a [`TaskChain`] is waiting on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskRun {
+    Job(JobId),
+    /// Tasks in the foreground block projectable until they exit
+    Foreground,
+}
+
+/// Tasks run one after the other, stopping at the first failure
+#[derive(Debug, Default)]
+pub struct TaskChain {
+    pending: VecDeque<String>,
+    running: Option<(String, Option<TaskRun>)>,
+}
+
+impl TaskChain {
+    pub fn new(order: Vec<String>) -> Self {
+        Self {
+            pending: order.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Take the next task to run. `None` means every task finished.
+    pub fn next_task(&mut self) -> Option<String> {
+        let name = self.pending.pop_front()?;
+        self.running = Some((name.clone(), None));
+        Some(name)
+    }
+
+    /// Record what the task taken with [`TaskChain::next_task`] is running as
+    pub fn started(&mut self, run: TaskRun) {
+        if let Some((_, waiting_on)) = &mut self.running {
+            *waiting_on = Some(run);
+        }
+    }
+
+    /// Name of the running task, if it runs as `run`
+    pub fn running(&self, run: TaskRun) -> Option<&str> {
+        self.running
+            .as_ref()
+            .filter(|(_, waiting_on)| *waiting_on == Some(run))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    fn task(depends: &[&str]) -> Task {
+        Task {
+            command: "true".to_owned(),
+            description: String::new(),
+            cwd: None,
+            foreground: false,
+            depends: depends.iter().map(|name| (*name).to_owned()).collect(),
+        }
+    }
+
+    fn tasks(tasks: &[(&str, &[&str])]) -> HashMap<String, Task> {
+        tasks
+            .iter()
+            .map(|(name, depends)| ((*name).to_owned(), task(depends)))
+            .collect()
+    }
+
+    #[test]
+    fn dependencies_run_first_and_only_once() {
+        let tasks = tasks(&[
+            ("deploy", &["build", "test"]),
+            ("test", &["build"]),
+            ("build", &["fetch"]),
+            ("fetch", &[]),
+        ]);
+        assert_eq!(
+            vec!["fetch", "build", "test", "deploy"],
+            run_order(&tasks, "deploy").unwrap()
+        );
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let tasks = tasks(&[("a", &["b"]), ("b", &["c"]), ("c", &["a"])]);
+        let err = run_order(&tasks, "a").unwrap_err();
+        assert_eq!(
+            "task \"a\" depends on itself: a -> b -> c -> a",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn unknown_tasks_are_errors() {
+        let tasks = tasks(&[("a", &["missing"])]);
+        assert!(run_order(&tasks, "a").is_err());
+        assert!(run_order(&tasks, "b").is_err());
+    }
+
+    #[test]
+    fn chain_waits_on_started_task() {
+        let mut chain = TaskChain::new(vec!["build".to_owned(), "run".to_owned()]);
+        assert_eq!(Some("build".to_owned()), chain.next_task());
+        assert_eq!(None, chain.running(TaskRun::Job(0)));
+        chain.started(TaskRun::Job(0));
+        assert_eq!(Some("build"), chain.running(TaskRun::Job(0)));
+        assert_eq!(None, chain.running(TaskRun::Foreground));
+
+        assert_eq!(Some("run".to_owned()), chain.next_task());
+        chain.started(TaskRun::Foreground);
+        assert_eq!(Some("run"), chain.running(TaskRun::Foreground));
+        assert_eq!(None, chain.next_task());
+    }
+}
@@ -1,16 +1,24 @@
 use easy_cast::CastFloat;
+use std::mem;
 use tui::{
     buffer::Buffer,
     layout::{Alignment, Margin, Rect},
     style::Style,
     symbols::{block::FULL, line::DOUBLE_VERTICAL},
-    text::Text,
+    text::{Span, Spans, Text},
     widgets::{Block, Paragraph, StatefulWidget, Widget},
 };
+use unicode_width::UnicodeWidthChar;
 
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
 pub struct ParagraphState {
     pub offset_top: u16,
+    pub offset_left: u16,
+    /// Line to scroll to on the next render. Needed because a line can span multiple rows when
+    /// wrapping, which is only known at render time
+    target_line: Option<usize>,
+    /// The largest `offset_top` can be, as of the last render
+    max_offset_top: u16,
 }
 
 impl ParagraphState {
@@ -41,13 +49,42 @@ impl ParagraphState {
         self.offset_top -= 1;
     }
 
+    pub fn right(&mut self) {
+        self.offset_left = self.offset_left.saturating_add(1);
+    }
+
+    pub fn left(&mut self) {
+        self.offset_left = self.offset_left.saturating_sub(1);
+    }
+
+    pub fn right_by(&mut self, amount: u16) {
+        self.offset_left = self.offset_left.saturating_add(amount);
+    }
+
+    pub fn left_by(&mut self, amount: u16) {
+        self.offset_left = self.offset_left.saturating_sub(amount);
+    }
+
     pub fn reset(&mut self) {
         self.offset_top = 0;
+        self.offset_left = 0;
+        self.target_line = None;
     }
 
     pub fn scroll_bottom(&mut self) {
         self.offset_top = u16::MAX;
     }
+
+    /// Scroll so that `line` of the text is at the top
+    pub fn scroll_to_line(&mut self, line: usize) {
+        self.offset_top = u16::try_from(line).unwrap_or(u16::MAX);
+        self.target_line = Some(line);
+    }
+
+    /// Whether the paragraph was scrolled all the way down when it was last rendered
+    pub fn at_bottom(&self) -> bool {
+        self.offset_top >= self.max_offset_top
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -59,6 +96,9 @@ pub struct ScrollParagraph<'a> {
     bar_style: Style,
     unreached_bar_style: Style,
     bar_margins: u8,
+    line_numbers: bool,
+    line_number_style: Style,
+    wrap: bool,
 }
 
 impl<'a> ScrollParagraph<'a> {
@@ -71,6 +111,9 @@ impl<'a> ScrollParagraph<'a> {
             alignment: Alignment::Left,
             unreached_bar_style: Style::default(),
             bar_margins: 1,
+            line_numbers: false,
+            line_number_style: Style::default(),
+            wrap: false,
         }
     }
 
@@ -109,6 +152,56 @@ impl<'a> ScrollParagraph<'a> {
         self.bar_margins = bar_margins;
         self
     }
+
+    #[must_use]
+    pub fn line_numbers(mut self, line_numbers: bool) -> Self {
+        self.line_numbers = line_numbers;
+        self
+    }
+
+    #[must_use]
+    pub fn line_number_style(mut self, style: Style) -> Self {
+        self.line_number_style = style;
+        self
+    }
+
+    /// Wrap lines that are too long to fit. Disables horizontal scrolling.
+    #[must_use]
+    pub fn wrap(mut self, wrap: bool) -> Self {
+        self.wrap = wrap;
+        self
+    }
+}
+
+/// Split `spans` into rows that are at most `width` columns wide
+fn wrap_spans<'a>(spans: Spans<'a>, width: usize) -> Vec<Spans<'a>> {
+    if width == 0 || spans.width() <= width {
+        return vec![spans];
+    }
+
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut row_width = 0;
+    for span in spans.0 {
+        let mut chunk = String::new();
+        for c in span.content.chars() {
+            let char_width = c.width().unwrap_or(0);
+            if row_width + char_width > width && row_width != 0 {
+                if !chunk.is_empty() {
+                    row.push(Span::styled(mem::take(&mut chunk), span.style));
+                }
+                rows.push(Spans(mem::take(&mut row)));
+                row_width = 0;
+            }
+            chunk.push(c);
+            row_width += char_width;
+        }
+        if !chunk.is_empty() {
+            row.push(Span::styled(chunk, span.style));
+        }
+    }
+    rows.push(Spans(row));
+    rows
 }
 
 impl<'a> StatefulWidget for ScrollParagraph<'a> {
@@ -123,15 +216,80 @@ impl<'a> StatefulWidget for ScrollParagraph<'a> {
             b.render(area, buf);
             inner_area
         });
+        buf.set_style(area, self.style);
 
-        let len = (self.text.lines.len() as u16).saturating_sub(area.height - 1);
-        state.offset_top = state.offset_top.min(len.saturating_sub(1));
+        let gutter_width = if self.line_numbers {
+            // Digits of the largest line number, plus a space of padding
+            self.text.lines.len().to_string().len() as u16 + 1
+        } else {
+            0
+        };
+        let text_area = Rect {
+            x: area.x + gutter_width.min(area.width),
+            width: area.width.saturating_sub(gutter_width),
+            ..area
+        };
 
-        buf.set_style(area, self.style);
-        let paragraph = Paragraph::new(self.text)
-            .scroll((state.offset_top, 0))
-            .alignment(self.alignment);
-        paragraph.render(area, buf);
+        // Every row that will be displayed, along with the line it starts (if it starts one)
+        let mut rows: Vec<(Option<usize>, Spans)> = Vec::with_capacity(self.text.lines.len());
+        let mut max_width = 0;
+        for (i, line) in self.text.lines.into_iter().enumerate() {
+            if self.wrap {
+                let wrapped = wrap_spans(line, text_area.width as usize);
+                rows.extend(
+                    wrapped
+                        .into_iter()
+                        .enumerate()
+                        .map(|(row, spans)| ((row == 0).then_some(i), spans)),
+                );
+            } else {
+                max_width = max_width.max(line.width());
+                rows.push((Some(i), line));
+            }
+        }
+
+        let row_count = u16::try_from(rows.len()).unwrap_or(u16::MAX);
+        let len = row_count.saturating_sub(area.height.saturating_sub(1));
+        state.max_offset_top = len.saturating_sub(1);
+        if let Some(line) = state.target_line.take() {
+            if let Some(row) = rows.iter().position(|(start, _)| *start == Some(line)) {
+                state.offset_top = u16::try_from(row).unwrap_or(u16::MAX);
+            }
+        }
+        state.offset_top = state.offset_top.min(state.max_offset_top);
+        let max_offset_left = if self.wrap {
+            0
+        } else {
+            u16::try_from(max_width)
+                .unwrap_or(u16::MAX)
+                .saturating_sub(text_area.width)
+        };
+        state.offset_left = state.offset_left.min(max_offset_left);
+
+        if self.line_numbers {
+            let visible = rows
+                .iter()
+                .skip(state.offset_top as usize)
+                .take(area.height as usize);
+            for (y, (line, _)) in visible.enumerate() {
+                if let Some(line) = line {
+                    buf.set_stringn(
+                        area.x,
+                        area.y + y as u16,
+                        format!("{:>width$}", line + 1, width = gutter_width as usize - 1),
+                        gutter_width as usize,
+                        self.line_number_style,
+                    );
+                }
+            }
+        }
+
+        let paragraph = Paragraph::new(Text::from(
+            rows.into_iter().map(|(_, spans)| spans).collect::<Vec<_>>(),
+        ))
+        .scroll((state.offset_top, state.offset_left))
+        .alignment(self.alignment);
+        paragraph.render(text_area, buf);
 
         if len == 0 || area.width <= 2 {
             return;
@@ -151,6 +309,9 @@ impl<'a> StatefulWidget for ScrollParagraph<'a> {
 
             (scrollbar_area.top(), scrollbar_area.height)
         };
+        if bar_height == 0 {
+            return;
+        }
 
         for y in bar_top..(bar_top + bar_height) {
             buf.set_string(right, y, DOUBLE_VERTICAL, self.unreached_bar_style);
@@ -171,3 +332,97 @@ impl<'a> StatefulWidget for ScrollParagraph<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+    use tui::{style::Color, widgets::Borders};
+
+    /// Render `paragraph` into a bordered 5x3 inner area
+    fn render(paragraph: ScrollParagraph, state: &mut ParagraphState) -> Buffer {
+        let mut buf = Buffer::empty(Rect::new(0, 0, 7, 5));
+        paragraph
+            .block(Block::default().borders(Borders::ALL))
+            .render(buf.area, &mut buf, state);
+        buf
+    }
+
+    #[test]
+    fn wraps_spans_by_width() {
+        let style = Style::default().fg(Color::Red);
+        let spans = Spans(vec![Span::raw("hello "), Span::styled("world", style)]);
+        assert_eq!(
+            vec![
+                Spans(vec![Span::raw("hell")]),
+                Spans(vec![Span::raw("o "), Span::styled("wo", style)]),
+                Spans(vec![Span::styled("rld", style)]),
+            ],
+            wrap_spans(spans, 4)
+        );
+    }
+
+    #[test]
+    fn short_spans_are_not_wrapped() {
+        let spans = Spans::from("short");
+        assert_eq!(vec![spans.clone()], wrap_spans(spans, 10));
+    }
+
+    #[test]
+    fn clamps_scroll_to_text() {
+        let mut state = ParagraphState {
+            offset_top: 100,
+            offset_left: 100,
+            ..Default::default()
+        };
+        render(ScrollParagraph::new("1234567\nab\nc\nd"), &mut state);
+        assert_eq!(1, state.offset_top);
+        assert_eq!(2, state.offset_left);
+        assert!(state.at_bottom());
+    }
+
+    #[test]
+    fn wrapping_counts_rows_when_clamping() {
+        let mut state = ParagraphState {
+            offset_top: 100,
+            offset_left: 100,
+            ..Default::default()
+        };
+        render(
+            ScrollParagraph::new("1234567\nab\nc\nd").wrap(true),
+            &mut state,
+        );
+        assert_eq!(2, state.offset_top);
+        assert_eq!(0, state.offset_left);
+    }
+
+    #[test]
+    fn scrolls_to_wrapped_line() {
+        let mut state = ParagraphState::default();
+        state.scroll_to_line(1);
+        render(
+            ScrollParagraph::new("1234567\nab\nc\nd\ne").wrap(true),
+            &mut state,
+        );
+        assert_eq!(2, state.offset_top);
+    }
+
+    #[test]
+    fn draws_line_numbers_on_first_row_of_line() {
+        let mut state = ParagraphState::default();
+        let buf = render(
+            ScrollParagraph::new("123456\nab")
+                .wrap(true)
+                .line_numbers(true),
+            &mut state,
+        );
+        let row = |y| {
+            (1..6)
+                .map(|x| buf.get(x, y).symbol.as_str())
+                .collect::<String>()
+        };
+        assert_eq!("1 123", row(1));
+        assert_eq!("  456", row(2));
+        assert_eq!("2 ab ", row(3));
+    }
+}
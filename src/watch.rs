@@ -0,0 +1,187 @@
+use crate::external_event::{JobId, RefreshData};
+use anyhow::{bail, Context, Result};
+use globset::{Glob, GlobMatcher};
+use std::{collections::HashMap, path::Path};
+
+/// A command re-run whenever files matching a glob change
+#[derive(Debug, Clone)]
+pub struct Watch {
+    pub glob: String,
+    pub command: String,
+    pub enabled: bool,
+    matcher: GlobMatcher,
+    /// Job of the last run, cancelled when the watch runs again
+    pub job: Option<JobId>,
+    /// Whether it is from the config, instead of added while running
+    configured: bool,
+}
+
+impl Watch {
+    pub fn new(glob: String, command: String) -> Result<Self> {
+        if command.starts_with("!!") {
+            bail!("watch commands always run in the background");
+        }
+        let matcher = Glob::new(&glob)
+            .with_context(|| format!("invalid watch glob \"{glob}\""))?
+            .compile_matcher();
+        Ok(Self {
+            glob,
+            command,
+            enabled: true,
+            matcher,
+            job: None,
+            configured: false,
+        })
+    }
+
+    /// Whether `path`, relative to the project root, is watched
+    pub fn is_match(&self, path: &Path) -> bool {
+        self.matcher.is_match(path)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Watches {
+    watches: Vec<Watch>,
+}
+
+impl Watches {
+    /// Watches from the config, sorted by glob
+    pub fn from_config(commands: &HashMap<String, String>) -> Result<Self> {
+        let mut watches = commands
+            .iter()
+            .map(|(glob, command)| {
+                let mut watch = Watch::new(glob.clone(), command.clone())?;
+                watch.configured = true;
+                Ok(watch)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        watches.sort_by(|a, b| a.glob.cmp(&b.glob));
+        Ok(Self { watches })
+    }
+
+    /// Replace the watches from the config with `commands`. Watches added while running are
+    /// kept, after the new ones. Nothing changes if any of `commands` is invalid.
+    pub fn reload(&mut self, commands: &HashMap<String, String>) -> Result<()> {
+        let configured = Self::from_config(commands)?;
+        self.watches.retain(|watch| !watch.configured);
+        self.watches.splice(0..0, configured.watches);
+        Ok(())
+    }
+
+    pub fn add(&mut self, watch: Watch) {
+        self.watches.push(watch);
+    }
+
+    pub fn remove(&mut self, index: usize) -> Watch {
+        self.watches.remove(index)
+    }
+
+    pub fn toggle(&mut self, index: usize) {
+        let watch = &mut self.watches[index];
+        watch.enabled = !watch.enabled;
+    }
+
+    pub fn watches(&self) -> &[Watch] {
+        &self.watches
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> &mut Watch {
+        &mut self.watches[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.watches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+
+    /// Indices of the enabled watches that match any of the changes in the project at `root`.
+    /// Each watch is in there once, however many of its files changed.
+    pub fn triggered<'a>(
+        &self,
+        root: &Path,
+        data: impl IntoIterator<Item = &'a RefreshData>,
+    ) -> Vec<usize> {
+        let paths = data
+            .into_iter()
+            .flat_map(|data| match data {
+                RefreshData::Add(path) | RefreshData::Delete(path) | RefreshData::Modify(path) => {
+                    vec![path]
+                }
+                RefreshData::Rename { from, to } => vec![from, to],
+            })
+            .filter_map(|path| path.strip_prefix(root).ok())
+            .collect::<Vec<_>>();
+        self.watches
+            .iter()
+            .enumerate()
+            .filter(|(_, watch)| watch.enabled && paths.iter().any(|path| watch.is_match(path)))
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    fn test_watches() -> Watches {
+        Watches::from_config(&HashMap::from([
+            ("**/*.rs".to_owned(), "cargo check".to_owned()),
+            ("*.md".to_owned(), "mdbook build".to_owned()),
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn burst_of_changes_triggers_each_watch_once() {
+        let watches = test_watches();
+        let root = Path::new("/project");
+        let data = [
+            RefreshData::Modify("/project/src/main.rs".into()),
+            RefreshData::Add("/project/src/lib.rs".into()),
+            RefreshData::Delete("/project/Cargo.lock".into()),
+        ];
+        assert_eq!(vec![0], watches.triggered(root, &data));
+        let data = [RefreshData::Rename {
+            from: "/project/draft".into(),
+            to: "/project/README.md".into(),
+        }];
+        assert_eq!(vec![1], watches.triggered(root, &data));
+    }
+
+    #[test]
+    fn disabled_watches_are_not_triggered() {
+        let mut watches = test_watches();
+        watches.toggle(0);
+        let data = [RefreshData::Modify("/project/src/main.rs".into())];
+        assert!(watches.triggered(Path::new("/project"), &data).is_empty());
+        watches.toggle(0);
+        assert_eq!(vec![0], watches.triggered(Path::new("/project"), &data));
+    }
+
+    #[test]
+    fn reloading_keeps_added_watches() {
+        let mut watches = test_watches();
+        watches.add(Watch::new("*.toml".to_owned(), "taplo check".to_owned()).unwrap());
+        watches
+            .reload(&HashMap::from([("*.py".to_owned(), "pytest".to_owned())]))
+            .unwrap();
+        let globs: Vec<_> = watches.watches().iter().map(|w| w.glob.as_str()).collect();
+        assert_eq!(vec!["*.py", "*.toml"], globs);
+
+        let invalid = HashMap::from([("[".to_owned(), "ls".to_owned())]);
+        assert!(watches.reload(&invalid).is_err());
+        assert_eq!(2, watches.len());
+    }
+
+    #[test]
+    fn invalid_watches_are_errors() {
+        assert!(Watch::new("[".to_owned(), "ls".to_owned()).is_err());
+        assert!(Watch::new("*".to_owned(), "!!ls".to_owned()).is_err());
+    }
+}